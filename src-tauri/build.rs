@@ -1,15 +1,54 @@
 #[cfg(windows)]
 extern crate winres;
 
+use std::fs;
+use std::process::Command;
+
 #[cfg(windows)]
 fn main() {
     let res = winres::WindowsResource::new();
     res.compile().unwrap();
 
+    set_build_info();
     tauri_build::build()
 }
 
 #[cfg(unix)]
 fn main() {
+    set_build_info();
     tauri_build::build()
 }
+
+fn set_build_info() {
+    let git_commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let cargo_lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+
+    println!("cargo:rustc-env=GIT_COMMIT_HASH={}", git_commit);
+    println!("cargo:rustc-env=TAURI_VERSION={}", get_dependency_version(&cargo_lock, "tauri"));
+    println!("cargo:rustc-env=CPAL_VERSION={}", get_dependency_version(&cargo_lock, "cpal"));
+
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+}
+
+fn get_dependency_version(cargo_lock: &str, name: &str) -> String {
+    let package_name = format!("name = \"{}\"", name);
+    let mut lines = cargo_lock.lines();
+
+    while let Some(line) = lines.next() {
+        if line == package_name {
+            let version = lines.next().and_then(|line| line.strip_prefix("version = \""));
+            if let Some(version) = version {
+                return version.trim_end_matches('"').to_string();
+            }
+        }
+    }
+    "unknown".to_string()
+}
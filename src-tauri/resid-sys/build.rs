@@ -1,36 +1,67 @@
-const USE_NEW_FILTER: bool = true;
+const RESID_VERSION: &str = "1.0";
+const OLD_FILTER_NAMESPACE: &str = "reSID_old_filter";
+
+const RESID_SRC: [&str; 7] = [
+    "src/resid10/dac.cc",
+    "src/resid10/envelope.cc",
+    "src/resid10/extfilt.cc",
+    "src/resid10/pot.cc",
+    "src/resid10/sid.cc",
+    "src/resid10/voice.cc",
+    "src/resid10/wave.cc",
+];
 
 fn main() -> miette::Result<()> {
     println!(r"cargo:rustc-link-search=C:\Program Files (x86)\Microsoft Visual Studio\2022\BuildTools\VC\Tools\MSVC\14.31.31103\bin\Hostx86\x86");
 
-    let mut src = vec![
-        "src/resid10/dac.cc",
-        "src/resid10/envelope.cc",
-        "src/resid10/extfilt.cc",
-        "src/resid10/pot.cc",
-        "src/resid10/sid.cc",
-        "src/resid10/version.cc",
-        "src/resid10/voice.cc",
-        "src/resid10/wave.cc",
-        ];
-
-    if USE_NEW_FILTER {
-        src.push("src/resid10/filter8580new.cc");
-    } else {
-        src.push("src/resid10/filter.cc");
-    }
-
     let path = std::path::PathBuf::from("src");
-    autocxx_build::Builder::new("src/lib.rs", &[&path]).build()?
-        .define("VERSION", Some("\"1.0\""))
-        .define("NEW_8580_FILTER", Some(if USE_NEW_FILTER {"1"} else {"0"}))
-        .files(src)
-        .flag_if_supported("-std=c++14")
-        .flag_if_supported("-Wno-psabi")
-        .warnings(false)
-        .compile("resid");
+    let mut build = autocxx_build::Builder::new("src/lib.rs", &[&path]).build()?;
+    configure_build(&mut build)
+        .define("NEW_8580_FILTER", Some("1"))
+        .files(RESID_SRC)
+        .file("src/resid10/filter8580new.cc")
+        .file("src/resid10/version.cc");
+    build.compile("resid");
+
+    // reSID is built a second time with the old 8580 filter in its own namespace,
+    // so both filters can be linked and the filter can be chosen at runtime,
+    // the version string is not namespaced and is only defined by the first build
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("old_filter");
+    let mut old_filter_build = cc::Build::new();
+    configure_build(&mut old_filter_build)
+        .out_dir(out_dir)
+        .cpp(true)
+        .define("NEW_8580_FILTER", Some("0"))
+        .define("reSID", Some(OLD_FILTER_NAMESPACE))
+        .files(RESID_SRC)
+        .file("src/resid10/filter.cc");
+    old_filter_build.compile("resid_old_filter");
+
+    println!("cargo:rustc-env=RESID_VERSION={}", RESID_VERSION);
 
     println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=src/old_filter.h");
     println!("cargo:rerun-if-changed=src/resid10/");
     Ok(())
 }
+
+fn configure_build(build: &mut cc::Build) -> &mut cc::Build {
+    build
+        .define("VERSION", Some(format!("\"{}\"", RESID_VERSION).as_str()))
+        .flag_if_supported("-std=c++14")
+        .flag_if_supported("-Wno-psabi")
+        .warnings(false);
+
+    // the FIR convolution of the resample method only gets vectorized when NEON can be used,
+    // which is always the case on aarch64 but has to be enabled for 32-bit ARM targets that have it,
+    // enabling it for targets without NEON would crash with illegal instructions
+    let target_arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let target_features = std::env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    if target_arch == "arm" && target_features.split(',').any(|feature| feature == "neon") {
+        build.flag_if_supported("-mfpu=neon-vfpv4");
+    }
+    if std::env::var("PROFILE").unwrap_or_default() == "release" {
+        build.flag_if_supported("-ftree-vectorize");
+    }
+    build
+}
@@ -13,10 +13,36 @@ include_cpp! {
     generate!("reSID::SID")
 }
 
+include_cpp! {
+    #include "old_filter.h"
+    name!(ffi_old_filter)
+    safety!(unsafe)
+    generate!("reSID_old_filter::SID")
+}
+
+use ffi_old_filter::reSID_old_filter as old_filter;
+
 const FILTER_SCALE: f64 = 0.97;
 
+pub const RESID_VERSION: &str = env!("RESID_VERSION");
+
+// both builds of reSID have the same interface, they only differ in the 8580 filter
+enum SidEngine {
+    NewFilter(cxx::UniquePtr<SID>),
+    OldFilter(cxx::UniquePtr<old_filter::SID>)
+}
+
+macro_rules! with_sid {
+    ($engine:expr, $sid:ident => $call:expr) => {
+        match &mut $engine {
+            SidEngine::NewFilter($sid) => $call,
+            SidEngine::OldFilter($sid) => $call
+        }
+    };
+}
+
 pub struct Sid {
-    sid: cxx::UniquePtr<SID>
+    sid: SidEngine
 }
 
 impl Default for Sid {
@@ -27,8 +53,16 @@ impl Default for Sid {
 
 impl Sid {
     pub fn new() -> Self {
+        Self::create(SidEngine::NewFilter(SID::new().within_unique_ptr()))
+    }
+
+    pub fn with_old_8580_filter() -> Self {
+        Self::create(SidEngine::OldFilter(old_filter::SID::new().within_unique_ptr()))
+    }
+
+    fn create(engine: SidEngine) -> Self {
         let mut sid = Sid {
-            sid: SID::new().within_unique_ptr()
+            sid: engine
         };
         // always call adjust_filter_bias to ensure all buffers are initialized
         sid.adjust_filter_bias(0.0);
@@ -36,69 +70,85 @@ impl Sid {
     }
 
     pub fn adjust_filter_bias(&mut self, dac_bias: f64) {
-        SID::adjust_filter_bias(self.sid.pin_mut(), dac_bias);
+        with_sid!(self.sid, sid => sid.pin_mut().adjust_filter_bias(dac_bias));
     }
 
     pub fn set_chip_model(&mut self, model: chip_model) {
-        SID::set_chip_model(self.sid.pin_mut(), model);
+        match &mut self.sid {
+            SidEngine::NewFilter(sid) => sid.pin_mut().set_chip_model(model),
+            SidEngine::OldFilter(sid) => sid.pin_mut().set_chip_model(match model {
+                chip_model::MOS6581 => old_filter::chip_model::MOS6581,
+                chip_model::MOS8580 => old_filter::chip_model::MOS8580
+            })
+        }
     }
 
     pub fn set_sampling_parameters(&mut self, clock_freq: f64, method: sampling_method, sample_freq: f64) -> bool {
         let pass_freq = sample_freq * 0.9 / 2.0;
-        SID::set_sampling_parameters(self.sid.pin_mut(), clock_freq, method, sample_freq, pass_freq, FILTER_SCALE)
+        match &mut self.sid {
+            SidEngine::NewFilter(sid) => sid.pin_mut().set_sampling_parameters(clock_freq, method, sample_freq, pass_freq, FILTER_SCALE),
+            SidEngine::OldFilter(sid) => {
+                let method = match method {
+                    sampling_method::SAMPLE_FAST => old_filter::sampling_method::SAMPLE_FAST,
+                    sampling_method::SAMPLE_INTERPOLATE => old_filter::sampling_method::SAMPLE_INTERPOLATE,
+                    sampling_method::SAMPLE_RESAMPLE => old_filter::sampling_method::SAMPLE_RESAMPLE,
+                    sampling_method::SAMPLE_RESAMPLE_FASTMEM => old_filter::sampling_method::SAMPLE_RESAMPLE_FASTMEM
+                };
+                sid.pin_mut().set_sampling_parameters(clock_freq, method, sample_freq, pass_freq, FILTER_SCALE)
+            }
+        }
     }
 
     pub fn adjust_sampling_frequency(&mut self, sample_freq: f64) {
-        SID::adjust_sampling_frequency(self.sid.pin_mut(), sample_freq)
+        with_sid!(self.sid, sid => sid.pin_mut().adjust_sampling_frequency(sample_freq));
     }
 
     pub fn enable_filter(&mut self, enable: bool) {
-        SID::enable_filter(self.sid.pin_mut(), enable);
+        with_sid!(self.sid, sid => sid.pin_mut().enable_filter(enable));
     }
 
     pub fn enable_external_filter(&mut self, enable: bool) {
-        SID::enable_external_filter(self.sid.pin_mut(), enable);
+        with_sid!(self.sid, sid => sid.pin_mut().enable_external_filter(enable));
     }
 
     pub fn set_voice_mask(&mut self, mask: u32) {
-        SID::set_voice_mask(self.sid.pin_mut(), c_uint::from(mask));
+        with_sid!(self.sid, sid => sid.pin_mut().set_voice_mask(c_uint::from(mask)));
     }
 
     pub fn input(&mut self, sample: i16) {
-        SID::input(self.sid.pin_mut(), c_short::from(sample));
+        with_sid!(self.sid, sid => sid.pin_mut().input(c_short::from(sample)));
     }
 
     pub fn reset(&mut self) {
-        SID::reset(self.sid.pin_mut());
+        with_sid!(self.sid, sid => sid.pin_mut().reset());
     }
 
     pub fn read(&mut self, reg: u32) -> u32 {
-        u32::from(SID::read(self.sid.pin_mut(), c_uint::from(reg)))
+        u32::from(with_sid!(self.sid, sid => sid.pin_mut().read(c_uint::from(reg))))
     }
 
     pub fn write(&mut self, reg: u32, data: u32) {
-        SID::write(self.sid.pin_mut(), c_uint::from(reg), c_uint::from(data));
+        with_sid!(self.sid, sid => sid.pin_mut().write(c_uint::from(reg), c_uint::from(data)));
     }
 
     pub fn clock(&mut self) {
-        SID::clock(self.sid.pin_mut());
+        with_sid!(self.sid, sid => sid.pin_mut().clock());
     }
 
     pub fn clock_delta(&mut self, cycles: u32) {
-        SID::clock1(self.sid.pin_mut(), c_int::from(cycles as i32));
+        with_sid!(self.sid, sid => sid.pin_mut().clock1(c_int::from(cycles as i32)));
     }
 
     pub fn sample(&mut self, cycles: u32, buffer: &mut [i16], interleave: i32) -> (usize, u32) {
         let mut delta = c_int::from(cycles as i32);
-        let offset = unsafe {
-            SID::clock2(
-                self.sid.pin_mut(),
+        let offset = with_sid!(self.sid, sid => unsafe {
+            sid.pin_mut().clock2(
                 Pin::new(&mut delta),
                 buffer.as_mut_ptr() as *mut c_short,
                 c_int::from(buffer.len() as i32),
                 c_int::from(interleave)
             )
-        };
+        });
         (i32::from(offset) as usize, i32::from(delta) as u32)
     }
 }
@@ -0,0 +1,15 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+// reSID with the old 8580 filter, in the namespace it is built with by build.rs
+
+#ifndef RESID_OLD_FILTER_H
+#define RESID_OLD_FILTER_H
+
+#undef NEW_8580_FILTER
+#define NEW_8580_FILTER 0
+#define reSID reSID_old_filter
+
+#include "resid10/sid.h"
+
+#endif
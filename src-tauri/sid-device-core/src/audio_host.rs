@@ -0,0 +1,45 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+//! The audio host (WASAPI, ASIO, ALSA, JACK, ...) the audio devices are opened with.
+//!
+//! Which hosts are available depends on the platform and on the enabled features, ASIO is only
+//! available on Windows when built with the `asio` feature.
+
+use cpal::{Host, HostId};
+use parking_lot::{const_mutex, Mutex};
+
+pub static AUDIO_HOST: AudioHost = AudioHost::new();
+
+pub struct AudioHost {
+    host_id: Mutex<Option<HostId>>
+}
+
+impl AudioHost {
+    const fn new() -> AudioHost {
+        AudioHost {
+            host_id: const_mutex(None)
+        }
+    }
+
+    // a host that is not available selects the default host of the platform
+    pub fn set_host(&self, host_name: Option<&str>) {
+        *self.host_id.lock() = host_name.and_then(|host_name| {
+            cpal::available_hosts().into_iter().find(|host_id| host_id.name() == host_name)
+        });
+    }
+
+    pub fn get_host_id(&self) -> HostId {
+        self.host_id.lock().unwrap_or_else(|| cpal::default_host().id())
+    }
+
+    pub fn get_host(&self) -> Host {
+        self.host_id.lock()
+            .and_then(|host_id| cpal::host_from_id(host_id).ok())
+            .unwrap_or_else(cpal::default_host)
+    }
+}
+
+pub fn get_host_names() -> Vec<&'static str> {
+    cpal::available_hosts().into_iter().map(|host_id| host_id.name()).collect()
+}
@@ -0,0 +1,52 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use parking_lot::{const_mutex, Mutex};
+
+const AUDIO_TAP_QUEUE_SIZE: usize = 256;
+const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+pub const AUDIO_TAP_CHANNELS: u16 = 2;
+
+pub static AUDIO_TAP: AudioTap = AudioTap::new();
+
+pub struct AudioTap {
+    listeners: Mutex<Vec<Sender<Vec<i16>>>>,
+    sample_rate: AtomicU32
+}
+
+impl AudioTap {
+    const fn new() -> AudioTap {
+        AudioTap {
+            listeners: const_mutex(Vec::new()),
+            sample_rate: AtomicU32::new(DEFAULT_SAMPLE_RATE)
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<Vec<i16>> {
+        let (sender, receiver) = bounded(AUDIO_TAP_QUEUE_SIZE);
+        self.listeners.lock().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, samples: &[i16]) {
+        let mut listeners = self.listeners.lock();
+        if listeners.is_empty() || samples.is_empty() {
+            return;
+        }
+
+        // slow listeners miss samples instead of stalling the emulation
+        listeners.retain(|listener| !matches!(listener.try_send(samples.to_vec()), Err(TrySendError::Disconnected(_))));
+    }
+
+    pub fn set_sample_rate(&self, sample_rate: u32) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use atomicring::AtomicRingBuffer;
+
+use crate::clock::CLOCK;
+
+pub static BUFFER_HEALTH: BufferHealth = BufferHealth::new();
+
+// the values are only statistics and don't publish other data, so relaxed ordering is enough
+pub struct BufferHealth {
+    sid_write_queue_fill: AtomicU32,
+    sound_buffer_fill: AtomicU32,
+    cycles_in_buffer: AtomicU32,
+    underruns: AtomicU32,
+    busy_responses: AtomicU32,
+    emulation_time_in_micros: AtomicU32
+}
+
+#[derive(Clone, Copy, serde::Serialize)]
+pub struct BufferHealthSnapshot {
+    sid_write_queue_fill: u32,
+    sound_buffer_fill: u32,
+    cycles_in_buffer: u32,
+    underruns_per_sec: f32,
+    busy_responses_per_sec: f32,
+    emulation_cpu_usage: f32
+}
+
+impl BufferHealthSnapshot {
+    pub fn underruns_per_sec(&self) -> f32 {
+        self.underruns_per_sec
+    }
+
+    pub fn busy_responses_per_sec(&self) -> f32 {
+        self.busy_responses_per_sec
+    }
+}
+
+impl BufferHealth {
+    const fn new() -> BufferHealth {
+        BufferHealth {
+            sid_write_queue_fill: AtomicU32::new(0),
+            sound_buffer_fill: AtomicU32::new(0),
+            cycles_in_buffer: AtomicU32::new(0),
+            underruns: AtomicU32::new(0),
+            busy_responses: AtomicU32::new(0),
+            emulation_time_in_micros: AtomicU32::new(0)
+        }
+    }
+
+    pub fn update_buffers<W, S>(&self, sid_write_queue: &AtomicRingBuffer<W>, sound_buffer: &AtomicRingBuffer<S>, cycles_in_buffer: u32) {
+        self.sid_write_queue_fill.store(fill_percentage(sid_write_queue), Ordering::Relaxed);
+        self.sound_buffer_fill.store(fill_percentage(sound_buffer), Ordering::Relaxed);
+        self.cycles_in_buffer.store(cycles_in_buffer, Ordering::Relaxed);
+    }
+
+    pub fn add_underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_busy_response(&self) {
+        self.busy_responses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_underruns(&self) -> u32 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    pub fn get_busy_responses(&self) -> u32 {
+        self.busy_responses.load(Ordering::Relaxed)
+    }
+
+    // the time the emulation threads spend clocking SIDs, which is their CPU usage
+    pub fn add_emulation_time(&self, duration: Duration) {
+        self.emulation_time_in_micros.fetch_add(duration.as_micros() as u32, Ordering::Relaxed);
+    }
+}
+
+pub struct BufferHealthMonitor {
+    last_underruns: u32,
+    last_busy_responses: u32,
+    last_emulation_time_in_micros: u32,
+    last_snapshot: Instant
+}
+
+impl BufferHealthMonitor {
+    pub fn new() -> BufferHealthMonitor {
+        BufferHealthMonitor {
+            last_underruns: BUFFER_HEALTH.underruns.load(Ordering::Relaxed),
+            last_busy_responses: BUFFER_HEALTH.busy_responses.load(Ordering::Relaxed),
+            last_emulation_time_in_micros: BUFFER_HEALTH.emulation_time_in_micros.load(Ordering::Relaxed),
+            last_snapshot: CLOCK.now()
+        }
+    }
+
+    pub fn take_snapshot(&mut self) -> BufferHealthSnapshot {
+        let elapsed_in_sec = (CLOCK.now() - self.last_snapshot).as_secs_f32().max(f32::EPSILON);
+        let underruns = BUFFER_HEALTH.underruns.load(Ordering::Relaxed);
+        let busy_responses = BUFFER_HEALTH.busy_responses.load(Ordering::Relaxed);
+        let emulation_time_in_micros = BUFFER_HEALTH.emulation_time_in_micros.load(Ordering::Relaxed);
+
+        let snapshot = BufferHealthSnapshot {
+            sid_write_queue_fill: BUFFER_HEALTH.sid_write_queue_fill.load(Ordering::Relaxed),
+            sound_buffer_fill: BUFFER_HEALTH.sound_buffer_fill.load(Ordering::Relaxed),
+            cycles_in_buffer: BUFFER_HEALTH.cycles_in_buffer.load(Ordering::Relaxed),
+            underruns_per_sec: underruns.wrapping_sub(self.last_underruns) as f32 / elapsed_in_sec,
+            busy_responses_per_sec: busy_responses.wrapping_sub(self.last_busy_responses) as f32 / elapsed_in_sec,
+            emulation_cpu_usage: emulation_time_in_micros.wrapping_sub(self.last_emulation_time_in_micros) as f32 / 10_000.0 / elapsed_in_sec
+        };
+
+        self.last_underruns = underruns;
+        self.last_busy_responses = busy_responses;
+        self.last_emulation_time_in_micros = emulation_time_in_micros;
+        self.last_snapshot = CLOCK.now();
+
+        snapshot
+    }
+}
+
+fn fill_percentage<T>(buffer: &AtomicRingBuffer<T>) -> u32 {
+    (buffer.len() * 100 / buffer.capacity().max(1)) as u32
+}
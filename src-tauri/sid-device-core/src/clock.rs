@@ -0,0 +1,183 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+//! The time source used for pacing the player and the server.
+//!
+//! All pacing goes through [`CLOCK`], which uses the system clock unless another [`Clock`] is set.
+//! With a [`VirtualClock`] time only moves when it's advanced, so the whole pipeline can be driven
+//! step by step:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use sid_device_core::clock::{CLOCK, VirtualClock};
+//!
+//! let clock = Arc::new(VirtualClock::new());
+//! CLOCK.set_clock(clock.clone());
+//!
+//! // lets 20 ms of audio drain from the sound buffers
+//! clock.advance(Duration::from_millis(20));
+//! ```
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use parking_lot::{const_rwlock, Condvar, Mutex, RwLock};
+
+pub static CLOCK: ClockSource = ClockSource::new();
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+
+    // audio devices pace themselves in real time, so they are only used with a real time clock
+    fn is_real_time(&self) -> bool {
+        true
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+pub struct VirtualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+    advanced: Condvar
+}
+
+impl VirtualClock {
+    pub fn new() -> VirtualClock {
+        VirtualClock {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+            advanced: Condvar::new()
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock() += duration;
+        self.advanced.notify_all();
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        VirtualClock::new()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock()
+    }
+
+    // sleeping threads only wake up when the clock is advanced far enough
+    fn sleep(&self, duration: Duration) {
+        let mut elapsed = self.elapsed.lock();
+        let wake_time = *elapsed + duration;
+
+        while *elapsed < wake_time {
+            self.advanced.wait(&mut elapsed);
+        }
+    }
+
+    fn is_real_time(&self) -> bool {
+        false
+    }
+}
+
+pub struct ClockSource {
+    clock: RwLock<Option<Arc<dyn Clock>>>
+}
+
+impl ClockSource {
+    const fn new() -> ClockSource {
+        ClockSource {
+            clock: const_rwlock(None)
+        }
+    }
+
+    // only affects players that are created afterwards
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.clock.write() = Some(clock);
+    }
+
+    pub fn now(&self) -> Instant {
+        match self.clock.read().as_ref() {
+            Some(clock) => clock.now(),
+            None => Instant::now()
+        }
+    }
+
+    pub fn sleep(&self, duration: Duration) {
+        let clock = self.clock.read().clone();
+        match clock {
+            Some(clock) => clock.sleep(duration),
+            None => thread::sleep(duration)
+        }
+    }
+
+    pub fn is_real_time(&self) -> bool {
+        self.clock.read().as_ref().map_or(true, |clock| clock.is_real_time())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_tap::{AUDIO_TAP, AUDIO_TAP_CHANNELS};
+    use crate::player::Player;
+
+    const PAL_CLOCK: u32 = 985_248;
+    const NULL_SINK_INTERVAL_IN_MILLIS: u64 = 10;
+    const WRITE_CYCLES: u16 = 1000;
+
+    #[test]
+    fn player_on_null_sink_follows_virtual_clock() {
+        let clock = Arc::new(VirtualClock::new());
+        CLOCK.set_clock(clock.clone());
+
+        let samples = AUDIO_TAP.subscribe();
+        let mut player = Player::new(None).unwrap();
+
+        // three seconds of writes, so the queue doesn't run dry during the test
+        for _ in 0..3 * PAL_CLOCK / WRITE_CYCLES as u32 {
+            player.write_to_sid(0x18, 0x0f, WRITE_CYCLES);
+        }
+        let queued_cycles = player.get_cycles_in_buffer();
+        player.start_draining();
+
+        // one second in steps of the null sink, the emulation gets real time to fill up the sound buffer
+        let mut rendered_samples = 0;
+        for _ in 0..1000 / NULL_SINK_INTERVAL_IN_MILLIS {
+            clock.advance(Duration::from_millis(NULL_SINK_INTERVAL_IN_MILLIS));
+            thread::sleep(Duration::from_millis(20));
+            rendered_samples += samples.try_iter().map(|chunk| chunk.len()).sum::<usize>();
+        }
+
+        let elapsed_cycles = PAL_CLOCK;
+        let consumed_cycles = queued_cycles - player.get_cycles_in_buffer();
+
+        // the emulation runs ahead of the sink by no more than its buffers, a little over 0.1 second
+        assert!(consumed_cycles >= elapsed_cycles * 9 / 10, "consumed {} cycles in {} cycles", consumed_cycles, elapsed_cycles);
+        assert!(consumed_cycles <= elapsed_cycles * 5 / 4, "consumed {} cycles in {} cycles", consumed_cycles, elapsed_cycles);
+
+        // every consumed cycle is rendered once at the sample rate of the output, blocks that are still mixed are not counted
+        let rendered_frames = (rendered_samples / AUDIO_TAP_CHANNELS as usize) as u64;
+        let expected_frames = consumed_cycles as u64 * AUDIO_TAP.get_sample_rate() as u64 / PAL_CLOCK as u64;
+        assert!(rendered_frames.abs_diff(expected_frames) <= expected_frames / 20, "rendered {} frames, expected {}", rendered_frames, expected_frames);
+
+        CLOCK.set_clock(Arc::new(SystemClock));
+    }
+}
@@ -0,0 +1,41 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+//! The SID engine and server of SID Device, usable without the GUI.
+//!
+//! A [`player::Player`] renders SID register writes with reSID to an audio device:
+//!
+//! ```no_run
+//! use sid_device_core::audio_tap::AUDIO_TAP;
+//! use sid_device_core::player::Player;
+//!
+//! let mut player = Player::new(None).expect("no usable audio device");
+//! let samples = AUDIO_TAP.subscribe();
+//!
+//! // set the volume of SID 1 to max after 100 cycles and start playing
+//! player.write_to_sid(0x18, 0x0f, 100);
+//! player.start_draining();
+//!
+//! // interleaved stereo samples as they are sent to the audio device
+//! let _chunk = samples.recv();
+//! ```
+//!
+//! Writes for more than one SID are addressed with `reg + sid_number * 0x20`. A
+//! [`sid_backend::SidRouter`] can send individual SIDs to other [`sid_backend::SidBackend`]
+//! implementations while the rest stays emulated.
+//!
+//! The [`protocol`] module has the commands of the Network SID Device protocol, for writing a
+//! client. A [`server::SidDeviceServer`] embeds a complete Network SID Device.
+
+pub mod audio_host;
+pub mod audio_tap;
+pub mod buffer_health;
+pub mod clock;
+pub mod log;
+pub mod player;
+pub mod protocol;
+pub mod server;
+pub mod sid_backend;
+pub mod watchdog;
+
+pub const MAX_SID_COUNT: usize = 8;
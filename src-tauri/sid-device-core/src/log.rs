@@ -0,0 +1,35 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+//! The log lines of the engine and the server, which are passed on to a handler of the embedding
+//! application:
+//!
+//! ```
+//! sid_device_core::log::set_log_handler(|line| eprintln!("{}", line));
+//! ```
+
+use parking_lot::{const_rwlock, RwLock};
+
+type LogHandler = Box<dyn Fn(&str) + Send + Sync>;
+
+// without a handler the log lines are dropped
+static LOG_HANDLER: RwLock<Option<LogHandler>> = const_rwlock(None);
+
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::log::write_line(&format!($($arg)*))
+    };
+}
+
+pub(crate) use log;
+
+/// Sets the handler that receives every log line, it replaces the previous handler.
+pub fn set_log_handler(handler: impl Fn(&str) + Send + Sync + 'static) {
+    *LOG_HANDLER.write() = Some(Box::new(handler));
+}
+
+pub(crate) fn write_line(line: &str) {
+    if let Some(handler) = LOG_HANDLER.read().as_ref() {
+        handler(line);
+    }
+}
@@ -0,0 +1,398 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+mod audio_output;
+mod audio_renderer;
+mod external_input;
+mod loudness_normalizer;
+#[cfg(target_arch = "aarch64")]
+mod neon;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use atomicring::AtomicRingBuffer;
+use audio_renderer::AudioRenderer;
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::log::log;
+use crate::sid_backend::SidBackend;
+use crate::watchdog::{WATCHDOG, WatchdogEvent};
+use crate::player::audio_renderer::{AUDIO_ERROR, PlayerCommand, SidWrite};
+use crate::player::external_input::EXTERNAL_INPUT;
+
+pub use audio_renderer::AUDIO_MUTED;
+
+const SID_WRITES_BUFFER_SIZE: usize = 65_536;
+const MAX_CYCLES_IN_BUFFER: u32 = 63*312 * 50 * 3; // ~3 seconds
+pub const MIN_CYCLES_TO_DRAIN_QUEUE: u32 = 500_000;
+const MIN_WRITES_TO_DRAIN_QUEUE: usize = 300;
+pub const MAX_CYCLES_TO_DRAIN_QUEUE: u32 = MAX_CYCLES_IN_BUFFER / 2;
+const MAX_READ_TAG: u32 = 0x7f_ffff;
+const READ_TIMEOUT_IN_MILLIS: u64 = 5000; // a read first renders all queued writes, which can take up to the buffer length
+
+pub struct Player {
+    // an estimate of the queued cycles, the writes themselves are published through the queue, so relaxed ordering is enough
+    cycles_in_buffer: Arc<AtomicU32>,
+    queue: Arc<AtomicRingBuffer<SidWrite>>,
+    queue_started: Arc<AtomicBool>,
+    aborted: Arc<AtomicBool>,
+    player_cmd_sender: Sender<(PlayerCommand, Option<i32>)>,
+    sid_read_receiver: Receiver<(u32, u8)>,
+    read_tag: u32,
+    audio_device: AudioRenderer,
+    min_cycles_to_drain: u32,
+    startup_delay: u32,
+    startup_delay_pending: bool
+}
+
+impl Player {
+    pub fn new(audio_device_number: Option<i32>) -> Result<Player, String> {
+        let cycles_in_buffer = Arc::new(AtomicU32::new(0));
+        let buf = Arc::new(AtomicRingBuffer::<SidWrite>::with_capacity(SID_WRITES_BUFFER_SIZE));
+        let aborted = Arc::new(AtomicBool::new(false));
+        let queue_started = Arc::new(AtomicBool::new(false));
+
+        let mut audio_device = AudioRenderer::new(
+            buf.clone(),
+            queue_started.clone(),
+            aborted.clone(),
+            cycles_in_buffer.clone()
+        );
+
+        audio_device.start(audio_device_number)?;
+
+        let player_cmd_sender = audio_device.get_channel_sender();
+
+        let sid_read_receiver = audio_device.get_sid_read_receiver();
+
+        Ok(Player {
+            cycles_in_buffer,
+            queue: buf,
+            queue_started,
+            aborted,
+            player_cmd_sender,
+            sid_read_receiver,
+            read_tag: 0,
+            audio_device,
+            min_cycles_to_drain: MIN_CYCLES_TO_DRAIN_QUEUE,
+            startup_delay: 0,
+            startup_delay_pending: false
+        })
+    }
+
+    pub fn has_error(&mut self) -> bool {
+        AUDIO_ERROR.load(Ordering::Acquire)
+    }
+
+    pub fn check_renderer(&mut self) {
+        if let Some(reason) = self.audio_device.check_threads() {
+            WATCHDOG.report(WatchdogEvent::RendererRestarted(reason));
+        }
+    }
+
+    pub fn has_max_data_in_buffer(&mut self) -> bool {
+        let cycles = self.cycles_in_buffer.load(Ordering::Relaxed);
+        let enough_data = self.queue.len() > SID_WRITES_BUFFER_SIZE / 2 || cycles > MAX_CYCLES_IN_BUFFER;
+        if enough_data {
+            self.start_draining();
+        }
+        enough_data
+    }
+
+    pub fn has_min_data_in_buffer(&mut self) -> bool {
+        let min_writes_to_drain = MIN_WRITES_TO_DRAIN_QUEUE * (self.min_cycles_to_drain / MIN_CYCLES_TO_DRAIN_QUEUE) as usize;
+        self.cycles_in_buffer.load(Ordering::Relaxed) > self.min_cycles_to_drain || self.queue.len() > min_writes_to_drain
+    }
+
+    pub fn set_min_cycles_to_drain(&mut self, cycles: u32) {
+        self.min_cycles_to_drain = cycles.clamp(MIN_CYCLES_TO_DRAIN_QUEUE, MAX_CYCLES_TO_DRAIN_QUEUE);
+    }
+
+    pub fn get_min_cycles_to_drain(&self) -> u32 {
+        self.min_cycles_to_drain
+    }
+
+    pub fn get_cycles_in_buffer(&self) -> u32 {
+        self.cycles_in_buffer.load(Ordering::Relaxed)
+    }
+
+    pub fn start_draining(&mut self) {
+        self.queue_started.store(true, Ordering::Release);
+    }
+
+    // the silent cycles are inserted before the first write after a flush, so playback starts with the delay
+    pub fn set_startup_delay(&mut self, cycles: u32) {
+        self.startup_delay = cycles;
+        self.startup_delay_pending = cycles > 0 && self.queue.is_empty();
+    }
+
+    pub fn write_to_sid(&mut self, reg: u8, data: u8, cycles: u16) {
+        if self.startup_delay_pending {
+            self.startup_delay_pending = false;
+            self.insert_startup_delay(reg);
+        }
+        self.push_write(reg, data, cycles);
+    }
+
+    fn insert_startup_delay(&mut self, reg: u8) {
+        let mut cycles_left = self.startup_delay;
+        while cycles_left > 0 {
+            let cycles = cycles_left.min(u16::MAX as u32) as u16;
+            self.push_write((reg & 0xe0) + 0x1e, 0, cycles);
+            cycles_left -= cycles as u32;
+        }
+    }
+
+    fn push_write(&mut self, reg: u8, data: u8, cycles: u16) {
+        let sid_write = SidWrite {reg, data, cycles};
+        let _ = self.queue.try_push(sid_write);
+        self.cycles_in_buffer.fetch_add(cycles as u32, Ordering::Relaxed);
+    }
+
+    pub fn read_from_sid(&mut self, reg: u8, cycles: u16) -> u8 {
+        self.queue_started.store(true, Ordering::Release);
+        self.dummy_write(reg, cycles);
+
+        // every read is tagged, so a late response of a read that timed out is never taken for the current one
+        self.read_tag = (self.read_tag + 1) & MAX_READ_TAG;
+        let deadline = Instant::now() + Duration::from_millis(READ_TIMEOUT_IN_MILLIS);
+
+        let request = (PlayerCommand::Read, Some(((self.read_tag << 8) | reg as u32) as i32));
+        if self.player_cmd_sender.send_deadline(request, deadline).is_err() {
+            log!("ERROR: SID read timed out, emulation thread is not responding.\r");
+            return 0;
+        }
+
+        loop {
+            match self.sid_read_receiver.recv_deadline(deadline) {
+                Ok((tag, sid_env_out)) if tag == self.read_tag => return sid_env_out,
+                Ok(_) => continue,
+                Err(_) => {
+                    log!("ERROR: SID read timed out, emulation thread is not responding.\r");
+                    return 0;
+                }
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.clear_queue();
+        self.aborted.store(true, Ordering::Release);
+    }
+
+    pub fn reset(&mut self) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::Reset, None));
+    }
+
+    // the fade follows the clocked cycles, so it's in step with the writes that are rendered at that moment
+    pub fn fade_in(&mut self, millis: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::FadeIn, Some(millis)));
+    }
+
+    pub fn fade_out(&mut self, millis: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::FadeOut, Some(millis)));
+    }
+
+    // trades sound quality and latency for CPU time on slow machines
+    pub fn enable_low_power_mode(&mut self, enabled: bool) {
+        let command = if enabled {
+            PlayerCommand::EnableLowPowerMode
+        } else {
+            PlayerCommand::DisableLowPowerMode
+        };
+        let _ = self.player_cmd_sender.send((command, None));
+    }
+
+    // the sound buffer of the player is filled up to the latency, none keeps the default limits
+    pub fn set_latency(&mut self, latency_in_millis: Option<i32>) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetLatency, latency_in_millis));
+    }
+
+    // renders every SID with both chip models, the balance is the share of the other model in the mix
+    pub fn enable_model_comparison(&mut self, enabled: bool) {
+        let command = if enabled {
+            PlayerCommand::EnableModelComparison
+        } else {
+            PlayerCommand::DisableModelComparison
+        };
+        let _ = self.player_cmd_sender.send((command, None));
+    }
+
+    pub fn set_model_comparison_balance(&mut self, balance: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetModelComparisonBalance, Some(balance)));
+    }
+
+    pub fn enable_loudness_normalization(&mut self, enabled: bool) {
+        let command = if enabled {
+            PlayerCommand::EnableLoudnessNormalization
+        } else {
+            PlayerCommand::DisableLoudnessNormalization
+        };
+        let _ = self.player_cmd_sender.send((command, None));
+    }
+
+    // rounds off the peaks of loud mixes instead of clipping them hard
+    pub fn enable_soft_clipping(&mut self, enabled: bool) {
+        let command = if enabled {
+            PlayerCommand::EnableSoftClipping
+        } else {
+            PlayerCommand::DisableSoftClipping
+        };
+        let _ = self.player_cmd_sender.send((command, None));
+    }
+
+    pub fn enable_old_8580_filter(&mut self, enabled: bool) {
+        let command = if enabled {
+            PlayerCommand::EnableOld8580Filter
+        } else {
+            PlayerCommand::DisableOld8580Filter
+        };
+        let _ = self.player_cmd_sender.send((command, None));
+    }
+
+    pub fn enable_digiboost(&mut self, enabled: bool) {
+        let command = if enabled {
+            PlayerCommand::EnableDigiboost
+        } else {
+            PlayerCommand::DisableDigiboost
+        };
+        let _ = self.player_cmd_sender.send((command, None));
+    }
+
+    pub fn set_digiboost_level(&mut self, level: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetDigiboostLevel, Some(level)));
+    }
+
+    // an input device that can't be opened leaves the external input off
+    pub fn set_external_input(&mut self, enabled: bool, input_device_number: Option<i32>) {
+        let enabled = enabled && match EXTERNAL_INPUT.start(input_device_number) {
+            Ok(()) => true,
+            Err(error) => {
+                log!("ERROR: Input device for EXT IN cannot be used: {}\r", error);
+                false
+            }
+        };
+
+        let command = if enabled {
+            PlayerCommand::EnableExternalInput
+        } else {
+            EXTERNAL_INPUT.stop();
+            PlayerCommand::DisableExternalInput
+        };
+        let _ = self.player_cmd_sender.send((command, None));
+    }
+
+    pub fn set_filter_bias_6581(&mut self, filter_bias: Option<i32>) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetFilterBias6581, filter_bias));
+    }
+
+    pub fn set_master_volume(&mut self, volume: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetMasterVolume, Some(volume)));
+    }
+
+    pub fn set_mixer_channel(&mut self, sid_number: i32, gain: i32, pan: i32, voice_mask: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetGain, Some((sid_number << 16) + gain)));
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetPan, Some((sid_number << 8) + (pan as i8 as u8) as i32)));
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetVoiceMask, Some((sid_number << 8) + voice_mask)));
+    }
+
+    // a persistent trim of the relative volume of the SID, independent of the mixer
+    pub fn set_gain_trim(&mut self, sid_number: i32, gain_trim: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetGainTrim, Some((sid_number << 16) + gain_trim)));
+    }
+
+    // the left and right output of the SID can go to any channel of a multichannel audio device
+    pub fn set_output_channels(&mut self, sid_number: i32, channel_left: i32, channel_right: i32) {
+        let param1 = (sid_number << 16) + ((channel_left & 0xff) << 8) + (channel_right & 0xff);
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetOutputChannels, Some(param1)));
+    }
+
+    pub fn mute_voice(&mut self, sid_number: u8, voice: u8, muted: bool) {
+        let param1 = ((sid_number as i32) << 8) + ((voice as i32 & 0x7f) << 1) + muted as i32;
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetVoiceMute, Some(param1)));
+    }
+
+    pub fn set_model(&mut self, model: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetModel, Some(model)));
+    }
+
+    pub fn set_clock(&mut self, clock: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetClock, Some(clock)));
+    }
+
+    pub fn set_sid_count(&mut self, count: i32) {
+        self.clear_queue();  // clear queue so there are no writes for multiple SIDs anymore
+        // a failing audio device is reported through the audio error
+        let _ = self.audio_device.restart(None);
+
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetSidCount, Some(count)));
+    }
+
+    pub fn set_position(&mut self, position: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetPosition, Some(position)));
+    }
+
+    pub fn set_sampling_method(&mut self, sampling_method: i32) {
+        let _ = self.player_cmd_sender.send((PlayerCommand::SetSamplingMethod, Some(sampling_method)));
+    }
+
+    pub fn set_audio_device(&mut self, audio_device_number: Option<i32>) {
+        self.clear_queue();
+        self.audio_device.set_audio_device(audio_device_number);
+    }
+
+    fn clear_queue(&mut self) {
+        self.cycles_in_buffer.store(0, Ordering::Relaxed);
+        self.queue.clear();
+        self.queue_started.store(false, Ordering::Release);
+        self.startup_delay_pending = self.startup_delay > 0;
+    }
+
+    fn dummy_write(&mut self, reg: u8, cycles: u16) {
+        self.write_to_sid((reg & 0xe0) + 0x1e, 0, cycles);
+    }
+}
+
+impl SidBackend for Player {
+    fn write(&mut self, reg: u8, data: u8, cycles: u16) {
+        self.write_to_sid(reg, data, cycles);
+    }
+
+    fn delay(&mut self, sid_number: u8, cycles: u16) {
+        self.write_to_sid(0x1e + sid_number * 0x20, 0, cycles);
+    }
+
+    fn read(&mut self, reg: u8, cycles: u16) -> u8 {
+        self.read_from_sid(reg, cycles)
+    }
+
+    fn flush(&mut self) {
+        Player::flush(self);
+    }
+
+    fn reset(&mut self) {
+        Player::reset(self);
+    }
+}
+
+// the output stream is shared by all players, so this isn't a setting of a single player
+pub fn enable_low_latency(enabled: bool) {
+    audio_output::AUDIO_OUTPUT.enable_low_latency(enabled);
+}
+
+// a part of the latency is spent in the buffer of the shared output stream
+pub fn set_output_latency(latency_in_millis: Option<i32>) {
+    audio_output::AUDIO_OUTPUT.set_latency(latency_in_millis.map(|latency_in_millis| latency_in_millis.max(0) as u32));
+}
+
+// the sample rate of the shared output stream, none uses the default sample rate of the audio device
+pub fn set_output_sample_rate(sample_rate: Option<i32>) {
+    audio_output::AUDIO_OUTPUT.set_sample_rate(sample_rate.map(|sample_rate| sample_rate.max(0) as u32));
+}
+
+// only the device of an open stream is returned, so the audio host isn't woken up to list the devices
+pub fn get_active_audio_device_name() -> Option<String> {
+    audio_output::AUDIO_OUTPUT.get_device_name()
+}
@@ -0,0 +1,508 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use atomicring::AtomicRingBuffer;
+use cpal::{BufferSize, DefaultStreamConfigError, Device, HostId, OutputCallbackInfo, Sample, SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize, SupportedStreamConfig};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use parking_lot::{const_mutex, Mutex};
+
+use crate::log::log;
+use crate::audio_host::AUDIO_HOST;
+use crate::audio_tap::AUDIO_TAP;
+use crate::buffer_health::BUFFER_HEALTH;
+use crate::clock::CLOCK;
+use crate::player::audio_renderer::{AUDIO_ERROR, AUDIO_MUTED};
+use crate::watchdog::{self, MAX_WATCHDOG_RESTARTS, WATCHDOG, WatchdogEvent};
+
+const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+const DEFAULT_CHANNEL_COUNT: u16 = 2;
+const STOP_PAUSE_LATENCY_IN_MILLIS: u64 = 10;
+const MAX_STREAM_RECOVERIES: u32 = 3;
+const LOW_LATENCY_BUFFER_IN_MILLIS: u32 = 10;
+
+// the part of the latency that is spent in the buffer of the audio device, the rest is spent in the sound buffers
+pub const DEVICE_LATENCY_DIVIDER: u32 = 4;
+
+// one output stream is shared by all players, so connections do not open and close the device
+pub static AUDIO_OUTPUT: AudioOutput = AudioOutput::new();
+
+pub struct OutputSource {
+    pub sound_buffer: Arc<AtomicRingBuffer<i16>>,
+    pub should_pause: Arc<AtomicBool>
+}
+
+struct OutputStream {
+    host_id: HostId,
+    audio_device_number: Option<i32>,
+    device_name: String,
+    sample_rate: u32,
+    channel_count: u16,
+    requested_sample_rate: Option<u32>,
+    buffer_time_in_millis: Option<u32>,
+    should_stop: Arc<AtomicBool>,
+    failed: Arc<AtomicBool>,
+    device_removed: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl OutputStream {
+    // the device numbers belong to the host, so another host always means another device
+    fn is_device(&self, audio_device_number: Option<i32>) -> bool {
+        self.host_id == AUDIO_HOST.get_host_id() && self.audio_device_number == audio_device_number
+    }
+
+    fn stop(&mut self) {
+        self.should_stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+pub struct AudioOutput {
+    sources: Mutex<Vec<Arc<OutputSource>>>,
+    stream: Mutex<Option<OutputStream>>,
+    watchdog_restarts: AtomicU32,
+    stream_recoveries: AtomicU32,
+    low_latency: AtomicBool,
+    latency_in_millis: AtomicU32,
+    requested_sample_rate: AtomicU32
+}
+
+impl AudioOutput {
+    const fn new() -> AudioOutput {
+        AudioOutput {
+            sources: const_mutex(Vec::new()),
+            stream: const_mutex(None),
+            watchdog_restarts: AtomicU32::new(0),
+            stream_recoveries: AtomicU32::new(0),
+            low_latency: AtomicBool::new(false),
+            latency_in_millis: AtomicU32::new(0),
+            requested_sample_rate: AtomicU32::new(0)
+        }
+    }
+
+    pub fn add_source(&self, source: Arc<OutputSource>, audio_device_number: Option<i32>) -> Result<u32, String> {
+        let mut stream = self.stream.lock();
+
+        if !stream.as_ref().map_or(false, |stream| stream.is_device(audio_device_number)) {
+            Self::start_stream(&mut stream, audio_device_number)?;
+        }
+
+        let mut sources = self.sources.lock();
+        if !sources.iter().any(|other| Arc::ptr_eq(other, &source)) {
+            sources.push(source);
+        }
+        Ok(stream.as_ref().unwrap().sample_rate)
+    }
+
+    pub fn remove_source(&self, source: &Arc<OutputSource>) {
+        let mut stream = self.stream.lock();
+
+        let no_sources_left = {
+            let mut sources = self.sources.lock();
+            sources.retain(|other| !Arc::ptr_eq(other, source));
+            sources.is_empty()
+        };
+
+        // the device is released when the last player is gone
+        if no_sources_left {
+            if let Some(mut stream) = stream.take() {
+                stream.stop();
+            }
+            self.watchdog_restarts.store(0, Ordering::Relaxed);
+            self.stream_recoveries.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_audio_device(&self, audio_device_number: Option<i32>) -> Result<u32, String> {
+        let mut stream = self.stream.lock();
+
+        // every player requests the change, only the first one reopens the device
+        match stream.as_ref() {
+            Some(running_stream) if running_stream.is_device(audio_device_number) => Ok(running_stream.sample_rate),
+            _ => Self::start_stream(&mut stream, audio_device_number)
+        }
+    }
+
+    pub fn enable_low_latency(&self, enabled: bool) {
+        self.low_latency.store(enabled, Ordering::Relaxed);
+        self.reopen_changed_stream();
+    }
+
+    // without a latency the audio device uses its default buffer size
+    pub fn set_latency(&self, latency_in_millis: Option<u32>) {
+        self.latency_in_millis.store(latency_in_millis.unwrap_or(0), Ordering::Relaxed);
+        self.reopen_changed_stream();
+    }
+
+    // without a sample rate the default sample rate of the audio device is used,
+    // the players pick up the new sample rate when they check the stream
+    pub fn set_sample_rate(&self, sample_rate: Option<u32>) {
+        self.requested_sample_rate.store(sample_rate.unwrap_or(0), Ordering::Relaxed);
+        self.reopen_changed_stream();
+    }
+
+    // the running stream is reopened on the same device
+    fn reopen_changed_stream(&self) {
+        let buffer_time_in_millis = self.get_buffer_time();
+        let requested_sample_rate = self.get_requested_sample_rate();
+
+        let mut stream = self.stream.lock();
+        if let Some(audio_device_number) = stream.as_ref()
+            .filter(|running_stream| running_stream.buffer_time_in_millis != buffer_time_in_millis || running_stream.requested_sample_rate != requested_sample_rate)
+            .map(|running_stream| running_stream.audio_device_number) {
+            let _ = Self::start_stream(&mut stream, audio_device_number);
+        }
+    }
+
+    fn get_requested_sample_rate(&self) -> Option<u32> {
+        let sample_rate = self.requested_sample_rate.load(Ordering::Relaxed);
+        (sample_rate > 0).then(|| sample_rate)
+    }
+
+    // the low latency mode limits the buffer of the audio device further
+    fn get_buffer_time(&self) -> Option<u32> {
+        let latency_in_millis = self.latency_in_millis.load(Ordering::Relaxed);
+        let buffer_time_in_millis = (latency_in_millis > 0).then(|| latency_in_millis / DEVICE_LATENCY_DIVIDER);
+
+        if self.low_latency.load(Ordering::Relaxed) {
+            Some(buffer_time_in_millis.map_or(LOW_LATENCY_BUFFER_IN_MILLIS, |buffer_time| buffer_time.min(LOW_LATENCY_BUFFER_IN_MILLIS)))
+        } else {
+            buffer_time_in_millis
+        }
+    }
+
+    // changes when the stream had to move to another device
+    pub fn get_sample_rate(&self) -> Option<u32> {
+        self.stream.lock().as_ref().map(|stream| stream.sample_rate)
+    }
+
+    pub fn get_channel_count(&self) -> Option<u16> {
+        self.stream.lock().as_ref().map(|stream| stream.channel_count)
+    }
+
+    pub fn get_device_name(&self) -> Option<String> {
+        self.stream.lock().as_ref().map(|stream| stream.device_name.clone())
+    }
+
+    // the stream thread only ends by itself after a panic or an audio device error
+    pub fn check_stream(&self) -> Option<String> {
+        let mut stream = self.stream.lock();
+
+        let running_stream = stream.as_mut()?;
+        let audio_device_number = running_stream.audio_device_number;
+
+        // the sound buffers keep their samples while switching, so playback continues where it stopped
+        if running_stream.device_removed.load(Ordering::Acquire) {
+            running_stream.stop();
+            let device_name = running_stream.device_name.clone();
+
+            log!("Audio device \"{}\" was removed, switching to the default audio device\r", device_name);
+            Self::start_stream(&mut stream, None).ok()?;
+            WATCHDOG.report(WatchdogEvent::AudioDeviceRemoved(device_name));
+            return None;
+        }
+
+        // a stream that is stopped by the driver is rebuilt without bothering the user, unless it keeps failing
+        if running_stream.failed.load(Ordering::Acquire) {
+            running_stream.stop();
+
+            let recoveries = self.stream_recoveries.fetch_add(1, Ordering::Relaxed);
+            if recoveries >= MAX_STREAM_RECOVERIES {
+                let error = "Audio stream keeps failing".to_string();
+                log!("ERROR: {}\r", error);
+                *stream = None;
+                AUDIO_ERROR.store(true, Ordering::Release);
+                WATCHDOG.report(WatchdogEvent::AudioDeviceFailed(error));
+                return None;
+            }
+
+            // the same device is tried once, after that the default device
+            let audio_device_number = if recoveries == 0 { audio_device_number } else { None };
+
+            log!("Audio stream failed, rebuilding audio stream\r");
+            let _ = Self::start_stream(&mut stream, audio_device_number);
+            return None;
+        }
+
+        let reason = watchdog::take_finished_thread(&mut running_stream.thread)?;
+
+        if self.watchdog_restarts.fetch_add(1, Ordering::Relaxed) >= MAX_WATCHDOG_RESTARTS {
+            log!("ERROR: Audio output stopped: {}, audio output keeps failing\r", reason);
+            AUDIO_ERROR.store(true, Ordering::Release);
+            return None;
+        }
+
+        log!("ERROR: Audio output stopped: {}, restarting audio output\r", reason);
+        Self::start_stream(&mut stream, audio_device_number).ok()?;
+        Some(reason)
+    }
+
+    fn start_stream(stream: &mut Option<OutputStream>, audio_device_number: Option<i32>) -> Result<u32, String> {
+        if let Some(mut running_stream) = stream.take() {
+            running_stream.stop();
+        }
+
+        let requested_sample_rate = AUDIO_OUTPUT.get_requested_sample_rate();
+
+        // audio devices can't follow a virtual clock, the null sink drains the buffers at its pace instead
+        let audio_device = if CLOCK.is_real_time() {
+            open_audio_device(audio_device_number, requested_sample_rate)
+        } else {
+            Ok(None)
+        };
+
+        let audio_device = audio_device.map_err(|error| {
+            // the audio error makes the client disconnect, the watchdog event informs the user
+            log!("ERROR: No usable audio device found: {}\r", error);
+            AUDIO_ERROR.store(true, Ordering::Release);
+            WATCHDOG.report(WatchdogEvent::AudioDeviceFailed(error.clone()));
+            error
+        })?;
+
+        let should_stop = Arc::new(AtomicBool::new(false));
+        let should_stop_clone = should_stop.clone();
+        let failed = Arc::new(AtomicBool::new(false));
+        let failed_clone = failed.clone();
+        let device_removed = Arc::new(AtomicBool::new(false));
+        let device_removed_clone = device_removed.clone();
+        let buffer_time_in_millis = AUDIO_OUTPUT.get_buffer_time();
+
+        let (device_name, sample_rate, channel_count, thread) = match audio_device {
+            Some((device, device_config)) => {
+                let device_name = device.name().unwrap_or_default();
+                let sample_rate = device_config.sample_rate().0;
+                let channel_count = device_config.channels();
+
+                if audio_device_number.is_some() {
+                    log!("Using audio device: \"{}\" (sample rate: {})\r", device_name, sample_rate);
+                }
+
+                let stream_config = create_stream_config(&device_config, buffer_time_in_millis);
+
+                (device_name, sample_rate, channel_count, thread::spawn(move || {
+                    let result = match device_config.sample_format() {
+                        SampleFormat::F32 => run::<f32>(&device, &stream_config, should_stop_clone, device_removed_clone),
+                        SampleFormat::I16 => run::<i16>(&device, &stream_config, should_stop_clone, device_removed_clone),
+                        SampleFormat::U16 => run::<u16>(&device, &stream_config, should_stop_clone, device_removed_clone)
+                    };
+
+                    if let Err(error) = result {
+                        log!("ERROR: {}\r", error);
+                        failed_clone.store(true, Ordering::Release);
+                    }
+                }))
+            }
+            None => {
+                log!("No audio output device available, rendering without audio output\r");
+                (String::new(), DEFAULT_SAMPLE_RATE, DEFAULT_CHANNEL_COUNT, thread::spawn(move || run_null_sink(should_stop_clone)))
+            }
+        };
+
+        AUDIO_TAP.set_sample_rate(sample_rate);
+
+        *stream = Some(OutputStream {
+            host_id: AUDIO_HOST.get_host_id(),
+            audio_device_number,
+            device_name,
+            sample_rate,
+            channel_count,
+            requested_sample_rate,
+            buffer_time_in_millis,
+            should_stop,
+            failed,
+            device_removed,
+            thread: Some(thread)
+        });
+        Ok(sample_rate)
+    }
+
+    fn is_paused(&self) -> bool {
+        self.sources.lock().iter().all(|source| source.should_pause.load(Ordering::Relaxed))
+    }
+}
+
+// the selected device is tried first, then the default device and then any other output device;
+// having no device at all is not an error, the output then uses a null sink
+fn open_audio_device(audio_device_number: Option<i32>, requested_sample_rate: Option<u32>) -> Result<Option<(Device, SupportedStreamConfig)>, String> {
+    let host = AUDIO_HOST.get_host();
+    let mut devices: Vec<Device> = host.output_devices().map(|devices| devices.collect()).unwrap_or_default();
+    let default_device = host.default_output_device();
+
+    if devices.is_empty() && default_device.is_none() {
+        return Ok(None);
+    }
+
+    let selected_device = audio_device_number
+        .filter(|&device_number| device_number >= 0 && (device_number as usize) < devices.len())
+        .map(|device_number| devices.remove(device_number as usize));
+
+    let mut last_error = String::new();
+
+    for (index, device) in selected_device.into_iter().chain(default_device).chain(devices).enumerate() {
+        match get_output_config(&device, requested_sample_rate) {
+            Ok(device_config) => {
+                if index > 0 {
+                    log!("Falling back to audio device: \"{}\"\r", device.name().unwrap_or_default());
+                }
+                return Ok(Some((device, device_config)));
+            }
+            Err(error) => {
+                log!("ERROR: Audio device \"{}\" cannot be used: {}\r", device.name().unwrap_or_default(), error);
+                last_error = error.to_string();
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+// a sample rate that the device doesn't support falls back to the default config of the device
+fn get_output_config(device: &Device, requested_sample_rate: Option<u32>) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+    let default_config = device.default_output_config()?;
+
+    let sample_rate = match requested_sample_rate {
+        Some(sample_rate) if sample_rate != default_config.sample_rate().0 => sample_rate,
+        _ => return Ok(default_config)
+    };
+
+    // the sample format of the default config is preferred, the driver converts the least then
+    let supported_config = device.supported_output_configs().ok().and_then(|configs| configs
+        .filter(|config| config.channels() == default_config.channels())
+        .filter(|config| (config.min_sample_rate().0..=config.max_sample_rate().0).contains(&sample_rate))
+        .max_by_key(|config| config.sample_format() == default_config.sample_format()));
+
+    match supported_config {
+        Some(supported_config) => Ok(supported_config.with_sample_rate(SampleRate(sample_rate))),
+        None => {
+            log!("Sample rate {} is not supported by audio device \"{}\", using sample rate {}\r", sample_rate, device.name().unwrap_or_default(), default_config.sample_rate().0);
+            Ok(default_config)
+        }
+    }
+}
+
+// cpal only opens WASAPI in shared mode, a small buffer in shared mode is the closest to exclusive mode
+fn create_stream_config(device_config: &SupportedStreamConfig, buffer_time_in_millis: Option<u32>) -> StreamConfig {
+    let mut stream_config = device_config.config();
+
+    if let Some(buffer_time_in_millis) = buffer_time_in_millis {
+        let frames = device_config.sample_rate().0 * buffer_time_in_millis / 1000;
+
+        stream_config.buffer_size = BufferSize::Fixed(match *device_config.buffer_size() {
+            SupportedBufferSize::Range { min, max } => frames.clamp(min, max),
+            SupportedBufferSize::Unknown => frames
+        });
+    }
+    stream_config
+}
+
+fn run<T>(device: &Device, config: &StreamConfig, should_stop: Arc<AtomicBool>, device_removed: Arc<AtomicBool>) -> Result<(), anyhow::Error> where T: Sample {
+    let channels = config.channels as usize;
+
+    // some drivers stop the stream after reporting an error, so the stream is rebuilt
+    let stream_error = Arc::new(AtomicBool::new(false));
+    let err_fn = {
+        let stream_error = stream_error.clone();
+        let device_removed = device_removed.clone();
+        move |err| {
+            log!("ERROR: {}\r", err);
+
+            if let StreamError::DeviceNotAvailable = err {
+                device_removed.store(true, Ordering::Release);
+            } else {
+                stream_error.store(true, Ordering::Release);
+            }
+        }
+    };
+
+    let output_stream = move |data: &mut [T], _: &OutputCallbackInfo| {
+        let sources = AUDIO_OUTPUT.sources.lock();
+
+        // an empty buffer means idle, a partially filled one means playback starved
+        for source in sources.iter() {
+            let samples_in_buffer = source.sound_buffer.len();
+            if samples_in_buffer > 0 && samples_in_buffer < data.len() {
+                BUFFER_HEALTH.add_underrun();
+            }
+        }
+
+        // muting doesn't need to be ordered with the sample data, so this stays cheap in the audio callback
+        let muted = AUDIO_MUTED.load(Ordering::Relaxed);
+
+        let mut next_value = || {
+            let sample: i32 = sources.iter().map(|source| source.sound_buffer.try_pop().unwrap_or(0) as i32).sum();
+
+            // keep draining the buffers while muted so emulation continues in real time
+            if muted {
+                T::from::<i16>(&0)
+            } else {
+                T::from::<i16>(&(sample.clamp(i16::MIN as i32, i16::MAX as i32) as i16))
+            }
+        };
+
+        write_data(data, channels, &mut next_value)
+    };
+
+    // a driver that rejects the requested buffer size gets its default buffer size
+    let stream = match device.build_output_stream(config, output_stream.clone(), err_fn.clone()) {
+        Err(error) if config.buffer_size != BufferSize::Default => {
+            log!("Audio buffer size is not supported: {}, using the default buffer size\r", error);
+
+            let default_config = StreamConfig { buffer_size: BufferSize::Default, ..config.clone() };
+            device.build_output_stream(&default_config, output_stream, err_fn)?
+        }
+        result => result?
+    };
+    stream.play()?;
+
+    while !should_stop.load(Ordering::Acquire) {
+        if device_removed.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        if stream_error.load(Ordering::Acquire) {
+            anyhow::bail!("Audio stream stopped");
+        }
+
+        if AUDIO_OUTPUT.is_paused() {
+            stream.pause()?;
+        } else {
+            stream.play()?;
+        }
+        thread::sleep(Duration::from_millis(STOP_PAUSE_LATENCY_IN_MILLIS));
+    }
+
+    Ok(())
+}
+
+// without any output device the sound buffers are drained in real time, so the protocol keeps working
+fn run_null_sink(should_stop: Arc<AtomicBool>) {
+    let samples_per_interval = (DEFAULT_SAMPLE_RATE as u64 * DEFAULT_CHANNEL_COUNT as u64 * STOP_PAUSE_LATENCY_IN_MILLIS / 1000) as usize;
+    let mut next_time = CLOCK.now();
+
+    while !should_stop.load(Ordering::Acquire) {
+        for source in AUDIO_OUTPUT.sources.lock().iter() {
+            for _ in 0..samples_per_interval {
+                let _ = source.sound_buffer.try_pop();
+            }
+        }
+
+        next_time += Duration::from_millis(STOP_PAUSE_LATENCY_IN_MILLIS);
+        CLOCK.sleep(next_time.saturating_duration_since(CLOCK.now()));
+    }
+}
+
+fn write_data<T>(output: &mut [T], channels: usize, next_value: &mut dyn FnMut() -> T) where T: Sample {
+    for frame in output.chunks_mut(channels) {
+        for sample in frame.iter_mut() {
+            *sample = next_value();
+        }
+    }
+}
@@ -0,0 +1,1176 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use parking_lot::Mutex;
+use std::cmp::min;
+use std::panic;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread::JoinHandle;
+use std::{thread, time::{Duration, Instant}};
+
+use atomicring::AtomicRingBuffer;
+use crossbeam_channel::{Sender, Receiver, bounded};
+use rand::Rng;
+use typed_builder::TypedBuilder;
+
+use resid::{chip_model, sampling_method, Sid};
+use thread_priority::{set_current_thread_priority, ThreadPriority};
+
+use crate::log::log;
+use crate::MAX_SID_COUNT;
+use crate::audio_tap::AUDIO_TAP;
+use crate::buffer_health::BUFFER_HEALTH;
+use crate::clock::CLOCK;
+use crate::player::audio_output::{AUDIO_OUTPUT, DEVICE_LATENCY_DIVIDER, OutputSource};
+use crate::player::external_input::{EXTERNAL_INPUT, InputSource};
+use crate::player::loudness_normalizer::LoudnessNormalizer;
+#[cfg(target_arch = "aarch64")]
+use crate::player::neon;
+use crate::watchdog::{self, MAX_WATCHDOG_RESTARTS};
+
+pub static AUDIO_ERROR: AtomicBool = AtomicBool::new(false);
+pub static AUDIO_MUTED: AtomicBool = AtomicBool::new(false);
+
+const AUDIO_BUFFER_SIZE: usize = 65_536;
+const SAMPLE_BUFFER_SIZE: usize = 8_192;
+const SID_READ_RESPONSE_QUEUE_SIZE: usize = 16;
+const SAMPLE_BLOCK_QUEUE_SIZE: usize = 4;
+const NORMAL_THREAD_PRIORITY: u8 = 50;
+
+const AUDIO_STREAM_LIMIT: usize = 10_000;
+const AUDIO_STREAM_MAX_LIMIT: usize = 55_000;
+const STEREO_CHANNELS: usize = 2;
+
+const PAL_CLOCK: u32 = 985_248;
+const NTSC_CLOCK: u32 = 1_022_727;
+
+const DEFAULT_FILTER_BIAS_6581: f64 = 0.24;
+
+const PAUSE_AUDIO_IDLE_TIME_IN_SEC: u64 = 2;
+
+const CYCLES_PER_SAMPLE: u32 = 5_000;
+
+const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+const CYCLES_IN_BUFFER_THRESHOLD: u32 = 10_000;
+const SOUND_BUFFER_SIZE_THRESHOLD: usize = 5_000;
+
+const MAX_DIGIBOOST_LEVEL: i32 = 100;
+
+const SOFT_CLIP_THRESHOLD: f64 = 24_576.0;     // about -2.5 dBFS
+
+const VOICE_COUNT: i32 = 3;
+const ALL_VOICES_MASK: u32 = 0x07;
+const EXTERNAL_INPUT_MASK: u32 = 0x08;
+
+#[derive(Copy, Clone)]
+pub struct SidWrite {
+    pub reg: u8,
+    pub data: u8,
+    pub cycles: u16,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PlayerCommand {
+    SetClock,
+    SetModel,
+    SetSidCount,
+    SetPosition,
+    SetSamplingMethod,
+    EnableDigiboost,
+    DisableDigiboost,
+    SetDigiboostLevel,
+    EnableExternalInput,
+    DisableExternalInput,
+    EnableOld8580Filter,
+    DisableOld8580Filter,
+    EnableLowPowerMode,
+    DisableLowPowerMode,
+    EnableModelComparison,
+    DisableModelComparison,
+    SetModelComparisonBalance,
+    EnableLoudnessNormalization,
+    DisableLoudnessNormalization,
+    EnableSoftClipping,
+    DisableSoftClipping,
+    FadeIn,
+    FadeOut,
+    SetFilterBias6581,
+    SetMasterVolume,
+    SetGain,
+    SetGainTrim,
+    SetPan,
+    SetVoiceMask,
+    SetVoiceMute,
+    SetOutputChannels,
+    SetSamplingFrequency,
+    SetLatency,
+    Reset,
+    Read
+}
+
+// the samples of every SID for one clocking step, mixed on the mixing thread
+struct SampleBlock {
+    sid_samples: Vec<Vec<i16>>,
+    output_levels: Vec<(i32, i32)>,
+    output_channels: Vec<(usize, usize)>,
+    channel_count: usize,
+    loudness_normalization: bool,
+    soft_clipping: bool,
+    sample_rate: u32,
+    fade_levels: (f64, f64),
+    generation: u32
+}
+
+// the flags use acquire/release ordering, the pause hint and the cycle count are only hints and use relaxed ordering
+struct DeviceState {
+    should_stop: Arc<AtomicBool>,
+    should_pause: Arc<AtomicBool>,
+    queue_started: Arc<AtomicBool>,
+    aborted: Arc<AtomicBool>,
+    cycles_in_buffer: Arc<AtomicU32>
+}
+
+#[derive(TypedBuilder)]
+pub struct Config {
+    pub sample_rate: u32,
+    pub sampling_method: sampling_method,
+    pub clock: u32,
+    pub sid_count: i32,
+    pub chip_model: Vec<chip_model>,
+    pub position_left: Vec<i32>,
+    pub position_right: Vec<i32>,
+    pub digiboost: bool,
+    pub filter_bias_6581: f64,
+
+    #[builder(default=100)]
+    pub master_volume: i32,
+    #[builder(default=MAX_DIGIBOOST_LEVEL)]
+    pub digiboost_level: i32,
+    #[builder(default=[100; MAX_SID_COUNT])]
+    pub gain: [i32; MAX_SID_COUNT],
+    // the relative volume of the SIDs that is set by the user, on top of the gain of the mixer
+    #[builder(default=[100; MAX_SID_COUNT])]
+    pub gain_trim: [i32; MAX_SID_COUNT],
+    #[builder(default=[100; MAX_SID_COUNT])]
+    pub pan_left: [i32; MAX_SID_COUNT],
+    #[builder(default=[100; MAX_SID_COUNT])]
+    pub pan_right: [i32; MAX_SID_COUNT],
+    #[builder(default=[ALL_VOICES_MASK; MAX_SID_COUNT])]
+    pub voice_mask: [u32; MAX_SID_COUNT],
+    // the voices the client muted, on top of the voices that are masked in the mixer
+    #[builder(default=[0; MAX_SID_COUNT])]
+    pub muted_voices: [u32; MAX_SID_COUNT],
+    // the channels of the audio device the left and right output of every SID go to
+    #[builder(default=[(0, 1); MAX_SID_COUNT])]
+    pub output_channels: [(usize, usize); MAX_SID_COUNT],
+    #[builder(default=STEREO_CHANNELS)]
+    pub output_channel_count: usize,
+
+    #[builder(default=false)]
+    pub old_8580_filter: bool,
+
+    // the last sample of the external input is held for its cycles, until the next sample is fed
+    #[builder(default=false)]
+    pub external_input: bool,
+    #[builder(default=0)]
+    pub external_input_sample: i16,
+    #[builder(default=0.0)]
+    pub external_input_cycles: f64,
+
+    #[builder(default=false)]
+    pub low_power_mode: bool,
+
+    // without a latency the sound buffer limits are used that suit the default buffer size of most audio devices
+    #[builder(default)]
+    pub latency_in_millis: Option<u32>,
+
+    #[builder(default=false)]
+    pub model_comparison: bool,
+    #[builder(default=50)]
+    pub model_comparison_balance: i32,
+
+    #[builder(default=false)]
+    pub loudness_normalization: bool,
+
+    #[builder(default=false)]
+    pub soft_clipping: bool,
+
+    // the fade level changes by the step for every clocked cycle, until it reaches silence or full volume
+    #[builder(default=1.0)]
+    pub fade_level: f64,
+    #[builder(default=0.0)]
+    pub fade_step: f64,
+
+    #[builder(default=false)]
+    pub config_changed: bool
+}
+
+impl Config {
+    fn start_fade(&mut self, millis: i32, fade_in: bool) {
+        let cycles = millis.max(0) as f64 * self.clock as f64 / 1000.0;
+
+        if fade_in {
+            self.fade_level = if cycles > 0.0 { 0.0 } else { 1.0 };
+            self.fade_step = if cycles > 0.0 { 1.0 / cycles } else { 0.0 };
+        } else {
+            self.fade_step = if cycles > 0.0 { -self.fade_level / cycles } else { 0.0 };
+            if cycles <= 0.0 {
+                self.fade_level = 0.0;
+            }
+        }
+    }
+
+    fn advance_fade(&mut self, cycles: u32) {
+        if self.fade_step != 0.0 {
+            self.fade_level = (self.fade_level + self.fade_step * cycles as f64).clamp(0.0, 1.0);
+
+            if self.fade_level <= 0.0 || self.fade_level >= 1.0 {
+                self.fade_step = 0.0;
+            }
+        }
+    }
+
+    // the sound buffer holds a sample for every channel of the audio device
+    fn get_audio_stream_limit(&self) -> usize {
+        self.latency_in_millis.map_or(AUDIO_STREAM_LIMIT * self.output_channel_count / STEREO_CHANNELS, |latency_in_millis| {
+            let buffer_time_in_millis = (latency_in_millis - latency_in_millis / DEVICE_LATENCY_DIVIDER) as usize;
+            buffer_time_in_millis * self.sample_rate as usize * self.output_channel_count / 1000
+        }).min(AUDIO_STREAM_MAX_LIMIT)
+    }
+
+    fn get_sound_buffer_size_threshold(&self) -> usize {
+        self.latency_in_millis.map_or(SOUND_BUFFER_SIZE_THRESHOLD * self.output_channel_count / STEREO_CHANNELS, |_| self.get_audio_stream_limit() / 2)
+    }
+
+    // channels that the audio device doesn't have are folded onto the channels it has
+    fn get_output_channels(&self, sid_index: usize) -> (usize, usize) {
+        let sid_number = sid_index % self.sid_count as usize;
+        let (left, right) = self.output_channels[sid_number];
+        (left % self.output_channel_count, right % self.output_channel_count)
+    }
+
+    // in model comparison mode every SID has a twin with the other chip model after the SIDs of the client
+    fn get_emulated_sid_count(&self) -> usize {
+        let sid_count = self.sid_count as usize;
+        if self.model_comparison { sid_count * 2 } else { sid_count }
+    }
+
+    fn get_chip_model(&self, sid_index: usize) -> chip_model {
+        let sid_count = self.sid_count as usize;
+        let model = self.chip_model[sid_index % sid_count];
+
+        if sid_index < sid_count {
+            model
+        } else if model == chip_model::MOS6581 {
+            chip_model::MOS8580
+        } else {
+            chip_model::MOS6581
+        }
+    }
+
+    fn get_voice_mask(&self, sid_index: usize) -> u32 {
+        let sid_number = sid_index % self.sid_count as usize;
+        let voice_mask = self.voice_mask[sid_number] & !self.muted_voices[sid_number];
+
+        if self.external_input || (self.digiboost && self.get_chip_model(sid_index) == chip_model::MOS8580) {
+            voice_mask | EXTERNAL_INPUT_MASK
+        } else {
+            voice_mask
+        }
+    }
+
+    // the boost is the DC offset on the external input of the 8580, a full boost is the maximum offset
+    fn get_digiboost_input(&self) -> i16 {
+        (i16::MIN as i32 * self.digiboost_level / MAX_DIGIBOOST_LEVEL) as i16
+    }
+
+    // the external input is added to the DC offset of the digi boost
+    fn get_input_sample(&self, sid_index: usize, external_sample: i16) -> i16 {
+        let offset = if self.digiboost && self.get_chip_model(sid_index) == chip_model::MOS8580 {
+            self.get_digiboost_input() as i32
+        } else {
+            0
+        };
+        (offset + external_sample as i32).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+
+    fn get_output_levels(&self, sid_index: usize) -> (i32, i32) {
+        let sid_number = sid_index % self.sid_count as usize;
+        let mut gain = self.gain[sid_number] * self.gain_trim[sid_number] / 100 * self.master_volume / 100;
+
+        // the balance is the share of the other chip model in the mix
+        if self.model_comparison {
+            let balance = if sid_index < self.sid_count as usize { 100 - self.model_comparison_balance } else { self.model_comparison_balance };
+            gain = gain * balance / 100;
+        }
+
+        if self.sid_count == 1 {
+            (gain * self.pan_left[sid_number] / 100, gain * self.pan_right[sid_number] / 100)
+        } else {
+            (
+                self.position_left[sid_number] * gain / 100 * self.pan_left[sid_number] / 100,
+                self.position_right[sid_number] * gain / 100 * self.pan_right[sid_number] / 100
+            )
+        }
+    }
+}
+
+pub struct AudioRenderer {
+    in_cmd_sender: Sender<(PlayerCommand, Option<i32>)>,
+    in_cmd_receiver: Receiver<(PlayerCommand, Option<i32>)>,
+    out_sid_read_sender: Sender<(u32, u8)>,
+    out_sid_read_receiver: Receiver<(u32, u8)>,
+    queue: Arc<AtomicRingBuffer<SidWrite>>,
+    queue_started: Arc<AtomicBool>,
+    aborted: Arc<AtomicBool>,
+    cycles_in_buffer: Arc<AtomicU32>,
+    audio_device_number: Option<i32>,
+    should_stop_audio_generator: Arc<AtomicBool>,
+    should_pause: Arc<AtomicBool>,
+    emulation_thread: Option<JoinHandle<()>>,
+    config: Arc<Mutex<Config>>,
+    sound_buffer: Arc<AtomicRingBuffer<i16>>,
+    output_source: Arc<OutputSource>,
+    watchdog_restarts: u32
+}
+
+impl Drop for AudioRenderer {
+    fn drop(&mut self) {
+        self.stop_threads();
+    }
+}
+
+impl AudioRenderer {
+    pub fn new(
+        queue: Arc<AtomicRingBuffer<SidWrite>>,
+        queue_started: Arc<AtomicBool>,
+        aborted: Arc<AtomicBool>,
+        cycles_in_buffer: Arc<AtomicU32>
+    ) -> AudioRenderer {
+        let (in_cmd_sender, in_cmd_receiver) = bounded(0);
+        // responses are buffered so the emulation thread never waits for a reader that has timed out
+        let (out_sid_read_sender, out_sid_read_receiver) = bounded(SID_READ_RESPONSE_QUEUE_SIZE);
+        let should_stop_audio_generator = Arc::new(AtomicBool::new(false));
+        let should_pause = Arc::new(AtomicBool::new(false));
+        let config = Self::create_default_config(DEFAULT_SAMPLE_RATE);
+        let sound_buffer = Arc::new(AtomicRingBuffer::<i16>::with_capacity(AUDIO_BUFFER_SIZE));
+        let output_source = Arc::new(OutputSource {
+            sound_buffer: sound_buffer.clone(),
+            should_pause: should_pause.clone()
+        });
+
+        AudioRenderer {
+            in_cmd_sender,
+            in_cmd_receiver,
+            out_sid_read_sender,
+            out_sid_read_receiver,
+            queue,
+            queue_started,
+            aborted,
+            cycles_in_buffer,
+            audio_device_number: None,
+            should_stop_audio_generator,
+            should_pause,
+            emulation_thread: None,
+            config: Arc::new(Mutex::new(config)),
+            sound_buffer,
+            output_source,
+            watchdog_restarts: 0
+        }
+    }
+
+    fn stop_threads(&mut self) {
+        self.stop_audio_generator_thread();
+        AUDIO_OUTPUT.remove_source(&self.output_source);
+    }
+
+    fn stop_audio_generator_thread(&mut self) {
+        self.should_stop_audio_generator.store(true, Ordering::Release);
+
+        if self.emulation_thread.is_some() {
+            let _ = self.emulation_thread.take().unwrap().join().ok();
+        }
+
+        self.should_stop_audio_generator.store(false, Ordering::Release);
+    }
+
+    pub fn start(&mut self, audio_device_number: Option<i32>) -> Result<(), String> {
+        if audio_device_number.is_some() {
+            self.audio_device_number = audio_device_number;
+        }
+
+        // the output source stays registered, so restarting the emulation does not reopen the shared device
+        self.stop_audio_generator_thread();
+        AUDIO_ERROR.store(false, Ordering::Release);
+
+        self.sound_buffer.clear();
+
+        let sample_rate = AUDIO_OUTPUT.add_source(self.output_source.clone(), self.audio_device_number)?;
+        self.config.lock().sample_rate = sample_rate;
+        self.update_output_channel_count();
+
+        let mut config = self.config.clone();
+
+        let mut sound_buffer_clone = self.sound_buffer.clone();
+        let should_stop_audio_generator_clone = self.should_stop_audio_generator.clone();
+        let should_pause_clone = self.should_pause.clone();
+        let aborted = self.aborted.clone();
+        let mut queue = self.queue.clone();
+        let cycles_in_buffer = self.cycles_in_buffer.clone();
+
+        let in_cmd_receiver = self.in_cmd_receiver.clone();
+        let out_sid_read_sender = self.out_sid_read_sender.clone();
+
+        let queue_started = self.queue_started.clone();
+
+        let device_state = DeviceState {
+            should_stop: should_stop_audio_generator_clone,
+            should_pause: should_pause_clone,
+            queue_started,
+            aborted,
+            cycles_in_buffer
+        };
+
+        self.emulation_thread = Some(thread::spawn(move || {
+            Self::sid_emulation_thread(
+                &mut queue,
+                &in_cmd_receiver,
+                &out_sid_read_sender,
+                &mut config,
+                &mut sound_buffer_clone,
+                device_state
+            )
+        }));
+        Ok(())
+    }
+
+    // the emulation thread only ends by itself after a panic
+    pub fn check_threads(&mut self) -> Option<String> {
+        if let Some(reason) = AUDIO_OUTPUT.check_stream() {
+            return Some(format!("Audio thread stopped: {}", reason));
+        }
+
+        // the stream can be moved to a device with another sample rate
+        if let Some(sample_rate) = AUDIO_OUTPUT.get_sample_rate() {
+            if self.config.lock().sample_rate != sample_rate {
+                self.config.lock().sample_rate = sample_rate;
+                let _ = self.in_cmd_sender.send((PlayerCommand::SetSamplingFrequency, Some(sample_rate as i32)));
+            }
+        }
+        self.update_output_channel_count();
+
+        let failure = format!("Emulation thread stopped: {}", watchdog::take_finished_thread(&mut self.emulation_thread)?);
+
+        // give up on a renderer that keeps failing, the client is disconnected through the audio error
+        if self.watchdog_restarts >= MAX_WATCHDOG_RESTARTS {
+            log!("ERROR: {}, audio renderer keeps failing\r", failure);
+            self.stop_threads();
+            AUDIO_ERROR.store(true, Ordering::Release);
+            return None;
+        }
+
+        log!("ERROR: {}, restarting audio renderer\r", failure);
+        self.watchdog_restarts += 1;
+        self.start(self.audio_device_number).ok()?;
+        Some(failure)
+    }
+
+    pub fn restart(&mut self, audio_device_number: Option<i32>) -> Result<(), String> {
+        if audio_device_number.is_some() {
+            self.audio_device_number = audio_device_number;
+        }
+        self.start(self.audio_device_number)
+    }
+
+    pub fn set_audio_device(&mut self, audio_device_number: Option<i32>) {
+        self.audio_device_number = audio_device_number;
+
+        self.sound_buffer.clear();
+        if let Ok(sample_rate) = AUDIO_OUTPUT.set_audio_device(self.audio_device_number) {
+            self.config.lock().sample_rate = sample_rate;
+            let _ = self.in_cmd_sender.send((PlayerCommand::SetSamplingFrequency, Some(sample_rate as i32)));
+        }
+        self.update_output_channel_count();
+    }
+
+    // the sound buffer is filled with frames of the channel count of the audio device
+    fn update_output_channel_count(&self) {
+        if let Some(channel_count) = AUDIO_OUTPUT.get_channel_count() {
+            self.config.lock().output_channel_count = channel_count.max(1) as usize;
+        }
+    }
+
+    fn sid_emulation_thread(
+        queue: &mut Arc<AtomicRingBuffer<SidWrite>>,
+        in_cmd_receiver_clone: &Receiver<(PlayerCommand, Option<i32>)>,
+        out_sid_read_sender: &Sender<(u32, u8)>,
+        config: &mut Arc<Mutex<Config>>,
+        sound_buffer: &mut Arc<AtomicRingBuffer<i16>>,
+        device_state: DeviceState
+    ) {
+        let mut sids: Vec<Sid> = vec![];
+
+        {
+            let mut config = config.lock();
+            let _ = set_current_thread_priority(get_emulation_thread_priority(config.low_power_mode));
+            configure_sids(&mut sids, &mut config);
+        }
+
+        // clocking the SIDs and mixing their output run on separate threads, so a burst of writes
+        // does not hold up the mixing of the samples that are already clocked
+        let (block_sender, block_receiver) = bounded(SAMPLE_BLOCK_QUEUE_SIZE);
+        let generation = Arc::new(AtomicU32::new(0));
+
+        let mut mixing_thread = Some(thread::spawn({
+            let sound_buffer = sound_buffer.clone();
+            let generation = generation.clone();
+            move || sid_mixing_thread(block_receiver, sound_buffer, generation)
+        }));
+
+        let mut last_activity = CLOCK.now();
+        loop {
+            let mut config = config.lock();
+
+            if device_state.should_stop.load(Ordering::Acquire) {
+                break;
+            }
+            if device_state.aborted.load(Ordering::Acquire) {
+                // blocks that are still queued for mixing belong to the aborted tune,
+                // the blocks are passed through a channel, so the generation itself needs no ordering
+                generation.fetch_add(1, Ordering::Relaxed);
+                sound_buffer.clear();
+                device_state.aborted.store(false, Ordering::Release);
+            }
+
+            // a failing mixing thread fails the emulation thread, so the watchdog restarts both
+            if mixing_thread.as_ref().map_or(false, |mixing_thread| mixing_thread.is_finished()) {
+                if let Err(payload) = mixing_thread.take().unwrap().join() {
+                    panic::resume_unwind(payload);
+                }
+            }
+
+            let sample_block_output = SampleBlockOutput {
+                sender: &block_sender,
+                generation: generation.load(Ordering::Relaxed)
+            };
+
+            if !queue.is_empty() && device_state.queue_started.load(Ordering::Acquire) {
+                last_activity = CLOCK.now();
+                device_state.should_pause.store(false, Ordering::Relaxed);
+            } else if !device_state.should_pause.load(Ordering::Relaxed) && (CLOCK.now() - last_activity).as_secs() > PAUSE_AUDIO_IDLE_TIME_IN_SEC {
+                device_state.should_pause.store(true, Ordering::Relaxed);
+            }
+
+            let cmd = process_player_command(in_cmd_receiver_clone, &mut config, &mut sids);
+
+            if let Some((command, param1)) = cmd {
+                if command == PlayerCommand::Read {
+                    while !queue.is_empty() {
+                        generate_sample(sound_buffer, &sample_block_output, queue, &mut sids, &device_state.cycles_in_buffer, &mut config);
+                    }
+
+                    let reg = param1.unwrap_or(0) & 0xff;
+                    let tag = (param1.unwrap_or(0) >> 8) as u32;
+                    let sid_num = min(reg >> 5, config.sid_count - 1) as usize;
+
+                    let sid_env_out = sids[sid_num].read(reg as u32 & 0x1f) as u8;
+                    let _ = out_sid_read_sender.try_send((tag, sid_env_out));
+                }
+            } else {
+                if !device_state.queue_started.load(Ordering::Acquire) {
+                    thread::sleep(Duration::from_millis(5));
+                    continue;
+                }
+
+                let busy_start = Instant::now();
+                try_generate_sample(sound_buffer, &sample_block_output, queue, &mut sids, &device_state.cycles_in_buffer, &mut config);
+                BUFFER_HEALTH.add_emulation_time(busy_start.elapsed());
+                BUFFER_HEALTH.update_buffers(queue, sound_buffer, device_state.cycles_in_buffer.load(Ordering::Relaxed));
+
+                if Self::has_enough_data(sound_buffer, &device_state, &config) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }
+
+        drop(block_sender);
+        if let Some(mixing_thread) = mixing_thread {
+            let _ = mixing_thread.join();
+        }
+    }
+
+    #[inline]
+    fn has_enough_data(sound_buffer: &mut Arc<AtomicRingBuffer<i16>>, device_state: &DeviceState, config: &Config) -> bool {
+        device_state.cycles_in_buffer.load(Ordering::Relaxed) > CYCLES_IN_BUFFER_THRESHOLD && sound_buffer.len() > config.get_sound_buffer_size_threshold()
+    }
+
+    fn create_default_config(sample_rate: u32) -> Config {
+        Config::builder()
+            .sample_rate(sample_rate)
+            .sampling_method(sampling_method::SAMPLE_RESAMPLE)
+            .clock(PAL_CLOCK)
+            .sid_count(1)
+            .chip_model(vec![chip_model::MOS6581])
+            .position_left(vec![0])
+            .position_right(vec![0])
+            .digiboost(false)
+            .filter_bias_6581(DEFAULT_FILTER_BIAS_6581)
+            .build()
+    }
+
+    pub fn get_channel_sender(&self) -> Sender<(PlayerCommand, Option<i32>)> {
+        self.in_cmd_sender.clone()
+    }
+
+    pub fn get_sid_read_receiver(&self) -> Receiver<(u32, u8)> {
+        self.out_sid_read_receiver.clone()
+    }
+}
+
+#[inline]
+fn process_player_command(in_cmd_receiver: &Receiver<(PlayerCommand, Option<i32>)>, config: &mut Config, sids: &mut [Sid]) -> Option<(PlayerCommand, Option<i32>)> {
+    let recv_result = in_cmd_receiver.try_recv();
+
+    if let Ok((command, param1)) = recv_result {
+        match command {
+            PlayerCommand::SetModel => {
+                if let Some(param1) = param1 {
+                    let model = param1 & 0xff;
+                    let sid_number = param1 >> 8;
+                    if sid_number >= 0 && sid_number < config.sid_count {
+                        config.chip_model[sid_number as usize] = if model == 0 {
+                            chip_model::MOS6581
+                        } else {
+                            chip_model::MOS8580
+                        };
+                    }
+
+                    config.config_changed = true;
+                }
+            }
+            PlayerCommand::SetClock => {
+                let clock = param1.unwrap();
+                config.clock = if clock == 0 {
+                    PAL_CLOCK
+                } else {
+                    NTSC_CLOCK
+                };
+
+                config.config_changed = true;
+            }
+            PlayerCommand::SetSidCount => {
+                let count = param1.unwrap() as usize;
+                config.sid_count = count as i32;
+                config.chip_model = vec![config.chip_model[0]; count];
+                config.position_left = vec![0; count];
+                config.position_right = vec![0; count];
+
+                config.config_changed = true;
+            }
+            PlayerCommand::SetPosition => {
+                if let Some(param1) = param1 {
+                    let position = ((param1 & 0xff) as i8) as i32;
+                    let sid_number = param1 >> 8;
+                    if sid_number >= 0 && sid_number < config.sid_count {
+                        config.position_left[sid_number as usize] = if position <= 0 { 100 } else { 100 - position };
+                        config.position_right[sid_number as usize] = if position >= 0 { 100 } else { 100 + position };
+                    }
+                }
+            }
+            PlayerCommand::SetSamplingMethod => {
+                let sampling_method = param1.unwrap();
+                config.sampling_method = if sampling_method == 1 {
+                    sampling_method::SAMPLE_RESAMPLE
+                } else {
+                    sampling_method::SAMPLE_INTERPOLATE
+                };
+
+                config.config_changed = true;
+            }
+            PlayerCommand::EnableDigiboost => {
+                config.digiboost = true;
+
+                for (i, sid) in sids.iter_mut().enumerate() {
+                    if config.get_chip_model(i) == chip_model::MOS8580 {
+                        sid.set_voice_mask(config.get_voice_mask(i));
+                        sid.input(config.get_digiboost_input());
+                    }
+                }
+            }
+            PlayerCommand::SetDigiboostLevel => {
+                if let Some(param1) = param1 {
+                    config.digiboost_level = param1.clamp(0, MAX_DIGIBOOST_LEVEL);
+
+                    if config.digiboost {
+                        for (i, sid) in sids.iter_mut().enumerate() {
+                            if config.get_chip_model(i) == chip_model::MOS8580 {
+                                sid.input(config.get_digiboost_input());
+                            }
+                        }
+                    }
+                }
+            }
+            PlayerCommand::DisableDigiboost => {
+                config.digiboost = false;
+
+                for (i, sid) in sids.iter_mut().enumerate() {
+                    if config.get_chip_model(i) == chip_model::MOS8580 {
+                        sid.set_voice_mask(config.get_voice_mask(i));
+                        sid.input(0);
+                    }
+                }
+            }
+            PlayerCommand::EnableExternalInput | PlayerCommand::DisableExternalInput => {
+                config.external_input = command == PlayerCommand::EnableExternalInput;
+                config.external_input_sample = 0;
+                config.external_input_cycles = 0.0;
+
+                for (i, sid) in sids.iter_mut().enumerate() {
+                    sid.set_voice_mask(config.get_voice_mask(i));
+                    sid.input(config.get_input_sample(i, 0));
+                }
+            }
+            PlayerCommand::EnableOld8580Filter | PlayerCommand::DisableOld8580Filter => {
+                config.old_8580_filter = command == PlayerCommand::EnableOld8580Filter;
+                config.config_changed = true;
+            }
+            PlayerCommand::EnableLowPowerMode | PlayerCommand::DisableLowPowerMode => {
+                config.low_power_mode = command == PlayerCommand::EnableLowPowerMode;
+                let _ = set_current_thread_priority(get_emulation_thread_priority(config.low_power_mode));
+
+                config.config_changed = true;
+            }
+            PlayerCommand::EnableModelComparison | PlayerCommand::DisableModelComparison => {
+                config.model_comparison = command == PlayerCommand::EnableModelComparison;
+                config.config_changed = true;
+            }
+            PlayerCommand::SetModelComparisonBalance => {
+                if let Some(param1) = param1 {
+                    config.model_comparison_balance = param1.clamp(0, 100);
+                }
+            }
+            PlayerCommand::EnableLoudnessNormalization | PlayerCommand::DisableLoudnessNormalization => {
+                config.loudness_normalization = command == PlayerCommand::EnableLoudnessNormalization;
+            }
+            PlayerCommand::EnableSoftClipping | PlayerCommand::DisableSoftClipping => {
+                config.soft_clipping = command == PlayerCommand::EnableSoftClipping;
+            }
+            PlayerCommand::SetFilterBias6581 => {
+                if let Some(param1) = param1 {
+                    let filter_bias = param1;
+                    config.filter_bias_6581 = filter_bias as f64 / 100.0;
+
+                    for (i, sid) in sids.iter_mut().enumerate() {
+                        if config.get_chip_model(i) == chip_model::MOS6581 {
+                            sid.adjust_filter_bias(config.filter_bias_6581);
+                        }
+                    }
+                }
+            }
+            PlayerCommand::SetMasterVolume => {
+                if let Some(param1) = param1 {
+                    config.master_volume = param1;
+                }
+            }
+            PlayerCommand::SetGain => {
+                if let Some(param1) = param1 {
+                    let sid_number = (param1 >> 16) as usize;
+                    if sid_number < MAX_SID_COUNT {
+                        config.gain[sid_number] = param1 & 0xffff;
+                    }
+                }
+            }
+            PlayerCommand::SetGainTrim => {
+                if let Some(param1) = param1 {
+                    let sid_number = (param1 >> 16) as usize;
+                    if sid_number < MAX_SID_COUNT {
+                        config.gain_trim[sid_number] = param1 & 0xffff;
+                    }
+                }
+            }
+            PlayerCommand::SetPan => {
+                if let Some(param1) = param1 {
+                    let pan = ((param1 & 0xff) as i8) as i32;
+                    let sid_number = (param1 >> 8) as usize;
+                    if sid_number < MAX_SID_COUNT {
+                        config.pan_left[sid_number] = if pan <= 0 { 100 } else { 100 - pan };
+                        config.pan_right[sid_number] = if pan >= 0 { 100 } else { 100 + pan };
+                    }
+                }
+            }
+            PlayerCommand::SetVoiceMask => {
+                if let Some(param1) = param1 {
+                    let sid_number = (param1 >> 8) as usize;
+                    if sid_number < MAX_SID_COUNT {
+                        config.voice_mask[sid_number] = (param1 as u32) & ALL_VOICES_MASK;
+                        update_voice_masks(sids, config, sid_number);
+                    }
+                }
+            }
+            PlayerCommand::SetOutputChannels => {
+                if let Some(param1) = param1 {
+                    let sid_number = (param1 >> 16) as usize;
+                    if sid_number < MAX_SID_COUNT {
+                        config.output_channels[sid_number] = (((param1 >> 8) & 0xff) as usize, (param1 & 0xff) as usize);
+                    }
+                }
+            }
+            PlayerCommand::SetVoiceMute => {
+                if let Some(param1) = param1 {
+                    let sid_number = (param1 >> 8) as usize;
+                    let voice = (param1 >> 1) & 0x7f;
+                    if sid_number < MAX_SID_COUNT && voice < VOICE_COUNT {
+                        let voice_bit = 1 << voice;
+                        if param1 & 1 != 0 {
+                            config.muted_voices[sid_number] |= voice_bit;
+                        } else {
+                            config.muted_voices[sid_number] &= !voice_bit;
+                        }
+                        update_voice_masks(sids, config, sid_number);
+                    }
+                }
+            }
+            PlayerCommand::SetLatency => {
+                config.latency_in_millis = param1.map(|latency_in_millis| latency_in_millis.max(0) as u32);
+            }
+            PlayerCommand::SetSamplingFrequency => {
+                if let Some(param1) = param1 {
+                    for sid in &mut sids.iter_mut() {
+                        sid.adjust_sampling_frequency(param1 as f64);
+                    }
+                }
+            }
+            PlayerCommand::FadeIn | PlayerCommand::FadeOut => {
+                if let Some(param1) = param1 {
+                    config.start_fade(param1, command == PlayerCommand::FadeIn);
+                }
+            }
+            PlayerCommand::Reset => {
+                config.fade_level = 1.0;
+                config.fade_step = 0.0;
+                config.config_changed = true;
+            }
+            _ => {}
+        }
+        return Some((command, param1));
+    }
+    None
+}
+
+// the twins of model comparison share the voice mask of the SID they compare with
+fn update_voice_masks(sids: &mut [Sid], config: &Config, sid_number: usize) {
+    let sid_count = config.sid_count as usize;
+    for (i, sid) in sids.iter_mut().enumerate().filter(|(i, _)| i % sid_count == sid_number) {
+        sid.set_voice_mask(config.get_voice_mask(i));
+    }
+}
+
+fn get_emulation_thread_priority(low_power_mode: bool) -> ThreadPriority {
+    if low_power_mode {
+        ThreadPriority::Crossplatform(NORMAL_THREAD_PRIORITY.try_into().unwrap())
+    } else {
+        ThreadPriority::Max
+    }
+}
+
+fn configure_sids(sids: &mut Vec<Sid>, config: &mut Config) {
+    sids.clear();
+
+    for i in 0..config.get_emulated_sid_count() {
+        // the filters only differ for the 8580
+        let mut sid = if config.old_8580_filter && config.get_chip_model(i) == chip_model::MOS8580 {
+            Sid::with_old_8580_filter()
+        } else {
+            Sid::new()
+        };
+
+        sid.set_chip_model(config.get_chip_model(i));
+
+        // interpolation takes far less CPU time than resampling
+        let sampling_method = if config.low_power_mode { sampling_method::SAMPLE_INTERPOLATE } else { config.sampling_method };
+        let _ = sid.set_sampling_parameters(config.clock as f64, sampling_method, config.sample_rate as f64);
+
+        sid.enable_filter(true);
+
+        let mut input_sample = 0;
+
+        if config.get_chip_model(i) == chip_model::MOS8580 {
+            if config.digiboost {
+                input_sample = config.get_digiboost_input();
+            }
+        } else {
+            sid.adjust_filter_bias(config.filter_bias_6581);
+        }
+
+        sid.set_voice_mask(config.get_voice_mask(i));
+        sid.input(input_sample);
+
+        sid.clock_delta(0xffff);
+
+        sids.push(sid);
+    }
+
+    config.config_changed = false;
+}
+
+struct SampleBlockOutput<'a> {
+    sender: &'a Sender<SampleBlock>,
+    generation: u32
+}
+
+fn try_generate_sample(audio_output_stream: &mut Arc<AtomicRingBuffer<i16>>, block_output: &SampleBlockOutput, sid_write_queue: &mut Arc<AtomicRingBuffer<SidWrite>>, sids: &mut Vec<Sid>, cycles_in_buffer: &Arc<AtomicU32>, config: &mut Config) {
+    if sid_write_queue.len() > 0 && audio_output_stream.len() < config.get_audio_stream_limit() {
+        generate_sample(audio_output_stream, block_output, sid_write_queue, sids, cycles_in_buffer, config);
+    }
+}
+
+fn generate_sample(audio_output_stream: &mut Arc<AtomicRingBuffer<i16>>, block_output: &SampleBlockOutput, sid_write_queue: &mut Arc<AtomicRingBuffer<SidWrite>>, sids: &mut Vec<Sid>, cycles_in_buffer: &Arc<AtomicU32>, config: &mut Config) {
+    if audio_output_stream.len() > AUDIO_STREAM_MAX_LIMIT {
+        return;
+    }
+
+    if config.config_changed {
+        configure_sids(sids, config);
+    }
+
+    let sid_count = config.sid_count as usize;
+    let external_input = if config.external_input { EXTERNAL_INPUT.get_source() } else { None };
+
+    let mut total_cycles = 0;
+    let mut sample_buffers = vec![[0i16; SAMPLE_BUFFER_SIZE]; sids.len()];
+
+    let mut sample_block = SampleBlock {
+        sid_samples: vec![Vec::new(); sids.len()],
+        output_levels: (0..sids.len()).map(|sid_index| config.get_output_levels(sid_index)).collect(),
+        output_channels: (0..sids.len()).map(|sid_index| config.get_output_channels(sid_index)).collect(),
+        channel_count: config.output_channel_count,
+        loudness_normalization: config.loudness_normalization,
+        soft_clipping: config.soft_clipping,
+        sample_rate: config.sample_rate,
+        fade_levels: (config.fade_level, config.fade_level),
+        generation: block_output.generation
+    };
+
+    while total_cycles < CYCLES_PER_SAMPLE {
+        let sid_write = sid_write_queue.try_pop();
+        if let Some(sid_write) = sid_write {
+
+            let cycles = sid_write.cycles as u32;
+            total_cycles += cycles;
+
+            if cycles > 0 {
+                let mut cycles = cycles;
+
+                while cycles > 0 {
+                    // the external input changes with every input sample, so the SIDs are clocked up to the next input sample
+                    let mut cycles_to_clock = match &external_input {
+                        Some(input_source) => feed_external_input(input_source, sids, config, cycles),
+                        None => cycles
+                    };
+                    cycles -= cycles_to_clock;
+
+                    while cycles_to_clock > 0 {
+                        let mut total_sample_length = 0;
+                        let mut total_cycles_left = 0;
+
+                        for (sid, sample_buffer) in sids.iter_mut().zip(sample_buffers.iter_mut()) {
+                            let (sample_length, cycles_left) = sid.sample(cycles_to_clock, sample_buffer, 1);
+
+                            total_sample_length = sample_length;
+                            total_cycles_left = cycles_left;
+                        }
+
+                        for (sid_samples, sample_buffer) in sample_block.sid_samples.iter_mut().zip(&sample_buffers) {
+                            sid_samples.extend_from_slice(&sample_buffer[..total_sample_length]);
+                        }
+                        cycles_to_clock = total_cycles_left;
+                    }
+                }
+
+                let sid_num = min(sid_write.reg >> 5, (config.sid_count - 1) as u8);
+                for sid in sids.iter_mut().skip(sid_num as usize).step_by(sid_count) {
+                    sid.write((sid_write.reg & 0x1f) as u32,  (sid_write.data) as u32);
+                }
+            }
+        } else {
+            break;
+        }
+    }
+
+    config.advance_fade(total_cycles);
+    sample_block.fade_levels.1 = config.fade_level;
+
+    if sample_block.sid_samples.first().map_or(false, |sid_samples| !sid_samples.is_empty()) {
+        let _ = block_output.sender.send(sample_block);
+    }
+
+    if total_cycles > 0 {
+        let cycles = cycles_in_buffer.load(Ordering::Relaxed);
+        if cycles > total_cycles {
+            cycles_in_buffer.fetch_sub(total_cycles, Ordering::Relaxed);
+        } else {
+            cycles_in_buffer.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// the last sample is held when the input device falls behind, returns the cycles until the next input sample
+fn feed_external_input(input_source: &InputSource, sids: &mut [Sid], config: &mut Config, cycles: u32) -> u32 {
+    if config.external_input_cycles < 1.0 {
+        if let Some(sample) = input_source.next_sample() {
+            config.external_input_sample = sample;
+        }
+
+        for (i, sid) in sids.iter_mut().enumerate() {
+            sid.input(config.get_input_sample(i, config.external_input_sample));
+        }
+        config.external_input_cycles += config.clock as f64 / input_source.sample_rate.max(1) as f64;
+    }
+
+    let cycles_to_clock = cycles.min(config.external_input_cycles as u32);
+    config.external_input_cycles -= cycles_to_clock as f64;
+    cycles_to_clock
+}
+
+fn sid_mixing_thread(block_receiver: Receiver<SampleBlock>, sound_buffer: Arc<AtomicRingBuffer<i16>>, generation: Arc<AtomicU32>) {
+    let _ = set_current_thread_priority(ThreadPriority::Max);
+
+    let mut mix_channels: Vec<Vec<i32>> = vec![];
+    let mut audio_buffer = vec![];    // for every channel of the audio device
+    let mut tap_buffer = vec![];      // for left and right channel
+    let mut loudness_normalizer = LoudnessNormalizer::new();
+
+    let mut rng = rand::thread_rng();
+    let mut prev_dithering = 0;
+    let mut generate_next_dithering_value = || -> i32 {
+        let tmp_value = prev_dithering;
+        prev_dithering = rng.gen::<i32>() & 1;
+        prev_dithering - tmp_value
+    };
+
+    // ends when the emulation thread drops the sender
+    for sample_block in block_receiver.iter() {
+        if sample_block.generation != generation.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        let length = sample_block.sid_samples[0].len();
+        mix_sid_samples(&sample_block, length, &mut mix_channels);
+
+        if sample_block.loudness_normalization {
+            loudness_normalizer.process(&mut mix_channels, sample_block.sample_rate);
+        }
+
+        // after the normalization, so a fade out isn't turned up again
+        if sample_block.fade_levels != (1.0, 1.0) {
+            apply_fade(&mut mix_channels, sample_block.fade_levels);
+        }
+
+        // the last stage before the hard limit, so the peaks of several SIDs summed up are rounded off instead of cut off
+        if sample_block.soft_clipping {
+            apply_soft_clipping(&mut mix_channels);
+        }
+
+        audio_buffer.clear();
+        for i in 0..length {
+            let dithering = generate_next_dithering_value();
+            audio_buffer.extend(mix_channels.iter().map(|channel| add_dithering_and_limit_output(channel[i], dithering)));
+        }
+
+        for sample in audio_buffer.iter() {
+            let _ = sound_buffer.try_push(*sample);
+        }
+
+        // recordings and the audio stream are stereo, so the channels of a multichannel device are mixed down
+        if sample_block.channel_count == STEREO_CHANNELS {
+            AUDIO_TAP.publish(&audio_buffer);
+        } else {
+            mix_down_to_stereo(&audio_buffer, sample_block.channel_count, &mut tap_buffer);
+            AUDIO_TAP.publish(&tap_buffer);
+        }
+    }
+}
+
+// the even channels go to the left and the odd channels to the right
+fn mix_down_to_stereo(audio_buffer: &[i16], channel_count: usize, stereo_buffer: &mut Vec<i16>) {
+    stereo_buffer.clear();
+
+    for frame in audio_buffer.chunks(channel_count) {
+        let (mut left, mut right) = (0, 0);
+        for (channel, &sample) in frame.iter().enumerate() {
+            if channel % 2 == 0 {
+                left += sample as i32;
+            } else {
+                right += sample as i32;
+            }
+        }
+
+        // a mono device has no odd channel
+        if channel_count == 1 {
+            right = left;
+        }
+        stereo_buffer.push(left.clamp(i16::MIN as i32, i16::MAX as i32) as i16);
+        stereo_buffer.push(right.clamp(i16::MIN as i32, i16::MAX as i32) as i16);
+    }
+}
+
+// mixes one SID at a time over the whole block, so the inner loops have no branches and can be vectorized
+fn mix_sid_samples(sample_block: &SampleBlock, length: usize, mix_channels: &mut Vec<Vec<i32>>) {
+    mix_channels.resize_with(sample_block.channel_count, Vec::new);
+    for mix_channel in mix_channels.iter_mut() {
+        mix_channel.clear();
+        mix_channel.resize(length, 0);
+    }
+
+    let outputs = sample_block.output_levels.iter().zip(&sample_block.output_channels);
+    for (sid_sample_buffer, (&(level_left, level_right), &(channel_left, channel_right))) in sample_block.sid_samples.iter().zip(outputs) {
+        let samples = &sid_sample_buffer[..length];
+
+        mix_channel(&mut mix_channels[channel_left], samples, level_left);
+        mix_channel(&mut mix_channels[channel_right], samples, level_right);
+    }
+}
+
+// ramps the gain linearly over the block, from the fade level at the start to the one at the end
+fn apply_fade(mix_channels: &mut [Vec<i32>], (start_level, end_level): (f64, f64)) {
+    for mix_channel in mix_channels.iter_mut() {
+        let length = mix_channel.len().max(1) as f64;
+
+        for (i, sample) in mix_channel.iter_mut().enumerate() {
+            let level = start_level + (end_level - start_level) * i as f64 / length;
+            *sample = (*sample as f64 * level) as i32;
+        }
+    }
+}
+
+fn mix_channel(mixed: &mut [i32], samples: &[i16], level: i32) {
+    #[cfg(target_arch = "aarch64")]
+    let start = neon::mix_channel(mixed, samples, level);
+    #[cfg(not(target_arch = "aarch64"))]
+    let start = 0;
+
+    for (mixed, &sample) in mixed[start..].iter_mut().zip(&samples[start..]) {
+        *mixed += sample as i32 * level / 100;
+    }
+}
+
+// the samples below the threshold pass unchanged, above it they are bent towards full scale with tanh
+fn apply_soft_clipping(mix_channels: &mut [Vec<i32>]) {
+    let headroom = i16::MAX as f64 - SOFT_CLIP_THRESHOLD;
+
+    for sample in mix_channels.iter_mut().flat_map(|mix_channel| mix_channel.iter_mut()) {
+        let magnitude = (*sample as f64).abs();
+
+        if magnitude > SOFT_CLIP_THRESHOLD {
+            let clipped = SOFT_CLIP_THRESHOLD + headroom * ((magnitude - SOFT_CLIP_THRESHOLD) / headroom).tanh();
+            *sample = clipped as i32 * sample.signum();
+        }
+    }
+}
+
+#[inline]
+fn add_dithering_and_limit_output(sample: i32, dithering: i32) -> i16 {
+    (sample + dithering).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
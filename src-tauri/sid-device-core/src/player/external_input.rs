@@ -0,0 +1,175 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use atomicring::AtomicRingBuffer;
+use cpal::{Device, HostId, InputCallbackInfo, Sample, SampleFormat, StreamConfig, SupportedStreamConfig};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use parking_lot::{const_mutex, Mutex};
+
+use crate::log::log;
+use crate::audio_host::AUDIO_HOST;
+
+const INPUT_BUFFER_SIZE: usize = 16_384;
+const MAX_BUFFERED_SAMPLES: usize = 4_800;
+const STOP_LATENCY_IN_MILLIS: u64 = 10;
+
+// one capture device feeds the EXT IN pin of every emulated SID
+pub static EXTERNAL_INPUT: ExternalInput = ExternalInput::new();
+
+#[derive(Clone)]
+pub struct InputSource {
+    samples: Arc<AtomicRingBuffer<i16>>,
+    pub sample_rate: u32
+}
+
+impl InputSource {
+    pub fn next_sample(&self) -> Option<i16> {
+        self.samples.try_pop()
+    }
+}
+
+struct InputStream {
+    host_id: HostId,
+    input_device_number: Option<i32>,
+    source: InputSource,
+    should_stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl InputStream {
+    fn stop(&mut self) {
+        self.should_stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+pub struct ExternalInput {
+    stream: Mutex<Option<InputStream>>
+}
+
+impl ExternalInput {
+    const fn new() -> ExternalInput {
+        ExternalInput {
+            stream: const_mutex(None)
+        }
+    }
+
+    // every player requests the input, only the first one opens the device
+    pub fn start(&self, input_device_number: Option<i32>) -> Result<(), String> {
+        let mut stream = self.stream.lock();
+
+        let host_id = AUDIO_HOST.get_host_id();
+        if stream.as_ref().map_or(false, |running_stream| running_stream.host_id == host_id && running_stream.input_device_number == input_device_number) {
+            return Ok(());
+        }
+
+        if let Some(mut running_stream) = stream.take() {
+            running_stream.stop();
+        }
+
+        let (device, device_config) = open_input_device(input_device_number)?;
+        log!("Using input device for EXT IN: \"{}\" (sample rate: {})\r", device.name().unwrap_or_default(), device_config.sample_rate().0);
+
+        let source = InputSource {
+            samples: Arc::new(AtomicRingBuffer::with_capacity(INPUT_BUFFER_SIZE)),
+            sample_rate: device_config.sample_rate().0
+        };
+        let should_stop = Arc::new(AtomicBool::new(false));
+
+        let thread = thread::spawn({
+            let samples = source.samples.clone();
+            let should_stop = should_stop.clone();
+
+            move || {
+                let result = match device_config.sample_format() {
+                    SampleFormat::F32 => run::<f32>(&device, &device_config.into(), samples, should_stop),
+                    SampleFormat::I16 => run::<i16>(&device, &device_config.into(), samples, should_stop),
+                    SampleFormat::U16 => run::<u16>(&device, &device_config.into(), samples, should_stop)
+                };
+
+                if let Err(error) = result {
+                    log!("ERROR: EXT IN input stopped: {}\r", error);
+                }
+            }
+        });
+
+        *stream = Some(InputStream {
+            host_id,
+            input_device_number,
+            source,
+            should_stop,
+            thread: Some(thread)
+        });
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some(mut stream) = self.stream.lock().take() {
+            stream.stop();
+        }
+    }
+
+    pub fn get_source(&self) -> Option<InputSource> {
+        self.stream.lock().as_ref().map(|stream| stream.source.clone())
+    }
+}
+
+fn open_input_device(input_device_number: Option<i32>) -> Result<(Device, SupportedStreamConfig), String> {
+    let host = AUDIO_HOST.get_host();
+
+    let device = match input_device_number {
+        Some(device_number) if device_number >= 0 => host.input_devices()
+            .map_err(|error| error.to_string())?
+            .nth(device_number as usize),
+        _ => host.default_input_device()
+    }.ok_or_else(|| "No input device found".to_string())?;
+
+    let device_config = device.default_input_config().map_err(|error| error.to_string())?;
+    Ok((device, device_config))
+}
+
+fn run<T>(device: &Device, config: &StreamConfig, samples: Arc<AtomicRingBuffer<i16>>, should_stop: Arc<AtomicBool>) -> Result<(), anyhow::Error> where T: Sample {
+    let channels = config.channels as usize;
+
+    let stream_error = Arc::new(AtomicBool::new(false));
+    let err_fn = {
+        let stream_error = stream_error.clone();
+        move |err| {
+            log!("ERROR: {}\r", err);
+            stream_error.store(true, Ordering::Release);
+        }
+    };
+
+    // the SID has a single input, so the channels are mixed down to mono
+    let input_stream = move |data: &[T], _: &InputCallbackInfo| {
+        for frame in data.chunks(channels) {
+            let sample = frame.iter().map(|sample| sample.to_i16() as i32).sum::<i32>() / frame.len() as i32;
+
+            // the oldest samples are dropped when the emulation falls behind, so the latency stays low
+            if samples.len() >= MAX_BUFFERED_SAMPLES {
+                let _ = samples.try_pop();
+            }
+            let _ = samples.try_push(sample as i16);
+        }
+    };
+
+    let stream = device.build_input_stream(config, input_stream, err_fn)?;
+    stream.play()?;
+
+    while !should_stop.load(Ordering::Acquire) {
+        if stream_error.load(Ordering::Acquire) {
+            anyhow::bail!("Input stream stopped");
+        }
+        thread::sleep(Duration::from_millis(STOP_LATENCY_IN_MILLIS));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+const TARGET_LEVEL: f64 = 6_000.0;      // RMS, about -15 dBFS
+const SILENCE_LEVEL: f64 = 150.0;       // RMS
+const MIN_GAIN: f64 = 0.25;
+const MAX_GAIN: f64 = 4.0;
+const MEASURE_TIME_IN_SEC: f64 = 3.0;
+const GAIN_TIME_IN_SEC: f64 = 2.0;
+const PEAK_LIMIT: f64 = i16::MAX as f64;
+const REFERENCE_CHANNELS: f64 = 2.0;
+
+// a slow automatic gain control on the final mix, so quiet and loud tunes play at a similar loudness
+pub struct LoudnessNormalizer {
+    mean_square: f64,
+    gain: f64
+}
+
+impl LoudnessNormalizer {
+    pub fn new() -> LoudnessNormalizer {
+        LoudnessNormalizer {
+            mean_square: TARGET_LEVEL * TARGET_LEVEL,
+            gain: 1.0
+        }
+    }
+
+    // the loudness is measured against stereo, so the channels of a multichannel device that are not used don't turn it up
+    pub fn process(&mut self, channels: &mut [Vec<i32>], sample_rate: u32) {
+        let measure_coefficient = 1.0 - (-1.0 / (MEASURE_TIME_IN_SEC * sample_rate as f64)).exp();
+        let gain_coefficient = 1.0 - (-1.0 / (GAIN_TIME_IN_SEC * sample_rate as f64)).exp();
+        let length = channels.iter().map(|channel| channel.len()).min().unwrap_or(0);
+
+        for i in 0..length {
+            let square = channels.iter().map(|channel| (channel[i] as f64).powi(2)).sum::<f64>() / REFERENCE_CHANNELS;
+            self.mean_square += (square - self.mean_square) * measure_coefficient;
+
+            // the gain is held during silence, so the pause between tunes isn't amplified
+            if self.mean_square > SILENCE_LEVEL * SILENCE_LEVEL {
+                let target_gain = (TARGET_LEVEL / self.mean_square.sqrt()).clamp(MIN_GAIN, MAX_GAIN);
+                self.gain += (target_gain - self.gain) * gain_coefficient;
+            }
+
+            // a loud start is turned down at once instead of clipping until the loudness is measured
+            let peak = channels.iter().map(|channel| (channel[i] as f64).abs()).fold(0.0, f64::max);
+            if peak * self.gain > PEAK_LIMIT {
+                self.gain = PEAK_LIMIT / peak;
+            }
+
+            for channel in channels.iter_mut() {
+                channel[i] = (channel[i] as f64 * self.gain) as i32;
+            }
+        }
+    }
+}
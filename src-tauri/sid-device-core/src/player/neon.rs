@@ -0,0 +1,70 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::arch::aarch64::*;
+
+const LANES: usize = 4;
+const DIVIDE_BY_100_MULTIPLIER: i32 = 0x51eb851f;
+const DIVIDE_BY_100_SHIFT: i32 = 37;
+
+// mixes 4 samples at a time and returns the number of samples mixed, the caller mixes the rest
+pub fn mix_channel(mixed: &mut [i32], samples: &[i16], level: i32) -> usize {
+    let length = mixed.len().min(samples.len()) / LANES * LANES;
+
+    // NEON is always available on aarch64 and the loads and stores stay within the length of both slices
+    unsafe {
+        for i in (0..length).step_by(LANES) {
+            let sample = vmovl_s16(vld1_s16(samples.as_ptr().add(i)));
+            let level_sample = divide_by_100(vmulq_n_s32(sample, level));
+
+            let mixed_samples = mixed.as_mut_ptr().add(i);
+            vst1q_s32(mixed_samples, vaddq_s32(vld1q_s32(mixed_samples), level_sample));
+        }
+    }
+    length
+}
+
+// same result as the integer division of the scalar mixer, which rounds towards zero
+#[inline]
+unsafe fn divide_by_100(value: int32x4_t) -> int32x4_t {
+    let low = vshlq_s64(vmull_n_s32(vget_low_s32(value), DIVIDE_BY_100_MULTIPLIER), vdupq_n_s64(-DIVIDE_BY_100_SHIFT as i64));
+    let high = vshlq_s64(vmull_n_s32(vget_high_s32(value), DIVIDE_BY_100_MULTIPLIER), vdupq_n_s64(-DIVIDE_BY_100_SHIFT as i64));
+    let quotient = vcombine_s32(vmovn_s64(low), vmovn_s64(high));
+
+    // subtracting the sign adds one to the quotient of negative values
+    vsubq_s32(quotient, vshrq_n_s32::<31>(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    fn mix_channel_scalar(mixed: &mut [i32], samples: &[i16], level: i32) {
+        for (mixed, &sample) in mixed.iter_mut().zip(samples) {
+            *mixed += sample as i32 * level / 100;
+        }
+    }
+
+    #[test]
+    fn mix_channel_matches_scalar_mixer() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(4230);
+
+        let mut samples: Vec<i16> = vec![i16::MIN, i16::MIN + 1, -101, -100, -99, -1, 0, 1, 99, 100, 101, i16::MAX - 1, i16::MAX];
+        samples.extend((0..1024).map(|_| rng.gen::<i16>()));
+        let initial_mixed: Vec<i32> = samples.iter().map(|_| rng.gen_range(-100_000..100_000)).collect();
+
+        for level in [0, 1, 33, 50, 99, 100, 101, 150, 200] {
+            let mut expected = initial_mixed.clone();
+            mix_channel_scalar(&mut expected, &samples, level);
+
+            let mut mixed = initial_mixed.clone();
+            let start = mix_channel(&mut mixed, &samples, level);
+            mix_channel_scalar(&mut mixed[start..], &samples[start..], level);
+
+            assert_eq!(start, samples.len() / LANES * LANES);
+            assert_eq!(mixed, expected, "level {}", level);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+//! The Network SID Device protocol, for servers and clients that talk to a SID device over TCP.
+//!
+//! Every command starts with a header of 4 bytes: the [`Command`], the SID number and the
+//! length of the data in big endian. A [`CommandFramer`] splits the received bytes into commands.
+
+use std::fmt;
+
+mod command_framer;
+mod psid_header;
+
+pub use command_framer::CommandFramer;
+pub use psid_header::{PsidHeader, TuneInfo};
+
+pub const PROTOCOL_VERSION: u8 = 5;
+pub const HEADER_SIZE: usize = 4;
+
+// clients of version 4 and older send GetVersion without their own version
+const LEGACY_PROTOCOL_VERSION: u8 = 4;
+
+#[derive(Copy, Clone)]
+pub enum SidClock {
+    Pal = 0,
+    Ntsc = 1
+}
+
+#[derive(Copy, Clone)]
+pub enum SamplingMethod {
+    Best = 0,
+    Fast = 1
+}
+
+#[derive(Copy, Clone)]
+pub enum CommandResponse {
+    Ok = 0,
+    Busy,
+    Error,
+    Read,
+    Version,
+    Count,
+    Info,
+    BufferInfo
+}
+
+#[derive(Copy, Clone)]
+pub enum Command {
+    Flush = 0,
+    TrySetSidCount,
+    Mute,
+    TryReset,
+    TryDelay,
+    TryWrite,
+    TryRead,
+    /// Replies with [`CommandResponse::Version`] and the version of the server. A client can send its own
+    /// version as one byte of data, the lower of both versions is used for the rest of the connection.
+    GetVersion,
+    TrySetSampling,
+    TrySetClock,
+    GetConfigCount,
+    GetConfigInfo,
+    SetSidPosition,
+    SetSidLevel,
+    TrySetSidModel,
+    SetDelay,
+    SetFadeIn,
+    SetFadeOut,
+    SetPsidHeader,
+    /// Sends the PIN of a remote host as a big endian u16. Needs protocol version 5.
+    TryPair,
+    /// Sends one byte, 0 for uncompressed and 1 for LZ4 compressed writes. Needs protocol version 5.
+    TrySetCompression,
+    /// Replies with [`CommandResponse::BufferInfo`], followed by the cycles in the buffer (u32), the minimum
+    /// cycles before the buffer is drained (u32) and the round trip time in milliseconds (u16), all big endian.
+    /// Needs protocol version 5.
+    GetBufferInfo
+}
+
+impl Command {
+    /// The protocol version that the client has to negotiate with [`Command::GetVersion`] to use the command.
+    pub fn get_min_protocol_version(&self) -> u8 {
+        match self {
+            Command::TryPair | Command::TrySetCompression | Command::GetBufferInfo => 5,
+            _ => 1
+        }
+    }
+}
+
+impl TryFrom<u8> for Command {
+    type Error = ProtocolError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Command::Flush),
+            1 => Ok(Command::TrySetSidCount),
+            2 => Ok(Command::Mute),
+            3 => Ok(Command::TryReset),
+            4 => Ok(Command::TryDelay),
+            5 => Ok(Command::TryWrite),
+            6 => Ok(Command::TryRead),
+            7 => Ok(Command::GetVersion),
+            8 => Ok(Command::TrySetSampling),
+            9 => Ok(Command::TrySetClock),
+            10 => Ok(Command::GetConfigCount),
+            11 => Ok(Command::GetConfigInfo),
+            12 => Ok(Command::SetSidPosition),
+            13 => Ok(Command::SetSidLevel),
+            14 => Ok(Command::TrySetSidModel),
+            15 => Ok(Command::SetDelay),
+            16 => Ok(Command::SetFadeIn),
+            17 => Ok(Command::SetFadeOut),
+            18 => Ok(Command::SetPsidHeader),
+            19 => Ok(Command::TryPair),
+            20 => Ok(Command::TrySetCompression),
+            21 => Ok(Command::GetBufferInfo),
+            _ => Err(ProtocolError::UnknownCommand(value))
+        }
+    }
+}
+
+/// The version that is used for a connection, from the data of a [`Command::GetVersion`] command.
+pub fn negotiate_version(data: &[u8]) -> u8 {
+    data.first().map_or(LEGACY_PROTOCOL_VERSION, |&client_version| client_version.min(PROTOCOL_VERSION))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProtocolError {
+    IncompleteHeader,
+    UnknownCommand(u8),
+    IncompleteData { expected: usize, received: usize }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::IncompleteHeader => write!(f, "Command header is incomplete."),
+            ProtocolError::UnknownCommand(value) => write!(f, "Unknown command {}.", value),
+            ProtocolError::IncompleteData { expected, received } => write!(f, "Not all data is retrieved, expected {} bytes but received {}.", expected, received)
+        }
+    }
+}
+
+/// Splits a command in its [`Command`], SID number and data.
+///
+/// The data is limited to the length in the header, the flush command is sent without data.
+pub fn parse_command(data: &[u8]) -> Result<(Command, u8, &[u8]), ProtocolError> {
+    if data.len() < HEADER_SIZE {
+        return Err(ProtocolError::IncompleteHeader);
+    }
+
+    let command = Command::try_from(data[0])?;
+    if matches!(command, Command::Flush) {
+        return Ok((command, data[1], &[]));
+    }
+
+    let data_length = ((data[2] as usize) << 8) + (data[3] as usize);
+    let received = data.len() - HEADER_SIZE;
+    if data_length > received {
+        return Err(ProtocolError::IncompleteData { expected: data_length, received });
+    }
+    Ok((command, data[1], &data[HEADER_SIZE..HEADER_SIZE + data_length]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_command_rejects_truncated_header() {
+        assert_eq!(parse_command(&[]).err(), Some(ProtocolError::IncompleteHeader));
+        assert_eq!(parse_command(&[Command::TryWrite as u8, 0, 0]).err(), Some(ProtocolError::IncompleteHeader));
+    }
+
+    #[test]
+    fn parse_command_rejects_unknown_command() {
+        assert_eq!(parse_command(&[22, 0, 0, 0]).err(), Some(ProtocolError::UnknownCommand(22)));
+        assert_eq!(parse_command(&[255, 0, 0, 0]).err(), Some(ProtocolError::UnknownCommand(255)));
+    }
+
+    #[test]
+    fn parse_command_rejects_oversized_length() {
+        let result = parse_command(&[Command::TryWrite as u8, 0, 0xff, 0xff, 1, 2, 3, 4]);
+        assert_eq!(result.err(), Some(ProtocolError::IncompleteData { expected: 0xffff, received: 4 }));
+    }
+
+    #[test]
+    fn parse_command_limits_data_to_length() {
+        let (command, sid_number, payload) = parse_command(&[Command::TryDelay as u8, 1, 0, 2, 0x12, 0x34, 0x56]).unwrap();
+        assert!(matches!(command, Command::TryDelay));
+        assert_eq!(sid_number, 1);
+        assert_eq!(payload, &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn parse_command_ignores_flush_length() {
+        let (command, _, payload) = parse_command(&[Command::Flush as u8, 0, 0xff, 0xff]).unwrap();
+        assert!(matches!(command, Command::Flush));
+        assert!(payload.is_empty());
+    }
+
+    #[test]
+    fn negotiate_version_uses_lowest_version() {
+        assert_eq!(negotiate_version(&[]), LEGACY_PROTOCOL_VERSION);
+        assert_eq!(negotiate_version(&[3]), 3);
+        assert_eq!(negotiate_version(&[PROTOCOL_VERSION]), PROTOCOL_VERSION);
+        assert_eq!(negotiate_version(&[PROTOCOL_VERSION + 1]), PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn new_commands_need_negotiated_version() {
+        let legacy_version = negotiate_version(&[]);
+        for command in [Command::TryPair, Command::TrySetCompression, Command::GetBufferInfo] {
+            assert!(command.get_min_protocol_version() > legacy_version);
+            assert!(command.get_min_protocol_version() <= negotiate_version(&[PROTOCOL_VERSION]));
+        }
+        assert!(Command::TryWrite.get_min_protocol_version() <= legacy_version);
+    }
+
+    #[test]
+    fn framer_output_never_panics_the_parser() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(4228);
+        let mut framer = CommandFramer::new();
+
+        for _ in 0..1000 {
+            let length = rng.gen_range(0..64);
+            let bytes: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            framer.read_from(&mut bytes.as_slice()).unwrap();
+
+            while let Some(command) = framer.next_command() {
+                let _ = parse_command(command);
+            }
+        }
+    }
+}
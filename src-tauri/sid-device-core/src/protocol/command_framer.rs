@@ -0,0 +1,108 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::io::{self, Read};
+
+use super::HEADER_SIZE;
+
+const MAX_COMMAND_SIZE: usize = HEADER_SIZE + u16::MAX as usize;
+const READ_BUFFER_SIZE: usize = 4 * MAX_COMMAND_SIZE;
+const FLUSH_COMMAND: u8 = 0;
+
+// splits the received data into commands, a read can contain several commands or only part of one
+pub struct CommandFramer {
+    buffer: Vec<u8>,
+    start: usize,
+    end: usize
+}
+
+impl CommandFramer {
+    pub fn new() -> CommandFramer {
+        CommandFramer {
+            buffer: vec![0u8; READ_BUFFER_SIZE],
+            start: 0,
+            end: 0
+        }
+    }
+
+    pub fn read_from(&mut self, stream: &mut impl Read) -> io::Result<usize> {
+        // move a partial command to the front, so there is always room for the largest command
+        if self.start > 0 {
+            self.buffer.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+
+        let size = stream.read(&mut self.buffer[self.end..])?;
+        self.end += size;
+        Ok(size)
+    }
+
+    pub fn next_command(&mut self) -> Option<&[u8]> {
+        let available = self.end - self.start;
+        if available < HEADER_SIZE {
+            return None;
+        }
+
+        let header = &self.buffer[self.start..self.start + HEADER_SIZE];
+
+        // clients send the flush command without its data
+        let data_length = if header[0] == FLUSH_COMMAND {
+            0
+        } else {
+            ((header[2] as usize) << 8) + (header[3] as usize)
+        };
+
+        let command_size = HEADER_SIZE + data_length;
+        if available < command_size {
+            return None;
+        }
+
+        let command_start = self.start;
+        self.start += command_size;
+        Some(&self.buffer[command_start..self.start])
+    }
+}
+
+impl Default for CommandFramer {
+    fn default() -> Self {
+        CommandFramer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_commands_received_in_one_read() {
+        let mut framer = CommandFramer::new();
+        framer.read_from(&mut &[7, 0, 0, 0, 3, 0, 0, 0][..]).unwrap();
+
+        assert_eq!(framer.next_command(), Some(&[7, 0, 0, 0][..]));
+        assert_eq!(framer.next_command(), Some(&[3, 0, 0, 0][..]));
+        assert_eq!(framer.next_command(), None);
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_command() {
+        let mut framer = CommandFramer::new();
+        framer.read_from(&mut &[4, 0, 0][..]).unwrap();
+        assert_eq!(framer.next_command(), None);
+
+        framer.read_from(&mut &[2, 0x01][..]).unwrap();
+        assert_eq!(framer.next_command(), None);
+
+        framer.read_from(&mut &[0x02][..]).unwrap();
+        assert_eq!(framer.next_command(), Some(&[4, 0, 0, 2, 0x01, 0x02][..]));
+    }
+
+    #[test]
+    fn flush_is_sent_without_data() {
+        let mut framer = CommandFramer::new();
+        framer.read_from(&mut &[FLUSH_COMMAND, 0, 0xff, 0xff, 7, 0, 0, 0][..]).unwrap();
+
+        assert_eq!(framer.next_command(), Some(&[FLUSH_COMMAND, 0, 0xff, 0xff][..]));
+        assert_eq!(framer.next_command(), Some(&[7, 0, 0, 0][..]));
+    }
+}
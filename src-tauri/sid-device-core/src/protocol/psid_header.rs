@@ -0,0 +1,100 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use super::SidClock;
+
+const PSID_VERSION_OFFSET: usize = 0x04;
+const PSID_TITLE_OFFSET: usize = 0x16;
+const PSID_AUTHOR_OFFSET: usize = 0x36;
+const PSID_RELEASED_OFFSET: usize = 0x56;
+const PSID_FLAGS_OFFSET: usize = 0x76;
+const PSID_SECOND_SID_ADDRESS_OFFSET: usize = 0x7a;
+const PSID_THIRD_SID_ADDRESS_OFFSET: usize = 0x7b;
+const PSID_V2_HEADER_SIZE: usize = 0x7c;
+const PSID_TEXT_SIZE: usize = 32;
+
+const FLAGS_CLOCK_SHIFT: u16 = 2;
+const FLAGS_SID_MODEL_SHIFTS: [u16; 3] = [4, 6, 8];
+const FLAGS_PAL: u16 = 0x01;
+const FLAGS_NTSC: u16 = 0x02;
+const FLAGS_6581: u16 = 0x01;
+const FLAGS_8580: u16 = 0x02;
+
+const SID_MODEL_6581: i32 = 0;
+const SID_MODEL_8580: i32 = 1;
+
+#[derive(Clone, serde::Serialize)]
+pub struct TuneInfo {
+    pub title: String,
+    pub author: String,
+    pub released: String
+}
+
+// the setup a tune asks for, fields a PSID version doesn't have or leaves open are None
+pub struct PsidHeader {
+    pub tune_info: TuneInfo,
+    pub clock: Option<SidClock>,
+    pub sid_count: i32,
+    pub sid_models: Vec<Option<i32>>
+}
+
+impl PsidHeader {
+    pub fn parse(header: &[u8]) -> Option<PsidHeader> {
+        if header.len() < PSID_RELEASED_OFFSET + PSID_TEXT_SIZE || !(header.starts_with(b"PSID") || header.starts_with(b"RSID")) {
+            return None;
+        }
+
+        let read_text = |offset: usize| {
+            let text = &header[offset..offset + PSID_TEXT_SIZE];
+            let length = text.iter().position(|&c| c == 0).unwrap_or(PSID_TEXT_SIZE);
+            // PSID texts are Latin-1 encoded
+            text[..length].iter().map(|&c| c as char).collect::<String>()
+        };
+
+        let tune_info = TuneInfo {
+            title: read_text(PSID_TITLE_OFFSET),
+            author: read_text(PSID_AUTHOR_OFFSET),
+            released: read_text(PSID_RELEASED_OFFSET)
+        };
+
+        let version = ((header[PSID_VERSION_OFFSET] as u16) << 8) + header[PSID_VERSION_OFFSET + 1] as u16;
+        if version < 2 || header.len() < PSID_V2_HEADER_SIZE {
+            return Some(PsidHeader { tune_info, clock: None, sid_count: 1, sid_models: vec![None] });
+        }
+
+        let flags = ((header[PSID_FLAGS_OFFSET] as u16) << 8) + header[PSID_FLAGS_OFFSET + 1] as u16;
+
+        let clock = match (flags >> FLAGS_CLOCK_SHIFT) & 0x03 {
+            FLAGS_PAL => Some(SidClock::Pal),
+            FLAGS_NTSC => Some(SidClock::Ntsc),
+            _ => None
+        };
+
+        let extra_sid_count = [PSID_SECOND_SID_ADDRESS_OFFSET, PSID_THIRD_SID_ADDRESS_OFFSET].iter()
+            .take((version as usize).saturating_sub(2).min(2))
+            .take_while(|&&offset| is_valid_sid_address(header[offset]))
+            .count();
+        let sid_count = 1 + extra_sid_count as i32;
+
+        // an extra SID without a model of its own has the model of the first SID
+        let first_sid_model = get_sid_model(flags, FLAGS_SID_MODEL_SHIFTS[0]);
+        let sid_models = FLAGS_SID_MODEL_SHIFTS[..sid_count as usize].iter()
+            .map(|&shift| get_sid_model(flags, shift).or(first_sid_model))
+            .collect();
+
+        Some(PsidHeader { tune_info, clock, sid_count, sid_models })
+    }
+}
+
+// the address is the middle byte of $Dxx0, only even values in $D420-$D7F0 and $DE00-$DFE0 are valid
+fn is_valid_sid_address(address: u8) -> bool {
+    address & 0x01 == 0 && ((0x42..=0x7e).contains(&address) || address >= 0xe0)
+}
+
+fn get_sid_model(flags: u16, shift: u16) -> Option<i32> {
+    match (flags >> shift) & 0x03 {
+        FLAGS_6581 => Some(SID_MODEL_6581),
+        FLAGS_8580 => Some(SID_MODEL_8580),
+        _ => None
+    }
+}
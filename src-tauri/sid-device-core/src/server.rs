@@ -0,0 +1,1026 @@
+// Copyright (C) 2021 - 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+//! A Network SID Device server, that plays the SID writes of its clients on a [`Player`].
+//!
+//! The application that embeds the server provides the settings, creates the player and is told
+//! what happens by a [`ServerHandler`]. The server runs until it's stopped with its [`ServerControl`]:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use std::thread;
+//!
+//! use sid_device_core::player::Player;
+//! use sid_device_core::server::{ServerControl, ServerHandler, ServerSettings, SidDeviceServer};
+//!
+//! struct Handler;
+//!
+//! impl ServerHandler for Handler {
+//!     type Command = ();
+//!
+//!     fn settings(&self) -> ServerSettings {
+//!         ServerSettings::default()
+//!     }
+//!
+//!     fn create_player(&self) -> Result<Player, String> {
+//!         Player::new(None)
+//!     }
+//!
+//!     fn apply_command(&self, _player: &mut Player, _command: ()) {}
+//! }
+//!
+//! let (_sender, receiver) = async_broadcast::broadcast(16);
+//! let control = ServerControl::new();
+//!
+//! let server_thread = thread::spawn({
+//!     let control = control.clone();
+//!     move || SidDeviceServer::new(Arc::new(Handler)).start(receiver, &control)
+//! });
+//!
+//! // ... clients play on port 6581 of this machine ...
+//!
+//! control.stop();
+//! let _ = server_thread.join();
+//! ```
+
+mod compression;
+mod connection_limiter;
+mod connection_stats;
+mod forwarder;
+mod latency_monitor;
+mod lazy_player;
+#[cfg(feature = "scripting")]
+mod register_script;
+mod register_transformer;
+mod session_store;
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, Shutdown, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use async_broadcast::Receiver;
+use parking_lot::Mutex;
+use socket2::{SockRef, TcpKeepalive};
+
+use compression::{COMPRESSION_MODE_LZ4, COMPRESSION_MODE_NONE, decompress};
+use connection_limiter::ConnectionLimiter;
+use connection_stats::{CONNECTION_STATS, ConnectionCounters};
+use forwarder::Forwarder;
+use latency_monitor::LatencyMonitor;
+use lazy_player::LazyPlayer;
+use register_transformer::RegisterTransformer;
+use session_store::SESSION_STORE;
+use crate::buffer_health::BUFFER_HEALTH;
+use crate::clock::CLOCK;
+use crate::log::log;
+use crate::player::Player;
+use crate::protocol::{negotiate_version, parse_command, Command, CommandFramer, CommandResponse, PROTOCOL_VERSION, PsidHeader, TuneInfo};
+use crate::sid_backend::SidRouter;
+
+pub use connection_stats::{ConnectionRate, ConnectionStatsMonitor};
+pub use register_transformer::RegisterTransform;
+
+pub const DEFAULT_PORT: u16 = 6581;
+
+const NUMBER_OF_DEVICES: u8 = 2;
+const SID_WRITE_SIZE: usize = 4;
+const PROBE_TIMEOUT_IN_MILLIS: u64 = 300;
+const WAKE_TIMEOUT_IN_MILLIS: u64 = 500;
+const MAX_UNPAIRED_CONNECTIONS: i32 = 4;
+const DEFERRED_RESPONSE_TIMEOUT_IN_MILLIS: u64 = 50;
+const RECEIVE_BUFFER_SIZE: usize = 256 * 1024;
+
+pub enum ServerError {
+    PortInUse { port: u16, owner: Option<String> },
+    SidDeviceRunning { port: u16, protocol_version: u8 },
+    Other(String)
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerError::PortInUse { port, owner: Some(owner) } => write!(f, "Port {} is already in use by {}.", port, owner),
+            ServerError::PortInUse { port, owner: None } => write!(f, "Another SID device seems to be already running on port {}.", port),
+            ServerError::SidDeviceRunning { port, protocol_version } =>
+                write!(f, "A JSIDDevice compatible SID device (protocol version {}) is already running on port {}.", protocol_version, port),
+            ServerError::Other(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+/// The settings of the server, they are read again for every connection and command.
+#[derive(Copy, Clone)]
+pub struct ServerSettings {
+    pub host: IpAddr,
+    pub port: u16,
+    pub forward_address: Option<SocketAddr>,
+    pub forward_render_locally: bool,
+    pub tcp_nodelay_enabled: bool,
+    pub keepalive_enabled: bool,
+    pub keepalive_interval_in_sec: u32,
+    pub deferred_responses_enabled: bool,
+    /// The lower bound of the buffer target of every connection, in cycles.
+    pub min_buffer_target: u32
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_PORT,
+            forward_address: None,
+            forward_render_locally: false,
+            tcp_nodelay_enabled: false,
+            keepalive_enabled: false,
+            keepalive_interval_in_sec: 30,
+            deferred_responses_enabled: false,
+            min_buffer_target: 0
+        }
+    }
+}
+
+/// What happens on the server, for the application to show or pass on.
+pub enum ServerEvent {
+    ClientConnected(SocketAddr),
+    ClientDisconnected(SocketAddr),
+    TuneChanged { address: Option<IpAddr>, tune_info: TuneInfo },
+    AudioError
+}
+
+/// Connects the server to the application that embeds it.
+///
+/// The server calls the handler from the threads of its connections.
+pub trait ServerHandler: Send + Sync + 'static {
+    /// A change of the settings, that is sent to every connection with the receiver of the server.
+    type Command: Clone + Send + 'static;
+
+    fn settings(&self) -> ServerSettings;
+
+    /// Creates the player of a connection, it's only created when a client starts to play.
+    fn create_player(&self) -> Result<Player, String>;
+
+    fn apply_command(&self, player: &mut Player, command: Self::Command);
+
+    /// The transforms for the writes of a new connection.
+    fn register_transforms(&self) -> Vec<RegisterTransform> {
+        Vec::new()
+    }
+
+    /// The source of a [Rhai](https://rhai.rs) script for the writes of a new connection, which runs after the
+    /// transforms. It has a `transform(sid, reg, value)` function that returns `()` to keep the write, a value
+    /// to write instead, or an array of `[sid, reg, value]` writes. Scripts need the `scripting` feature.
+    fn register_script(&self) -> Option<String> {
+        None
+    }
+
+    /// Remote hosts that are not paired can only get the version and configuration until they sent the right PIN,
+    /// without pairing only clients on this machine can play.
+    fn is_paired_host(&self, _address: IpAddr) -> bool {
+        false
+    }
+
+    /// Asks to show a PIN for a remote host that is not paired.
+    fn request_pin(&self, _address: IpAddr) {}
+
+    /// Returns true when the PIN is right, the host is remembered as paired by the handler.
+    fn verify_pin(&self, _address: IpAddr, _pin: u16) -> bool {
+        false
+    }
+
+    fn notify(&self, _event: ServerEvent) {}
+
+    /// Called with the writes of a client as they are received, 4 bytes per write.
+    fn record_writes(&self, _client_address: SocketAddr, _data: &[u8]) {}
+
+    fn record_delay(&self, _client_address: SocketAddr, _sid_number: u8, _cycles: u16) {}
+
+    /// The name of the process that uses the port, to explain why the server can't start.
+    fn find_port_owner(&self, _port: u16) -> Option<String> {
+        None
+    }
+}
+
+/// Reports whether the server is ready and stops it, from any thread.
+#[derive(Clone, Default)]
+pub struct ServerControl {
+    ready: Arc<AtomicBool>,
+    quit: Arc<AtomicBool>,
+    listen_address: Arc<Mutex<Option<SocketAddr>>>
+}
+
+impl ServerControl {
+    pub fn new() -> ServerControl {
+        ServerControl::default()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::SeqCst);
+    }
+
+    pub fn stop(&self) {
+        self.quit.store(true, Ordering::SeqCst);
+
+        // the server blocks on accepting connections, connecting to it makes it see the quit request
+        if let Some(address) = self.get_local_address() {
+            let _ = TcpStream::connect_timeout(&address, Duration::from_millis(WAKE_TIMEOUT_IN_MILLIS));
+        }
+    }
+
+    /// Clears a stop request, so the server can be started again.
+    pub fn reset(&self) {
+        self.quit.store(false, Ordering::SeqCst);
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.quit.load(Ordering::SeqCst)
+    }
+
+    /// The address to connect to the running server from this machine.
+    pub fn get_local_address(&self) -> Option<SocketAddr> {
+        let mut address = (*self.listen_address.lock())?;
+        if address.ip().is_unspecified() {
+            address.set_ip(match address.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST)
+            });
+        }
+        Some(address)
+    }
+}
+
+pub struct SidDeviceServer<H: ServerHandler> {
+    handler: Arc<H>,
+    unpaired_connection_count: Arc<AtomicI32>,
+    connection_limiter: ConnectionLimiter
+}
+
+impl<H: ServerHandler> SidDeviceServer<H> {
+    pub fn new(handler: Arc<H>) -> SidDeviceServer<H> {
+        SidDeviceServer {
+            handler,
+            unpaired_connection_count: Arc::new(AtomicI32::new(0)),
+            connection_limiter: ConnectionLimiter::new()
+        }
+    }
+
+    /// Runs the server until it's stopped, the settings changes of the receiver are applied to every connection.
+    pub fn start(&mut self, receiver: Receiver<H::Command>, control: &ServerControl) -> Result<(), ServerError> {
+        let settings = self.handler.settings();
+        let (host, port) = (settings.host, settings.port);
+
+        if let Some(protocol_version) = probe_sid_device(port) {
+            return Err(ServerError::SidDeviceRunning { port, protocol_version });
+        }
+
+        let listener = TcpListener::bind((host, port));
+        if let Err(error) = listener {
+            return Err(
+                if error.kind() == ErrorKind::AddrInUse || error.kind() == ErrorKind::PermissionDenied {
+                    ServerError::PortInUse { port, owner: self.handler.find_port_owner(port) }
+                } else {
+                    ServerError::Other(error.to_string())
+                }
+            );
+        }
+
+        let listener = listener.unwrap();
+
+        let local_address = listener.local_addr().ok();
+        if let Some(address) = local_address {
+            log!("Listening on: {}\r", address);
+        }
+
+        *control.listen_address.lock() = local_address;
+        control.set_ready(true);
+
+        let mut client_threads: Vec<JoinHandle<()>> = vec![];
+
+        // the accept blocks until a client connects, a quit request wakes it up by connecting as well
+        while !control.is_stopped() {
+            match listener.accept() {
+                Ok(_) if control.is_stopped() => break,
+                Ok((stream, address)) => {
+                    client_threads.retain(|client_thread| !client_thread.is_finished());
+
+                    let is_remote = !address.ip().is_loopback();
+                    let unpaired = is_remote && !self.handler.is_paired_host(address.ip());
+
+                    if is_remote && !self.connection_limiter.allow(address.ip()) {
+                        log!("Connection rate limit reached, rejecting client: {}\r", address);
+                        let _ = stream.shutdown(Shutdown::Both);
+                        continue;
+                    }
+
+                    if unpaired && self.unpaired_connection_count.load(Ordering::Relaxed) >= MAX_UNPAIRED_CONNECTIONS {
+                        log!("Too many unpaired connections, rejecting client: {}\r", address);
+                        let _ = stream.shutdown(Shutdown::Both);
+                        continue;
+                    }
+
+                    log!("New client connected: {}\r", address);
+                    self.handler.notify(ServerEvent::ClientConnected(address));
+
+                    let local_quit = control.quit.clone();
+                    let receiver_clone: Receiver<H::Command> = receiver.clone();
+                    let unpaired_connection_count = self.unpaired_connection_count.clone();
+                    let handler = self.handler.clone();
+
+                    if unpaired {
+                        unpaired_connection_count.fetch_add(1, Ordering::Relaxed);
+                    }
+
+                    client_threads.push(thread::spawn(move || {
+                        SidDeviceServerThread::new(handler.clone()).handle_client(stream, receiver_clone, local_quit);
+                        handler.notify(ServerEvent::ClientDisconnected(address));
+
+                        if unpaired {
+                            unpaired_connection_count.fetch_sub(1, Ordering::Relaxed);
+                        }
+                    }));
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    log!("ERROR: {}\r", e);
+                    break;
+                }
+            }
+        }
+
+        if control.is_stopped() {
+            log!("User interruption. Quitting...\r");
+        }
+        *control.listen_address.lock() = None;
+
+        // wait for connections to close
+        for client_thread in client_threads {
+            let _ = client_thread.join();
+        }
+        Ok(())
+    }
+}
+
+fn probe_sid_device(port: u16) -> Option<u8> {
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+    let timeout = Duration::from_millis(PROBE_TIMEOUT_IN_MILLIS);
+
+    let mut stream = TcpStream::connect_timeout(&address, timeout).ok()?;
+    stream.set_read_timeout(Some(timeout)).ok()?;
+    stream.write_all(&[Command::GetVersion as u8, 0, 0, 0]).ok()?;
+
+    let mut response = [0u8; 2];
+    stream.read_exact(&mut response).ok()?;
+    let _ = stream.shutdown(Shutdown::Both);
+
+    if response[0] == CommandResponse::Version as u8 {
+        Some(response[1])
+    } else {
+        None
+    }
+}
+
+// forwarding to the address the server listens on would forward every command to itself over and over,
+// binding a socket only works with an address of this machine
+fn is_own_address(address: SocketAddr, settings: &ServerSettings) -> bool {
+    let is_listening_address = settings.host.is_unspecified() || address.ip().is_unspecified() || address.ip() == settings.host;
+    address.port() == settings.port && is_listening_address && UdpSocket::bind((address.ip(), 0)).is_ok()
+}
+
+// the fade time is in milliseconds, as a 32-bit big endian value
+fn get_fade_time(data: &[u8]) -> i32 {
+    let millis = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    millis.min(i32::MAX as u32) as i32
+}
+
+struct SidDeviceServerThread<H: ServerHandler> {
+    player: LazyPlayer,
+    router: SidRouter,
+    transformer: RegisterTransformer,
+    writes: Vec<(u8, u8)>,
+    handler: Arc<H>,
+    stats: Option<Arc<ConnectionCounters>>,
+    peer_address: Option<SocketAddr>,
+    paired: bool,
+    protocol_version: u8,
+    compression_enabled: bool,
+    forwarder: Option<Forwarder>,
+    render_locally: bool,
+    latency_monitor: LatencyMonitor
+}
+
+impl<H: ServerHandler> SidDeviceServerThread<H> {
+    fn new(handler: Arc<H>) -> SidDeviceServerThread<H> {
+        SidDeviceServerThread {
+            player: LazyPlayer::new(),
+            router: SidRouter::new(),
+            transformer: RegisterTransformer::new(handler.register_transforms(), handler.register_script()),
+            writes: Vec::new(),
+            handler,
+            stats: None,
+            peer_address: None,
+            paired: false,
+            protocol_version: negotiate_version(&[]),
+            compression_enabled: false,
+            forwarder: None,
+            render_locally: true,
+            latency_monitor: LatencyMonitor::new()
+        }
+    }
+
+    fn handle_client(&mut self, stream: TcpStream, receiver: Receiver<H::Command>, quit: Arc<AtomicBool>) {
+        // a connection that can't be set up is closed, a misbehaving peer must not take down the server
+        let peer_address = match Self::prepare_stream(&stream) {
+            Ok(peer_address) => peer_address,
+            Err(error) => {
+                log!("ERROR: Cannot set up connection: {}\r", error);
+                let _ = stream.shutdown(Shutdown::Both);
+                return;
+            }
+        };
+        self.apply_socket_options(&stream);
+
+        let stats = CONNECTION_STATS.register(peer_address);
+        self.stats = Some(stats.clone());
+
+        self.peer_address = Some(peer_address);
+        self.paired = peer_address.ip().is_loopback() || self.handler.is_paired_host(peer_address.ip());
+
+        if !self.paired {
+            log!("Client {} is not paired, waiting for PIN\r", peer_address.ip());
+            self.handler.request_pin(peer_address.ip());
+        }
+
+        if let Some(session) = SESSION_STORE.take(peer_address.ip()) {
+            log!("Restoring session of client: {}\r", peer_address);
+            self.player.restore_session(session);
+        }
+
+        let settings = self.handler.settings();
+
+        if let Some(forward_address) = settings.forward_address {
+            if is_own_address(forward_address, &settings) {
+                log!("ERROR: Cannot forward to this SID device itself: {}\r", forward_address);
+                let _ = stream.shutdown(Shutdown::Both);
+                CONNECTION_STATS.unregister(&stats);
+                return;
+            }
+
+            match Forwarder::connect(forward_address) {
+                Ok(forwarder) => {
+                    log!("Forwarding client to: {}\r", forward_address);
+                    self.forwarder = Some(forwarder);
+                    self.render_locally = settings.forward_render_locally;
+                }
+                Err(error) => {
+                    log!("ERROR: Cannot connect to forward device {}: {}\r", forward_address, error);
+                    let _ = stream.shutdown(Shutdown::Both);
+                    CONNECTION_STATS.unregister(&stats);
+                    return;
+                }
+            }
+        }
+
+        self.process_client(stream, peer_address, receiver, quit);
+
+        CONNECTION_STATS.unregister(&stats);
+    }
+
+    fn prepare_stream(stream: &TcpStream) -> io::Result<SocketAddr> {
+        stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+        stream.set_write_timeout(Some(Duration::from_millis(100)))?;
+        stream.set_nonblocking(false)?;
+        stream.peer_addr()
+    }
+
+    fn apply_socket_options(&self, stream: &TcpStream) {
+        let settings = self.handler.settings();
+        let (tcp_nodelay_enabled, keepalive_enabled) = (settings.tcp_nodelay_enabled, settings.keepalive_enabled);
+        let keepalive_interval = Duration::from_secs(settings.keepalive_interval_in_sec as u64);
+
+        if let Err(error) = stream.set_nodelay(tcp_nodelay_enabled) {
+            log!("ERROR: Cannot change TCP_NODELAY: {}\r", error);
+        }
+
+        // large write batches of multi SID tunes arrive in fewer reads with a larger receive buffer
+        if let Err(error) = SockRef::from(stream).set_recv_buffer_size(RECEIVE_BUFFER_SIZE) {
+            log!("ERROR: Cannot change receive buffer size: {}\r", error);
+        }
+
+        if keepalive_enabled {
+            let keepalive = TcpKeepalive::new().with_time(keepalive_interval).with_interval(keepalive_interval);
+            if let Err(error) = SockRef::from(stream).set_tcp_keepalive(&keepalive) {
+                log!("ERROR: Cannot enable keepalive: {}\r", error);
+            }
+        }
+    }
+
+    fn process_client(&mut self, mut stream: TcpStream, peer_address: SocketAddr, mut receiver: Receiver<H::Command>, quit: Arc<AtomicBool>) {
+        let mut framer = CommandFramer::new();
+
+        loop {
+            if quit.load(Ordering::SeqCst) {
+                let _ = stream.shutdown(Shutdown::Both);
+                self.router.flush(&mut self.player);
+
+                // the client's SID setup survives the server restart, clients only send it at the start of a tune
+                SESSION_STORE.store(peer_address.ip(), self.player.get_session().clone());
+                break;
+            }
+
+            if let Ok(command) = receiver.try_recv() {
+                // a player that isn't started yet picks up the changed settings when it's created
+                if let Some(player) = self.player.get_mut() {
+                    self.handler.apply_command(player, command);
+                }
+            }
+
+            self.player.check_renderer();
+
+            match framer.read_from(&mut stream) {
+                Ok(0) => {
+                    log!("Client disconnected: {}\r", peer_address);
+                    let _ = stream.shutdown(Shutdown::Both);
+                    break;
+                }
+                Ok(_) => {
+                    while let Some(data) = framer.next_command() {
+                        if self.paired && self.forwarder.is_some() {
+                            if let Err(error) = self.forward_command(&mut stream, data) {
+                                log!("ERROR: Forwarding failed: {}\r", error);
+                                log!("Terminating connection for client: {}\r", peer_address);
+                                let _ = stream.shutdown(Shutdown::Both);
+                                return;
+                            }
+                        } else {
+                            self.latency_monitor.command_received();
+                            if let Err(error) = self.process_command(&mut stream, data) {
+                                log!("ERROR: {}, {:?}\r", error, error.kind());
+                                log!("Terminating connection for client: {}\r", peer_address);
+                                let _ = stream.shutdown(Shutdown::Both);
+                                return;
+                            }
+                            self.latency_monitor.response_sent();
+                            self.adjust_buffer_target();
+                        }
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => {
+                    continue;
+                }
+                Err(e) => {
+                    log!("ERROR: {}, {:?}\r", e, e.kind());
+                    log!("Terminating connection for client: {}\r", peer_address);
+                    let _ = stream.shutdown(Shutdown::Both);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn adjust_buffer_target(&mut self) {
+        // local clients have no network latency to compensate
+        let is_remote = self.peer_address.map_or(false, |address| !address.ip().is_loopback());
+        let latency_target = if is_remote { self.latency_monitor.get_buffer_target() } else { 0 };
+
+        let previous_target = self.player.get_min_cycles_to_drain();
+        self.player.set_min_cycles_to_drain(latency_target.max(self.handler.settings().min_buffer_target));
+        let target = self.player.get_min_cycles_to_drain();
+
+        if latency_target >= target && target >= previous_target * 2 {
+            log!("High latency detected ({} ms), raising buffer target to {} cycles\r", self.latency_monitor.get_round_trip().as_millis(), target);
+        }
+    }
+
+    fn forward_command(&mut self, stream: &mut TcpStream, data: &[u8]) -> io::Result<()> {
+        let response = self.forwarder.as_mut().unwrap().forward(data)?;
+        stream.write_all(response)?;
+
+        if self.render_locally && response[0] == CommandResponse::Ok as u8 {
+            self.monitor_command(data);
+        }
+        Ok(())
+    }
+
+    fn monitor_command(&mut self, data: &[u8]) {
+        let (command, sid_number, payload) = match parse_command(data) {
+            Ok(command) => command,
+            Err(_) => return
+        };
+        let data_length = payload.len();
+
+        // only the commands that affect the sound are replayed on the local player
+        match command {
+            Command::TryWrite if !self.compression_enabled && data_length % SID_WRITE_SIZE == 0 && self.start_player() => {
+                let _ = self.process_writes(payload);
+            }
+            Command::TryDelay if data_length >= 2 && self.start_player() => {
+                let cycles = ((data[4] as u16) << 8) + data[5] as u16;
+                self.record_delay(sid_number, cycles);
+                self.router.delay(&mut self.player, sid_number, cycles);
+            }
+            Command::TrySetCompression if data_length == 1 => {
+                self.compression_enabled = data[4] == COMPRESSION_MODE_LZ4;
+            }
+            Command::Flush => self.router.flush(&mut self.player),
+            Command::TryReset => self.router.reset(&mut self.player),
+            Command::TrySetSidCount if sid_number > 0 && sid_number <= 8 => self.player.set_sid_count(sid_number as i32),
+            Command::TrySetSidModel if data_length == 1 => self.player.set_model(((sid_number as i32) << 8) | data[4] as i32),
+            Command::TrySetClock if data_length == 1 => self.player.set_clock(data[4] as i32),
+            Command::TrySetSampling if data_length == 1 => self.player.set_sampling_method(data[4] as i32),
+            Command::SetPsidHeader => self.process_psid_header(payload),
+            Command::Mute if data_length == 2 => self.player.mute_voice(sid_number, data[4], data[5] != 0),
+            Command::SetDelay if data_length == 2 => self.player.set_startup_delay(((data[4] as u32) << 8) + data[5] as u32),
+            Command::SetFadeIn if data_length == 4 => self.player.fade_in(get_fade_time(&data[4..8])),
+            Command::SetFadeOut if data_length == 4 => self.player.fade_out(get_fade_time(&data[4..8])),
+            _ => {}
+        }
+    }
+
+    fn process_command(&mut self, stream: &mut TcpStream, data: &[u8]) -> io::Result<()> {
+        // only the data of the command itself is used, the flush command is sent without its data
+        let (command, sid_number, payload) = match parse_command(data) {
+            Ok(command) => command,
+            Err(error) => {
+                log!("ERROR: {}\r", error);
+                stream.write_all(&[CommandResponse::Error as u8])?;
+                stream.flush()?;
+                return Ok(());
+            }
+        };
+        let data_length = payload.len();
+
+        if !self.paired && !matches!(command, Command::GetVersion | Command::GetConfigCount | Command::GetConfigInfo | Command::TryPair) {
+            stream.write_all(&[CommandResponse::Error as u8])?;
+            return Ok(());
+        }
+
+        // older clients don't know the newer commands, a command byte of them is never taken for one
+        if command.get_min_protocol_version() > self.protocol_version {
+            log!("ERROR: Command {} needs protocol version {}, the client uses version {}.\r", data[0], command.get_min_protocol_version(), self.protocol_version);
+            stream.write_all(&[CommandResponse::Error as u8])?;
+            return Ok(());
+        }
+
+        match command {
+            Command::TryWrite => {
+                let write_data = if self.compression_enabled {
+                    decompress(payload).map(Cow::Owned)
+                } else {
+                    Some(Cow::Borrowed(payload))
+                };
+
+                if !self.start_player() {
+                    stream.shutdown(Shutdown::Both)?;
+                } else if self.player.has_error() {
+                    self.report_audio_error();
+                    stream.shutdown(Shutdown::Both)?;
+                } else if write_data.is_none() {
+                    log!("ERROR: TryWrite compressed write data is invalid.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                } else if write_data.as_ref().unwrap().len() % 4 != 0 {
+                    log!("ERROR: TryWrite write data size for write data.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                } else if self.wait_for_buffer_space() {
+                    let write_data = write_data.unwrap();
+                    if write_data.len() >= 4 {
+                        let _ = self.process_writes(&write_data);
+                    }
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    BUFFER_HEALTH.add_busy_response();
+                    stream.write_all(&[CommandResponse::Busy as u8])?;
+                }
+            }
+            Command::TryRead => {
+                if !self.start_player() {
+                    stream.shutdown(Shutdown::Both)?;
+                } else if self.player.has_error() {
+                    self.report_audio_error();
+                    stream.shutdown(Shutdown::Both)?;
+                } else if data_length < 3 || (data_length - 3) % 4 != 0 {
+                    log!("ERROR: TryRead missing read data.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                } else if self.wait_for_buffer_space() {
+                    let read_value = self.process_writes(payload);
+                    stream.write_all(&[CommandResponse::Read as u8, read_value])?;
+                } else {
+                    BUFFER_HEALTH.add_busy_response();
+                    stream.write_all(&[CommandResponse::Busy as u8])?;
+                }
+            }
+            Command::TryDelay => {
+                if !self.start_player() {
+                    stream.shutdown(Shutdown::Both)?;
+                } else if self.player.has_error() {
+                    self.report_audio_error();
+                    stream.shutdown(Shutdown::Both)?;
+                } else if data_length < 2 {
+                    log!("ERROR: TryDelay missing cycle data.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                } else if self.wait_for_buffer_space() {
+                    let cycles = ((payload[0] as u16) << 8) + payload[1] as u16;
+                    self.record_delay(sid_number, cycles);
+                    self.router.delay(&mut self.player, sid_number, cycles);
+
+                    if let Some(stats) = &self.stats {
+                        stats.add_cycles(cycles as u64);
+                    }
+                    if self.player.has_min_data_in_buffer() {
+                        self.player.start_draining();
+                    }
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    BUFFER_HEALTH.add_busy_response();
+                    stream.write_all(&[CommandResponse::Busy as u8])?;
+                }
+            }
+            Command::TryReset => {
+                if data_length == 1 {
+                    if self.wait_for_buffer_space() {
+                        self.router.reset(&mut self.player);
+                        stream.write_all(&[CommandResponse::Ok as u8])?;
+                    } else {
+                        BUFFER_HEALTH.add_busy_response();
+                        stream.write_all(&[CommandResponse::Busy as u8])?;
+                    }
+                } else {
+                    log!("ERROR: TryReset missing data for volume.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::GetVersion => {
+                self.protocol_version = negotiate_version(payload);
+                stream.write_all(&[CommandResponse::Version as u8, PROTOCOL_VERSION])?;
+            }
+            Command::GetBufferInfo => {
+                let round_trip_in_millis = self.latency_monitor.get_round_trip().as_millis().min(u16::MAX as u128) as u16;
+
+                let mut response = vec![CommandResponse::BufferInfo as u8];
+                response.extend_from_slice(&self.player.get_cycles_in_buffer().to_be_bytes());
+                response.extend_from_slice(&self.player.get_min_cycles_to_drain().to_be_bytes());
+                response.extend_from_slice(&round_trip_in_millis.to_be_bytes());
+                stream.write_all(response.as_slice())?;
+            }
+            Command::SetPsidHeader => {
+                self.process_psid_header(payload);
+                stream.write_all(&[CommandResponse::Ok as u8])?;
+            }
+            Command::TrySetCompression => {
+                // compression only pays off over slow links, local clients always stay uncompressed
+                let is_remote = self.peer_address.map_or(false, |address| !address.ip().is_loopback());
+
+                if data_length == 1 && payload[0] <= COMPRESSION_MODE_LZ4 && (is_remote || payload[0] == COMPRESSION_MODE_NONE) {
+                    self.compression_enabled = payload[0] == COMPRESSION_MODE_LZ4;
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    log!("ERROR: TrySetCompression unsupported compression mode.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::TryPair => {
+                if self.paired {
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else if let (2, Some(address)) = (data_length, self.peer_address.map(|address| address.ip())) {
+                    let pin = ((payload[0] as u16) << 8) + payload[1] as u16;
+
+                    if self.handler.verify_pin(address, pin) {
+                        self.paired = true;
+                        log!("Client {} paired\r", address);
+                        stream.write_all(&[CommandResponse::Ok as u8])?;
+                    } else {
+                        // a wrong guess closes the connection, so PINs can't be tried in a fast loop
+                        log!("ERROR: TryPair invalid PIN.\r");
+                        stream.write_all(&[CommandResponse::Error as u8])?;
+                        stream.flush()?;
+                        stream.shutdown(Shutdown::Both)?;
+                    }
+                } else {
+                    log!("ERROR: TryPair missing data for PIN.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::GetConfigCount => {
+                stream.write_all(&[CommandResponse::Count as u8, NUMBER_OF_DEVICES])?;
+            }
+            Command::GetConfigInfo => {
+                let mut response = vec![CommandResponse::Info as u8, sid_number & 0x01];
+                if sid_number == 0 {
+                    response.append(&mut b"reSID Device (6581)\0".to_vec());
+                } else {
+                    response.append(&mut b"reSID Device (8580)\0".to_vec());
+                }
+                stream.write_all(response.as_slice())?;
+            }
+            Command::Flush => {
+                self.router.flush(&mut self.player);
+                stream.write_all(&[CommandResponse::Ok as u8])?;
+            }
+            Command::TrySetSidCount => {
+                if sid_number > 0 && sid_number <= 8 {
+                    self.player.set_sid_count(sid_number as i32);
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    log!("ERROR: TrySetSidCount sid count should be in range 1..8.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::TrySetSidModel => {
+                if data_length == 1 {
+                    let sid_model = payload[0];
+                    self.player.set_model(((sid_number as i32) << 8) | sid_model as i32);
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    log!("ERROR: TrySetSidModel missing data for SID model.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::TrySetClock => {
+                if data_length == 1 {
+                    let sid_clock = payload[0];
+                    self.player.set_clock(sid_clock as i32);
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    log!("ERROR: TrySetClock missing data for clock.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::SetSidPosition => {
+                if data_length == 1 {
+                    let position = payload[0];
+                    self.player.set_position(((sid_number as i32) << 8) | position as i32);
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    log!("ERROR: SetSidPosition missing data for SID position.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::TrySetSampling => {
+                if data_length == 1 {
+                    let sampling_method = payload[0];
+                    self.player.set_sampling_method(sampling_method as i32);
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    log!("ERROR: TrySetSampling missing data for sampling method.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::Mute => {
+                if data_length == 2 {
+                    self.player.mute_voice(sid_number, payload[0], payload[1] != 0);
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    log!("ERROR: Mute missing data for voice.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::SetDelay => {
+                if data_length == 2 {
+                    let cycles = ((payload[0] as u32) << 8) + payload[1] as u32;
+                    self.player.set_startup_delay(cycles);
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    log!("ERROR: SetDelay missing data for delay.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            Command::SetFadeIn | Command::SetFadeOut => {
+                if data_length == 4 {
+                    let millis = get_fade_time(&payload[..4]);
+                    if matches!(command, Command::SetFadeIn) {
+                        self.player.fade_in(millis);
+                    } else {
+                        self.player.fade_out(millis);
+                    }
+                    stream.write_all(&[CommandResponse::Ok as u8])?;
+                } else {
+                    log!("ERROR: SetFadeIn/SetFadeOut missing data for fade time.\r");
+                    stream.write_all(&[CommandResponse::Error as u8])?;
+                }
+            }
+            _ => {
+                // return Ok for not implemented methods
+                stream.write_all(&[CommandResponse::Ok as u8])?;
+            }
+        }
+        stream.flush()?;
+        Ok(())
+    }
+
+    // the setup of the tune is applied before the client sends its own, which takes precedence
+    fn process_psid_header(&mut self, data: &[u8]) {
+        let header = match PsidHeader::parse(data) {
+            Some(header) => header,
+            None => return
+        };
+
+        if header.sid_count != self.player.get_session().get_sid_count() {
+            self.player.set_sid_count(header.sid_count);
+        }
+        for (sid_number, sid_model) in header.sid_models.iter().enumerate() {
+            if let Some(sid_model) = sid_model {
+                self.player.set_model(((sid_number as i32) << 8) | sid_model);
+            }
+        }
+        if let Some(clock) = header.clock {
+            self.player.set_clock(clock as i32);
+        }
+
+        self.handler.notify(ServerEvent::TuneChanged { address: self.peer_address.map(|address| address.ip()), tune_info: header.tune_info });
+    }
+
+    fn record_delay(&self, sid_number: u8, cycles: u16) {
+        if let Some(address) = self.peer_address {
+            self.handler.record_delay(address, sid_number, cycles);
+        }
+    }
+
+    fn report_audio_error(&self) {
+        log!("ERROR: Audio error occurred.\r");
+        self.handler.notify(ServerEvent::AudioError);
+    }
+
+    fn start_player(&mut self) -> bool {
+        match self.player.start(|| self.handler.create_player()) {
+            Ok(()) => true,
+            Err(error) => {
+                let address = self.peer_address.map_or_else(|| "unknown".to_string(), |address| address.to_string());
+                log!("ERROR: Cannot play for client {}: {}\r", address, error);
+                false
+            }
+        }
+    }
+
+    // with deferred responses a full buffer delays the response, so naive clients don't have to retry on busy
+    fn wait_for_buffer_space(&mut self) -> bool {
+        if !self.player.has_max_data_in_buffer() {
+            return true;
+        }
+
+        if !self.handler.settings().deferred_responses_enabled {
+            return false;
+        }
+
+        let deadline = CLOCK.now() + Duration::from_millis(DEFERRED_RESPONSE_TIMEOUT_IN_MILLIS);
+        while CLOCK.now() < deadline && !self.player.has_error() {
+            CLOCK.sleep(Duration::from_millis(1));
+
+            if !self.player.has_max_data_in_buffer() {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn process_writes(&mut self, data: &[u8]) -> u8 {
+        let number_of_sid_writes = data.len() / SID_WRITE_SIZE;
+        let write_data_length = number_of_sid_writes * SID_WRITE_SIZE;
+        let mut total_cycles = 0u64;
+
+        if let Some(address) = self.peer_address {
+            self.handler.record_writes(address, &data[..write_data_length]);
+        }
+
+        for n in (0..write_data_length).step_by(SID_WRITE_SIZE) {
+            let cycles = ((data[n] as u16) << 8) + data[n + 1] as u16;
+            let reg = data[n + 2];
+            let val = data[n + 3];
+            total_cycles += cycles as u64;
+
+            if !self.transformer.is_active() {
+                self.router.write(&mut self.player, reg, val, cycles);
+                continue;
+            }
+
+            // only the first write of a transformed write takes the cycles
+            self.writes.clear();
+            self.transformer.transform(reg, val, &mut self.writes);
+            for (index, &(reg, val)) in self.writes.iter().enumerate() {
+                self.router.write(&mut self.player, reg, val, if index == 0 { cycles } else { 0 });
+            }
+        }
+
+        if let Some(stats) = &self.stats {
+            stats.add_writes(number_of_sid_writes as u64, total_cycles);
+        }
+
+        if self.player.has_min_data_in_buffer() {
+            self.player.start_draining();
+        }
+
+        if data.len() == write_data_length + 3 {
+            let cycles = ((data[write_data_length] as u16) << 8) + data[write_data_length + 1] as u16;
+            let reg = data[write_data_length + 2];
+            self.router.read(&mut self.player, reg, cycles)
+        } else {
+            0
+        }
+    }
+}
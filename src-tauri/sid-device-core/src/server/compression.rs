@@ -0,0 +1,49 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+pub const COMPRESSION_MODE_NONE: u8 = 0;
+pub const COMPRESSION_MODE_LZ4: u8 = 1;
+
+const MAX_DECOMPRESSED_SIZE: usize = 256 * 1024;
+
+// the size prefix comes from the client, so it's limited before anything is allocated
+pub fn decompress(data: &[u8]) -> Option<Vec<u8>> {
+    let (size, compressed_data) = lz4_flex::block::uncompressed_size(data).ok()?;
+    if size > MAX_DECOMPRESSED_SIZE {
+        return None;
+    }
+    lz4_flex::decompress(compressed_data, size).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompress_roundtrip() {
+        let writes: Vec<u8> = (0..64u8).flat_map(|n| [0, 0x08, n % 0x19, n]).collect();
+        assert_eq!(decompress(&lz4_flex::compress_prepend_size(&writes)), Some(writes));
+    }
+
+    #[test]
+    fn decompress_rejects_missing_size_prefix() {
+        assert_eq!(decompress(&[]), None);
+        assert_eq!(decompress(&[0x10, 0, 0]), None);
+    }
+
+    #[test]
+    fn decompress_rejects_oversized_size_prefix() {
+        let mut data = ((MAX_DECOMPRESSED_SIZE + 1) as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(&[0x1f, 0]);
+        assert_eq!(decompress(&data), None);
+
+        assert_eq!(decompress(&[0xff, 0xff, 0xff, 0xff, 0x1f, 0]), None);
+    }
+
+    #[test]
+    fn decompress_rejects_size_prefix_that_does_not_match_data() {
+        let mut data = lz4_flex::compress_prepend_size(&[1, 2, 3, 4]);
+        data[0] = 8;
+        assert_eq!(decompress(&data), None);
+    }
+}
@@ -0,0 +1,41 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+const MAX_CONNECTIONS_PER_WINDOW: usize = 10;
+const CONNECTION_WINDOW_IN_SEC: u64 = 10;
+
+pub struct ConnectionLimiter {
+    attempts: HashMap<IpAddr, VecDeque<Instant>>
+}
+
+impl ConnectionLimiter {
+    pub fn new() -> ConnectionLimiter {
+        ConnectionLimiter {
+            attempts: HashMap::new()
+        }
+    }
+
+    pub fn allow(&mut self, address: IpAddr) -> bool {
+        let now = Instant::now();
+        let window = Duration::from_secs(CONNECTION_WINDOW_IN_SEC);
+
+        // forget hosts that have been quiet for a whole window so scans don't grow the map forever
+        self.attempts.retain(|_, attempts| {
+            while attempts.front().map_or(false, |attempt| now.duration_since(*attempt) > window) {
+                attempts.pop_front();
+            }
+            !attempts.is_empty()
+        });
+
+        let attempts = self.attempts.entry(address).or_insert_with(VecDeque::new);
+        if attempts.len() >= MAX_CONNECTIONS_PER_WINDOW {
+            return false;
+        }
+        attempts.push_back(now);
+        true
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use parking_lot::{const_mutex, Mutex};
+
+use crate::clock::CLOCK;
+
+pub static CONNECTION_STATS: ConnectionStats = ConnectionStats::new();
+
+pub struct ConnectionStats {
+    connections: Mutex<Vec<Arc<ConnectionCounters>>>
+}
+
+// only statistics, relaxed ordering is enough
+pub struct ConnectionCounters {
+    address: SocketAddr,
+    writes: AtomicU64,
+    cycles: AtomicU64
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ConnectionRate {
+    address: String,
+    writes_per_sec: f32,
+    cycles_per_sec: f32
+}
+
+impl ConnectionStats {
+    const fn new() -> ConnectionStats {
+        ConnectionStats {
+            connections: const_mutex(Vec::new())
+        }
+    }
+
+    pub fn register(&self, address: SocketAddr) -> Arc<ConnectionCounters> {
+        let counters = Arc::new(ConnectionCounters {
+            address,
+            writes: AtomicU64::new(0),
+            cycles: AtomicU64::new(0)
+        });
+
+        self.connections.lock().push(counters.clone());
+        counters
+    }
+
+    pub fn unregister(&self, counters: &Arc<ConnectionCounters>) {
+        self.connections.lock().retain(|connection| !Arc::ptr_eq(connection, counters));
+    }
+}
+
+impl ConnectionCounters {
+    pub fn add_writes(&self, writes: u64, cycles: u64) {
+        self.writes.fetch_add(writes, Ordering::Relaxed);
+        self.cycles.fetch_add(cycles, Ordering::Relaxed);
+    }
+
+    pub fn add_cycles(&self, cycles: u64) {
+        self.cycles.fetch_add(cycles, Ordering::Relaxed);
+    }
+}
+
+pub struct ConnectionStatsMonitor {
+    last_counts: Vec<(Arc<ConnectionCounters>, u64, u64)>,
+    last_snapshot: Instant
+}
+
+impl ConnectionStatsMonitor {
+    pub fn new() -> ConnectionStatsMonitor {
+        ConnectionStatsMonitor {
+            last_counts: vec![],
+            last_snapshot: CLOCK.now()
+        }
+    }
+
+    pub fn take_snapshot(&mut self) -> Vec<ConnectionRate> {
+        let elapsed_in_sec = (CLOCK.now() - self.last_snapshot).as_secs_f32().max(f32::EPSILON);
+
+        let counts: Vec<(Arc<ConnectionCounters>, u64, u64)> = CONNECTION_STATS.connections.lock().iter()
+            .map(|counters| (counters.clone(), counters.writes.load(Ordering::Relaxed), counters.cycles.load(Ordering::Relaxed)))
+            .collect();
+
+        let rates = counts.iter().map(|(counters, writes, cycles)| {
+            let (last_writes, last_cycles) = self.last_counts.iter()
+                .find(|(last_counters, _, _)| Arc::ptr_eq(last_counters, counters))
+                .map_or((0, 0), |(_, last_writes, last_cycles)| (*last_writes, *last_cycles));
+
+            ConnectionRate {
+                address: counters.address.to_string(),
+                writes_per_sec: (writes - last_writes) as f32 / elapsed_in_sec,
+                cycles_per_sec: (cycles - last_cycles) as f32 / elapsed_in_sec
+            }
+        }).collect();
+
+        self.last_counts = counts;
+        self.last_snapshot = CLOCK.now();
+
+        rates
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::time::Duration;
+
+use crate::protocol::CommandResponse;
+
+const FORWARD_TIMEOUT_IN_MILLIS: u64 = 1000;
+const MAX_RESPONSE_SIZE: usize = 256;
+const BUFFER_INFO_SIZE: usize = 10;
+
+pub struct Forwarder {
+    stream: TcpStream,
+    response: Vec<u8>
+}
+
+impl Forwarder {
+    pub fn connect(address: SocketAddr) -> io::Result<Forwarder> {
+        let timeout = Duration::from_millis(FORWARD_TIMEOUT_IN_MILLIS);
+
+        let stream = TcpStream::connect_timeout(&address, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+
+        Ok(Forwarder {
+            stream,
+            response: Vec::with_capacity(MAX_RESPONSE_SIZE)
+        })
+    }
+
+    pub fn forward(&mut self, command: &[u8]) -> io::Result<&[u8]> {
+        self.stream.write_all(command)?;
+        self.read_response()?;
+        Ok(&self.response)
+    }
+
+    // a response can arrive in parts, so its length follows from the response code
+    fn read_response(&mut self) -> io::Result<()> {
+        self.response.clear();
+
+        let response = self.read_byte()?;
+        self.response.push(response);
+
+        match response {
+            code if code == CommandResponse::Ok as u8 || code == CommandResponse::Busy as u8 || code == CommandResponse::Error as u8 => {}
+            code if code == CommandResponse::Read as u8 || code == CommandResponse::Version as u8 || code == CommandResponse::Count as u8 => {
+                let value = self.read_byte()?;
+                self.response.push(value);
+            }
+            code if code == CommandResponse::BufferInfo as u8 => {
+                let mut buffer_info = [0u8; BUFFER_INFO_SIZE];
+                self.stream.read_exact(&mut buffer_info)?;
+                self.response.extend_from_slice(&buffer_info);
+            }
+            code if code == CommandResponse::Info as u8 => {
+                // the SID model is followed by a name that ends with a zero byte
+                let sid_model = self.read_byte()?;
+                self.response.push(sid_model);
+
+                loop {
+                    let character = self.read_byte()?;
+                    self.response.push(character);
+
+                    if character == 0 {
+                        break;
+                    }
+                    if self.response.len() >= MAX_RESPONSE_SIZE {
+                        return Err(io::Error::new(ErrorKind::InvalidData, "Forward device sent a too long device name"));
+                    }
+                }
+            }
+            code => return Err(io::Error::new(ErrorKind::InvalidData, format!("Forward device sent unknown response {}", code)))
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8; 1];
+        match self.stream.read_exact(&mut byte) {
+            Ok(()) => Ok(byte[0]),
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => {
+                Err(io::Error::new(ErrorKind::ConnectionAborted, "Forward device closed the connection"))
+            }
+            Err(error) => Err(error)
+        }
+    }
+}
+
+impl Drop for Forwarder {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::time::{Duration, Instant};
+
+const PAL_CYCLES_PER_MILLISECOND: u32 = 985;
+const HIGH_LATENCY_THRESHOLD_IN_MILLIS: u32 = 10;
+const MAX_ROUND_TRIP_IN_MILLIS: u128 = 1000;
+const ROUND_TRIPS_TO_BUFFER: u32 = 20;
+const SMOOTHING_FACTOR: f64 = 0.1;
+
+pub struct LatencyMonitor {
+    last_response: Option<Instant>,
+    round_trip_in_millis: f64
+}
+
+impl LatencyMonitor {
+    pub fn new() -> LatencyMonitor {
+        LatencyMonitor {
+            last_response: None,
+            round_trip_in_millis: 0.0
+        }
+    }
+
+    pub fn command_received(&mut self) {
+        if let Some(elapsed) = self.last_response.map(|last_response| last_response.elapsed()) {
+            // longer gaps mean the client was idle rather than waiting for the network
+            if elapsed.as_millis() < MAX_ROUND_TRIP_IN_MILLIS {
+                let elapsed = elapsed.as_secs_f64() * 1000.0;
+                self.round_trip_in_millis += (elapsed - self.round_trip_in_millis) * SMOOTHING_FACTOR;
+            }
+        }
+    }
+
+    pub fn response_sent(&mut self) {
+        self.last_response = Some(Instant::now());
+    }
+
+    pub fn get_round_trip(&self) -> Duration {
+        Duration::from_secs_f64(self.round_trip_in_millis / 1000.0)
+    }
+
+    pub fn get_buffer_target(&self) -> u32 {
+        let round_trip_in_millis = self.round_trip_in_millis as u32;
+
+        if round_trip_in_millis < HIGH_LATENCY_THRESHOLD_IN_MILLIS {
+            0
+        } else {
+            round_trip_in_millis * ROUND_TRIPS_TO_BUFFER * PAL_CYCLES_PER_MILLISECOND
+        }
+    }
+}
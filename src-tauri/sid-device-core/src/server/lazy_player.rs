@@ -0,0 +1,199 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use super::session_store::SessionState;
+use crate::player::Player;
+use crate::sid_backend::SidBackend;
+
+// the player is only started on the first write or delay, so probing clients don't open the audio device,
+// the SID settings of the session are applied when the player starts
+pub struct LazyPlayer {
+    player: Option<Player>,
+    session: SessionState,
+    min_cycles_to_drain: Option<u32>,
+    fade_in_millis: Option<i32>,
+    startup_delay: Option<u32>
+}
+
+impl LazyPlayer {
+    pub fn new() -> LazyPlayer {
+        LazyPlayer {
+            player: None,
+            session: SessionState::default(),
+            min_cycles_to_drain: None,
+            fade_in_millis: None,
+            startup_delay: None
+        }
+    }
+
+    pub fn start(&mut self, create_player: impl FnOnce() -> Result<Player, String>) -> Result<(), String> {
+        if self.player.is_none() {
+            let mut player = create_player()?;
+            self.session.apply(&mut player);
+
+            if let Some(cycles) = self.min_cycles_to_drain {
+                player.set_min_cycles_to_drain(cycles);
+            }
+            if let Some(cycles) = self.startup_delay {
+                player.set_startup_delay(cycles);
+            }
+            if let Some(millis) = self.fade_in_millis.take() {
+                player.fade_in(millis);
+            }
+            self.player = Some(player);
+        }
+        Ok(())
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut Player> {
+        self.player.as_mut()
+    }
+
+    pub fn has_error(&mut self) -> bool {
+        self.player.as_mut().map_or(false, |player| player.has_error())
+    }
+
+    pub fn check_renderer(&mut self) {
+        if let Some(player) = self.player.as_mut() {
+            player.check_renderer();
+        }
+    }
+
+    pub fn has_max_data_in_buffer(&mut self) -> bool {
+        self.player.as_mut().map_or(false, |player| player.has_max_data_in_buffer())
+    }
+
+    pub fn has_min_data_in_buffer(&mut self) -> bool {
+        self.player.as_mut().map_or(false, |player| player.has_min_data_in_buffer())
+    }
+
+    pub fn start_draining(&mut self) {
+        if let Some(player) = self.player.as_mut() {
+            player.start_draining();
+        }
+    }
+
+    pub fn set_min_cycles_to_drain(&mut self, cycles: u32) {
+        match self.player.as_mut() {
+            Some(player) => player.set_min_cycles_to_drain(cycles),
+            None => self.min_cycles_to_drain = Some(cycles)
+        }
+    }
+
+    pub fn get_min_cycles_to_drain(&self) -> u32 {
+        match self.player.as_ref() {
+            Some(player) => player.get_min_cycles_to_drain(),
+            None => self.min_cycles_to_drain.unwrap_or(0)
+        }
+    }
+
+    pub fn get_cycles_in_buffer(&self) -> u32 {
+        self.player.as_ref().map_or(0, |player| player.get_cycles_in_buffer())
+    }
+
+    pub fn set_startup_delay(&mut self, cycles: u32) {
+        match self.player.as_mut() {
+            Some(player) => player.set_startup_delay(cycles),
+            None => self.startup_delay = Some(cycles)
+        }
+    }
+
+    // a fade in is usually sent before the first write, so it's kept until the player starts
+    pub fn fade_in(&mut self, millis: i32) {
+        match self.player.as_mut() {
+            Some(player) => player.fade_in(millis),
+            None => self.fade_in_millis = Some(millis)
+        }
+    }
+
+    pub fn fade_out(&mut self, millis: i32) {
+        if let Some(player) = self.player.as_mut() {
+            player.fade_out(millis);
+        }
+    }
+
+    pub fn get_session(&self) -> &SessionState {
+        &self.session
+    }
+
+    // a restored session is applied when the player starts
+    pub fn restore_session(&mut self, session: SessionState) {
+        if let Some(player) = self.player.as_mut() {
+            session.apply(player);
+        }
+        self.session = session;
+    }
+
+    pub fn set_sid_count(&mut self, count: i32) {
+        self.session.set_sid_count(count);
+        if let Some(player) = self.player.as_mut() {
+            player.set_sid_count(count);
+        }
+    }
+
+    pub fn set_model(&mut self, model: i32) {
+        self.session.set_model(model);
+        if let Some(player) = self.player.as_mut() {
+            player.set_model(model);
+        }
+    }
+
+    pub fn mute_voice(&mut self, sid_number: u8, voice: u8, muted: bool) {
+        self.session.mute_voice(sid_number, voice, muted);
+        if let Some(player) = self.player.as_mut() {
+            player.mute_voice(sid_number, voice, muted);
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: i32) {
+        self.session.set_clock(clock);
+        if let Some(player) = self.player.as_mut() {
+            player.set_clock(clock);
+        }
+    }
+
+    pub fn set_position(&mut self, position: i32) {
+        self.session.set_position(position);
+        if let Some(player) = self.player.as_mut() {
+            player.set_position(position);
+        }
+    }
+
+    pub fn set_sampling_method(&mut self, sampling_method: i32) {
+        self.session.set_sampling_method(sampling_method);
+        if let Some(player) = self.player.as_mut() {
+            player.set_sampling_method(sampling_method);
+        }
+    }
+}
+
+// without a started player there is nothing to play, flush or reset
+impl SidBackend for LazyPlayer {
+    fn write(&mut self, reg: u8, data: u8, cycles: u16) {
+        if let Some(player) = self.player.as_mut() {
+            player.write(reg, data, cycles);
+        }
+    }
+
+    fn delay(&mut self, sid_number: u8, cycles: u16) {
+        if let Some(player) = self.player.as_mut() {
+            player.delay(sid_number, cycles);
+        }
+    }
+
+    fn read(&mut self, reg: u8, cycles: u16) -> u8 {
+        self.player.as_mut().map_or(0, |player| player.read(reg, cycles))
+    }
+
+    fn flush(&mut self) {
+        if let Some(player) = self.player.as_mut() {
+            player.flush();
+        }
+    }
+
+    fn reset(&mut self) {
+        if let Some(player) = self.player.as_mut() {
+            player.reset();
+        }
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+
+use crate::log::log;
+use crate::MAX_SID_COUNT;
+
+const TRANSFORM_FUNCTION: &str = "transform";
+const TRANSFORM_PARAM_COUNT: usize = 3;
+const SID_REGISTER_COUNT: i64 = 0x20;
+const VALUE_COUNT: i64 = 0x100;
+
+// the script runs for every write, a script that takes too long fails instead of stalling the playback
+const MAX_OPERATIONS: u64 = 10_000;
+
+/// A [Rhai](https://rhai.rs) script that changes the register writes of a client before they are played.
+///
+/// The script has a `transform(sid, reg, value)` function that is called for every write, it returns:
+/// - `()` to keep the write
+/// - a number to write that value instead
+/// - an array of `[sid, reg, value]` writes that replace the write, an empty array drops it
+///
+/// The function can keep state between writes in `this`, which starts as an empty object map:
+///
+/// ```rhai
+/// // play the second SID of a tune on the first SID
+/// fn transform(sid, reg, value) {
+///     if sid == 1 { [[0, reg, value]] }
+/// }
+/// ```
+///
+/// When the script fails, it's turned off and the writes of the client are played unchanged.
+pub struct RegisterScript {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Dynamic,
+    failed: bool
+}
+
+impl RegisterScript {
+    pub fn compile(script: &str) -> Result<RegisterScript, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let ast = engine.compile(script).map_err(|error| error.to_string())?;
+
+        let has_transform_function = ast.iter_functions()
+            .any(|function| function.name == TRANSFORM_FUNCTION && function.params.len() == TRANSFORM_PARAM_COUNT);
+        if !has_transform_function {
+            return Err(format!("The script has no {}(sid, reg, value) function", TRANSFORM_FUNCTION));
+        }
+
+        Ok(RegisterScript {
+            engine,
+            ast,
+            scope: Scope::new(),
+            state: Dynamic::from_map(Map::new()),
+            failed: false
+        })
+    }
+
+    pub fn transform(&mut self, reg: u8, value: u8, writes: &mut Vec<(u8, u8)>) {
+        if self.failed {
+            writes.push((reg, value));
+            return;
+        }
+
+        let args = ((reg >> 5) as i64, (reg & 0x1f) as i64, value as i64);
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        let result = self.engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, TRANSFORM_FUNCTION, args)
+            .map_err(|error| error.to_string())
+            .and_then(|result| to_register_writes(reg, value, result));
+
+        match result {
+            Ok(transformed_writes) => writes.extend(transformed_writes),
+            Err(error) => {
+                log!("ERROR: Register script failed, writes are played unchanged: {}\r", error);
+                self.failed = true;
+                writes.push((reg, value));
+            }
+        }
+    }
+}
+
+fn to_register_writes(reg: u8, value: u8, result: Dynamic) -> Result<Vec<(u8, u8)>, String> {
+    if result.is_unit() {
+        return Ok(vec![(reg, value)]);
+    }
+
+    if result.is::<Array>() {
+        return result.cast::<Array>().into_iter()
+            .map(to_register_write)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| "The writes have to be arrays of [sid, reg, value]".to_string());
+    }
+
+    to_byte(&result, VALUE_COUNT)
+        .map(|value| vec![(reg, value)])
+        .ok_or_else(|| format!("{} returned {}, which is not a write", TRANSFORM_FUNCTION, result))
+}
+
+fn to_register_write(write: Dynamic) -> Option<(u8, u8)> {
+    let write = write.try_cast::<Array>()?;
+
+    match write.as_slice() {
+        [sid, reg, value] => {
+            let sid = to_byte(sid, MAX_SID_COUNT as i64)?;
+            let reg = to_byte(reg, SID_REGISTER_COUNT)?;
+            Some((sid << 5 | reg, to_byte(value, VALUE_COUNT)?))
+        }
+        _ => None
+    }
+}
+
+fn to_byte(value: &Dynamic, count: i64) -> Option<u8> {
+    value.as_int().ok()
+        .filter(|value| (0..count).contains(value))
+        .map(|value| value as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(script: &mut RegisterScript, reg: u8, value: u8) -> Vec<(u8, u8)> {
+        let mut writes = Vec::new();
+        script.transform(reg, value, &mut writes);
+        writes
+    }
+
+    #[test]
+    fn compile_requires_transform_function() {
+        assert!(RegisterScript::compile("fn other(sid, reg, value) { }").is_err());
+        assert!(RegisterScript::compile("fn transform(").is_err());
+    }
+
+    #[test]
+    fn transform_keeps_replaces_and_drops_writes() {
+        let mut script = RegisterScript::compile(r#"
+            fn transform(sid, reg, value) {
+                if reg == 0x18 { return 0x0f; }
+                if reg == 0x04 { return []; }
+                if sid == 1 { return [[0, reg, value], [2, reg, value]]; }
+            }
+        "#).unwrap();
+
+        assert_eq!(transform(&mut script, 0x00, 0x12), vec![(0x00, 0x12)]);
+        assert_eq!(transform(&mut script, 0x18, 0x01), vec![(0x18, 0x0f)]);
+        assert!(transform(&mut script, 0x04, 0x41).is_empty());
+        assert_eq!(transform(&mut script, 0x21, 0x34), vec![(0x01, 0x34), (0x41, 0x34)]);
+    }
+
+    #[test]
+    fn transform_keeps_state_in_this() {
+        let mut script = RegisterScript::compile(r#"
+            fn transform(sid, reg, value) {
+                if this.count == () { this.count = 0; }
+                this.count += 1;
+                this.count
+            }
+        "#).unwrap();
+
+        assert_eq!(transform(&mut script, 0x00, 0), vec![(0x00, 1)]);
+        assert_eq!(transform(&mut script, 0x00, 0), vec![(0x00, 2)]);
+    }
+
+    #[test]
+    fn failing_script_passes_writes_unchanged() {
+        let mut script = RegisterScript::compile("fn transform(sid, reg, value) { [[8, reg, value]] }").unwrap();
+        assert_eq!(transform(&mut script, 0x01, 0x02), vec![(0x01, 0x02)]);
+
+        let mut script = RegisterScript::compile("fn transform(sid, reg, value) { loop { } }").unwrap();
+        assert_eq!(transform(&mut script, 0x01, 0x02), vec![(0x01, 0x02)]);
+        assert_eq!(transform(&mut script, 0x03, 0x04), vec![(0x03, 0x04)]);
+    }
+}
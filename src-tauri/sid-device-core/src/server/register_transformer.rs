@@ -0,0 +1,135 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use crate::log::log;
+use crate::MAX_SID_COUNT;
+#[cfg(feature = "scripting")]
+use super::register_script::RegisterScript;
+
+const VOICE_COUNT: usize = 3;
+const VOICE_REGISTER_COUNT: u8 = 7;
+const FREQUENCY_LOW_REGISTER: u8 = 0;
+const FREQUENCY_HIGH_REGISTER: u8 = 1;
+const CONTROL_REGISTER: u8 = 4;
+const WAVEFORM_MASK: u8 = 0xf0;
+
+// rules that change the register writes of clients before they are played
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RegisterTransform {
+    Transpose { semitones: i32 },
+    RemapSid { from: u8, to: u8 },
+    ForceWaveform { voice: u8, waveform: u8 }
+}
+
+// applies the register transforms of the config to the writes of a client, in the order of the config,
+// followed by the register script
+pub struct RegisterTransformer {
+    transforms: Vec<RegisterTransform>,
+    frequency_ratio: Option<f64>,
+    frequencies: [[u16; VOICE_COUNT]; MAX_SID_COUNT],
+    #[cfg(feature = "scripting")]
+    script: Option<RegisterScript>
+}
+
+impl RegisterTransformer {
+    pub fn new(transforms: Vec<RegisterTransform>, script: Option<String>) -> RegisterTransformer {
+        let semitones: i32 = transforms.iter().map(|transform| match transform {
+            RegisterTransform::Transpose { semitones } => *semitones,
+            _ => 0
+        }).sum();
+
+        #[cfg(not(feature = "scripting"))]
+        load_script(script);
+
+        RegisterTransformer {
+            transforms,
+            frequency_ratio: if semitones != 0 { Some(2f64.powf(semitones as f64 / 12.0)) } else { None },
+            frequencies: [[0; VOICE_COUNT]; MAX_SID_COUNT],
+            #[cfg(feature = "scripting")]
+            script: load_script(script)
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        #[cfg(feature = "scripting")]
+        if self.script.is_some() {
+            return true;
+        }
+        !self.transforms.is_empty()
+    }
+
+    pub fn transform(&mut self, reg: u8, value: u8, writes: &mut Vec<(u8, u8)>) {
+        #[cfg(feature = "scripting")]
+        if let Some(mut script) = self.script.take() {
+            let mut transformed_writes = Vec::new();
+            self.apply_transforms(reg, value, &mut transformed_writes);
+
+            for (reg, value) in transformed_writes {
+                script.transform(reg, value, writes);
+            }
+            self.script = Some(script);
+            return;
+        }
+
+        self.apply_transforms(reg, value, writes);
+    }
+
+    // a transposed frequency changes both frequency registers, so one write can become two
+    fn apply_transforms(&mut self, reg: u8, value: u8, writes: &mut Vec<(u8, u8)>) {
+        let mut sid_number = (reg >> 5) as usize;
+        let offset = reg & 0x1f;
+        let mut value = value;
+
+        for transform in self.transforms.iter() {
+            match *transform {
+                RegisterTransform::RemapSid { from, to } if sid_number == from as usize && (to as usize) < MAX_SID_COUNT => {
+                    sid_number = to as usize;
+                }
+                RegisterTransform::ForceWaveform { voice, waveform } if offset == voice * VOICE_REGISTER_COUNT + CONTROL_REGISTER => {
+                    value = (value & !WAVEFORM_MASK) | (waveform & WAVEFORM_MASK);
+                }
+                _ => {}
+            }
+        }
+
+        let sid_base = (sid_number as u8) << 5;
+        let voice = (offset / VOICE_REGISTER_COUNT) as usize;
+        let voice_register = offset % VOICE_REGISTER_COUNT;
+
+        match self.frequency_ratio {
+            Some(frequency_ratio) if sid_number < MAX_SID_COUNT && voice < VOICE_COUNT && voice_register <= FREQUENCY_HIGH_REGISTER => {
+                let frequency = &mut self.frequencies[sid_number][voice];
+                *frequency = if voice_register == FREQUENCY_LOW_REGISTER {
+                    (*frequency & 0xff00) | value as u16
+                } else {
+                    (*frequency & 0x00ff) | (value as u16) << 8
+                };
+
+                let transposed_frequency = (*frequency as f64 * frequency_ratio).round().min(u16::MAX as f64) as u16;
+                let voice_base = sid_base + voice as u8 * VOICE_REGISTER_COUNT;
+                writes.push((voice_base + FREQUENCY_LOW_REGISTER, transposed_frequency as u8));
+                writes.push((voice_base + FREQUENCY_HIGH_REGISTER, (transposed_frequency >> 8) as u8));
+            }
+            _ => writes.push((sid_base + offset, value))
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn load_script(script: Option<String>) -> Option<RegisterScript> {
+    match RegisterScript::compile(&script?) {
+        Ok(script) => Some(script),
+        Err(error) => {
+            log!("ERROR: Register script is not used: {}\r", error);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+fn load_script(script: Option<String>) {
+    if script.is_some() {
+        log!("ERROR: Register script is not used, SID Device is built without the scripting feature\r");
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use parking_lot::{const_mutex, Mutex};
+
+use crate::clock::CLOCK;
+use crate::player::Player;
+
+const SESSION_GRACE_PERIOD_IN_SEC: u64 = 30;
+
+pub static SESSION_STORE: SessionStore = SessionStore::new();
+
+// the SID setup a client sent, which is set once at the start of a tune and not repeated by clients
+#[derive(Clone, Default)]
+pub struct SessionState {
+    sid_count: Option<i32>,
+    models: Vec<i32>,
+    positions: Vec<i32>,
+    clock: Option<i32>,
+    sampling_method: Option<i32>,
+    muted_voices: Vec<(u8, u8)>
+}
+
+impl SessionState {
+    pub fn get_sid_count(&self) -> i32 {
+        self.sid_count.unwrap_or(1)
+    }
+
+    pub fn set_sid_count(&mut self, count: i32) {
+        self.sid_count = Some(count);
+    }
+
+    // the SID number is in the upper byte of the model and the position
+    pub fn set_model(&mut self, model: i32) {
+        Self::set_sid_value(&mut self.models, model);
+    }
+
+    pub fn set_position(&mut self, position: i32) {
+        Self::set_sid_value(&mut self.positions, position);
+    }
+
+    pub fn mute_voice(&mut self, sid_number: u8, voice: u8, muted: bool) {
+        self.muted_voices.retain(|&muted_voice| muted_voice != (sid_number, voice));
+        if muted {
+            self.muted_voices.push((sid_number, voice));
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: i32) {
+        self.clock = Some(clock);
+    }
+
+    pub fn set_sampling_method(&mut self, sampling_method: i32) {
+        self.sampling_method = Some(sampling_method);
+    }
+
+    pub fn apply(&self, player: &mut Player) {
+        if let Some(count) = self.sid_count {
+            player.set_sid_count(count);
+        }
+        for &model in self.models.iter() {
+            player.set_model(model);
+        }
+        for &position in self.positions.iter() {
+            player.set_position(position);
+        }
+        if let Some(clock) = self.clock {
+            player.set_clock(clock);
+        }
+        if let Some(sampling_method) = self.sampling_method {
+            player.set_sampling_method(sampling_method);
+        }
+        for &(sid_number, voice) in self.muted_voices.iter() {
+            player.mute_voice(sid_number, voice, true);
+        }
+    }
+
+    fn set_sid_value(values: &mut Vec<i32>, value: i32) {
+        values.retain(|&existing_value| existing_value >> 8 != value >> 8);
+        values.push(value);
+    }
+}
+
+// keeps the sessions of the connections that were closed by a server restart, until the client reconnects
+pub struct SessionStore {
+    sessions: Mutex<Vec<(IpAddr, SessionState, Instant)>>
+}
+
+impl SessionStore {
+    const fn new() -> SessionStore {
+        SessionStore {
+            sessions: const_mutex(Vec::new())
+        }
+    }
+
+    pub fn store(&self, address: IpAddr, session: SessionState) {
+        let mut sessions = self.sessions.lock();
+        sessions.retain(|(session_address, _, _)| *session_address != address);
+        sessions.push((address, session, CLOCK.now()));
+    }
+
+    pub fn take(&self, address: IpAddr) -> Option<SessionState> {
+        let now = CLOCK.now();
+        let grace_period = Duration::from_secs(SESSION_GRACE_PERIOD_IN_SEC);
+
+        let mut sessions = self.sessions.lock();
+        sessions.retain(|(_, _, stored_at)| now.saturating_duration_since(*stored_at) < grace_period);
+
+        let index = sessions.iter().position(|(session_address, _, _)| *session_address == address)?;
+        Some(sessions.remove(index).1)
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use crate::MAX_SID_COUNT;
+
+pub trait SidBackend {
+    fn write(&mut self, reg: u8, data: u8, cycles: u16);
+    fn delay(&mut self, sid_number: u8, cycles: u16);
+    fn read(&mut self, reg: u8, cycles: u16) -> u8;
+    fn flush(&mut self);
+    fn reset(&mut self);
+}
+
+pub struct SidRouter {
+    backends: Vec<Box<dyn SidBackend>>,
+    routes: [Option<usize>; MAX_SID_COUNT]
+}
+
+impl SidRouter {
+    pub fn new() -> SidRouter {
+        SidRouter {
+            backends: Vec::new(),
+            routes: [None; MAX_SID_COUNT]
+        }
+    }
+
+    pub fn add_backend(&mut self, backend: Box<dyn SidBackend>, sid_numbers: &[usize]) {
+        self.backends.push(backend);
+
+        for &sid_number in sid_numbers.iter().filter(|&&sid_number| sid_number < MAX_SID_COUNT) {
+            self.routes[sid_number] = Some(self.backends.len() - 1);
+        }
+    }
+
+    // SIDs without a route are played by the emulated backend; every other backend receives a delay
+    // for the same cycles so all backends stay in sync
+    pub fn write(&mut self, emulated: &mut dyn SidBackend, reg: u8, data: u8, cycles: u16) {
+        let sid_number = Self::get_sid_number(reg);
+        let route = self.routes[sid_number as usize];
+
+        for (index, backend) in self.backends.iter_mut().enumerate() {
+            if route == Some(index) {
+                backend.write(reg, data, cycles);
+            } else {
+                backend.delay(sid_number, cycles);
+            }
+        }
+
+        if route.is_none() {
+            emulated.write(reg, data, cycles);
+        } else {
+            emulated.delay(sid_number, cycles);
+        }
+    }
+
+    pub fn delay(&mut self, emulated: &mut dyn SidBackend, sid_number: u8, cycles: u16) {
+        for backend in self.backends.iter_mut() {
+            backend.delay(sid_number, cycles);
+        }
+        emulated.delay(sid_number, cycles);
+    }
+
+    pub fn read(&mut self, emulated: &mut dyn SidBackend, reg: u8, cycles: u16) -> u8 {
+        let sid_number = Self::get_sid_number(reg);
+        let route = self.routes[sid_number as usize];
+        let mut value = None;
+
+        for (index, backend) in self.backends.iter_mut().enumerate() {
+            if route == Some(index) {
+                value = Some(backend.read(reg, cycles));
+            } else {
+                backend.delay(sid_number, cycles);
+            }
+        }
+
+        match value {
+            Some(value) => {
+                emulated.delay(sid_number, cycles);
+                value
+            }
+            None => emulated.read(reg, cycles)
+        }
+    }
+
+    pub fn flush(&mut self, emulated: &mut dyn SidBackend) {
+        self.backends.iter_mut().for_each(|backend| backend.flush());
+        emulated.flush();
+    }
+
+    pub fn reset(&mut self, emulated: &mut dyn SidBackend) {
+        self.backends.iter_mut().for_each(|backend| backend.reset());
+        emulated.reset();
+    }
+
+    fn get_sid_number(reg: u8) -> u8 {
+        (reg >> 5).min(MAX_SID_COUNT as u8 - 1)
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::any::Any;
+use std::thread::JoinHandle;
+
+use parking_lot::{const_mutex, Mutex};
+
+pub(crate) const MAX_WATCHDOG_RESTARTS: u32 = 3;
+
+pub static WATCHDOG: Watchdog = Watchdog::new();
+
+pub enum WatchdogEvent {
+    RendererRestarted(String),
+    AudioDeviceFailed(String),
+    AudioDeviceRemoved(String)
+}
+
+pub struct Watchdog {
+    events: Mutex<Vec<WatchdogEvent>>
+}
+
+impl Watchdog {
+    const fn new() -> Watchdog {
+        Watchdog {
+            events: const_mutex(Vec::new())
+        }
+    }
+
+    pub fn report(&self, event: WatchdogEvent) {
+        self.events.lock().push(event);
+    }
+
+    pub fn take_events(&self) -> Vec<WatchdogEvent> {
+        self.events.lock().drain(..).collect()
+    }
+}
+
+pub(crate) fn take_finished_thread(thread: &mut Option<JoinHandle<()>>) -> Option<String> {
+    if !thread.as_ref()?.is_finished() {
+        return None;
+    }
+
+    match thread.take().unwrap().join() {
+        Ok(()) => Some("thread exited".to_string()),
+        Err(payload) => Some(get_panic_message(payload))
+    }
+}
+
+fn get_panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
@@ -1,18 +1,52 @@
 // Copyright (C) 2022 Wilfred Bos
 // Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
 
+use std::net::{IpAddr, TcpListener};
 use std::sync::Arc;
-use std::sync::atomic::Ordering;
+use std::thread;
 
 use async_broadcast::Sender;
 use futures_lite::{future::block_on};
 use parking_lot::Mutex;
-use tauri::{AppHandle, command, State, Window, Wry};
+use sid_device_core::audio_host::AUDIO_HOST;
+use tauri::{AppHandle, ClipboardManager, command, State, Theme, Window, Wry};
+use tauri::api::dialog::message;
 
 use crate::device_state::DeviceState;
+use crate::i18n::I18n;
 use crate::{Config, Settings, SettingsCommand};
-use crate::toggle_launch_at_start;
-use crate::utils::audio;
+use crate::log;
+use crate::settings::{
+    MAX_AUDIO_LATENCY_IN_MILLIS,
+    MAX_DIGIBOOST_LEVEL,
+    MAX_FILTER_BIAS_6581,
+    MAX_KEEPALIVE_INTERVAL_IN_SEC,
+    MAX_MASTER_VOLUME,
+    MAX_MIXER_GAIN,
+    MAX_MIXER_PAN,
+    MAX_OUTPUT_CHANNELS,
+    MAX_PORT,
+    MAX_SID_COUNT,
+    MAX_SID_GAIN_TRIM,
+    MIN_AUDIO_LATENCY_IN_MILLIS,
+    MIN_FILTER_BIAS_6581,
+    MIN_KEEPALIVE_INTERVAL_IN_SEC,
+    MIN_MIXER_PAN,
+    MIN_PORT,
+    Mixer,
+    MixerChannel,
+    SettingsError,
+    SUPPORTED_SAMPLE_RATES,
+    ThemeSetting
+};
+use crate::sid_device_server::{self, run_self_test};
+use crate::{change_audio_device, change_master_volume, emit_theme, enable_digiboost, get_theme, toggle_launch_at_start, toggle_recording, update_system_tray_menu};
+use crate::utils::{audio, diagnostics, file_manager, network};
+use crate::utils::network::NetworkInterface;
+use crate::utils::log::LOG;
+use crate::utils::runtime_info::{self, AppStartTime, RuntimeInfo};
+
+const COPIED_LOG_LINES: usize = 200;
 
 #[derive(serde::Serialize)]
 pub struct DevicesResponse {
@@ -20,6 +54,12 @@ pub struct DevicesResponse {
     default_device: i32
 }
 
+#[derive(serde::Serialize)]
+pub struct AudioHostsResponse {
+    hosts: Vec<String>,
+    selected_host: i32
+}
+
 #[command]
 pub fn get_devices_cmd() -> DevicesResponse {
     let (devices, default_device) = audio::get_available_audio_output_device_names();
@@ -31,71 +71,506 @@ pub fn get_devices_cmd() -> DevicesResponse {
 }
 
 #[command]
-pub fn change_filter_bias_6581_cmd(filter_bias_6581: i32, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) {
+pub fn get_output_channel_count_cmd(settings: State<'_, Arc<Mutex<Settings>>>) -> u16 {
+    let audio_device_number = settings.lock().get_config().lock().audio_device_number;
+    audio::get_audio_output_channel_count(audio_device_number)
+}
+
+#[command]
+pub fn get_input_devices_cmd() -> Vec<String> {
+    audio::get_available_audio_input_device_names()
+}
+
+#[command]
+pub fn get_audio_hosts_cmd() -> AudioHostsResponse {
+    let (hosts, selected_host) = audio::get_available_audio_host_names();
+
+    AudioHostsResponse {
+        hosts,
+        selected_host
+    }
+}
+
+// the device numbers belong to a host, so the default devices of the new host are used
+#[command]
+pub fn change_audio_host_cmd(host_index: i32, app_handle: AppHandle<Wry>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    let (hosts, _) = audio::get_available_audio_host_names();
+    let host_name = usize::try_from(host_index).ok()
+        .and_then(|index| hosts.get(index).cloned())
+        .ok_or(SettingsError::AudioHostMissing { host_index })?;
+
+    AUDIO_HOST.set_host(Some(&host_name));
+    {
+        let config = settings.lock().get_config();
+        let mut config = config.lock();
+        config.audio_host = Some(host_name);
+        config.external_input_device_number = None;
+    }
+
+    change_audio_device(&app_handle, &settings, 0);
+    Ok(())
+}
+
+#[command]
+pub fn change_filter_bias_6581_cmd(filter_bias_6581: i32, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    check_range("filter_bias_6581", filter_bias_6581, MIN_FILTER_BIAS_6581, MAX_FILTER_BIAS_6581)?;
+
     block_on(async {
         settings.lock().get_config().lock().filter_bias_6581 = Some(filter_bias_6581);
 
         let _ = sender.broadcast((SettingsCommand::FilterBias6581, Some(filter_bias_6581))).await.unwrap();
         settings.lock().save_config();
     });
+    Ok(())
 }
 
 
 #[command]
-pub fn toggle_launch_at_start_cmd(app_handle: AppHandle<Wry>, settings: State<'_, Arc<Mutex<Settings>>>) {
-    toggle_launch_at_start(&app_handle.tray_handle(), &settings, "launch at startup");
+pub fn toggle_launch_at_start_cmd(app_handle: AppHandle<Wry>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    toggle_launch_at_start(&app_handle.tray_handle(), &settings, "launch at startup")
 }
 
 #[command]
-pub fn reset_to_default_cmd(window: Window<Wry>, device_state: State<'_, DeviceState>, settings: State<'_, Arc<Mutex<Settings>>>) {
+pub fn reset_to_default_cmd(app_handle: AppHandle<Wry>, window: Window<Wry>, device_state: State<'_, DeviceState>, settings: State<'_, Arc<Mutex<Settings>>>) {
     settings.lock().reset_config();
     device_state.reset();
+    update_system_tray_menu(&app_handle, &settings);
+    emit_theme(&app_handle, &settings);
 
     window.emit("update-settings", &*settings.lock().get_config().lock()).unwrap();
 }
 
 #[command]
-pub fn change_audio_device_cmd(device_index: i32, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) {
+pub fn change_audio_device_cmd(device_index: i32, app_handle: AppHandle<Wry>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    let device_count = audio::get_available_audio_output_devices().len() as i32;
+    if device_index < 0 || device_index > device_count {
+        return Err(SettingsError::DeviceMissing { device_index });
+    }
+
+    change_audio_device(&app_handle, &settings, device_index);
+    Ok(())
+}
+
+#[command]
+pub fn enable_digiboost_cmd(digi_boost_enabled: bool, app_handle: AppHandle<Wry>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    enable_digiboost(&app_handle, &settings, digi_boost_enabled);
+    Ok(())
+}
+
+#[command]
+pub fn allow_external_ip_cmd(external_ip_allowed: bool, device_state: State<'_, DeviceState>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    settings.lock().get_config().lock().allow_external_connections = external_ip_allowed;
+
+    device_state.server.set_ready(false);
+    device_state.reset();
+
+    settings.lock().save_config();
+    Ok(())
+}
+
+#[command]
+pub fn enable_pairing_cmd(pairing_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    settings.lock().get_config().lock().pairing_enabled = pairing_enabled;
+
+    settings.lock().save_config();
+    Ok(())
+}
+
+#[command]
+pub fn get_network_interfaces_cmd() -> Vec<NetworkInterface> {
+    network::get_network_interfaces()
+}
+
+#[command]
+pub fn set_bind_address_cmd(bind_address: Option<IpAddr>, device_state: State<'_, DeviceState>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    settings.lock().get_config().lock().bind_address = bind_address;
+
+    device_state.server.set_ready(false);
+    device_state.reset();
+
+    settings.lock().save_config();
+    Ok(())
+}
+
+// the port is checked before the server is restarted, so a busy port doesn't leave the device stopped
+#[command]
+pub fn set_port_cmd(port: i32, device_state: State<'_, DeviceState>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    check_range("port", port, MIN_PORT, MAX_PORT)?;
+    let port = port as u16;
+
+    let (current_port, host) = {
+        let config = settings.lock().get_config();
+        let config = config.lock();
+        (config.port, sid_device_server::get_host(&config))
+    };
+
+    if port == current_port {
+        return Ok(());
+    }
+
+    if TcpListener::bind((host, port)).is_err() {
+        return Err(SettingsError::PortBusy { port });
+    }
+
+    settings.lock().get_config().lock().port = port;
+
+    device_state.server.set_ready(false);
+    device_state.reset();
+
+    settings.lock().save_config();
+    Ok(())
+}
+
+#[command]
+pub fn change_socket_options_cmd(
+        tcp_nodelay_enabled: bool,
+        keepalive_enabled: bool,
+        keepalive_interval: i32,
+        settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    check_range("keepalive_interval", keepalive_interval, MIN_KEEPALIVE_INTERVAL_IN_SEC, MAX_KEEPALIVE_INTERVAL_IN_SEC)?;
+
+    {
+        let config = settings.lock().get_config();
+        let mut config = config.lock();
+        config.tcp_nodelay_enabled = tcp_nodelay_enabled;
+        config.keepalive_enabled = keepalive_enabled;
+        config.keepalive_interval_in_sec = keepalive_interval as u32;
+    }
+
+    settings.lock().save_config();
+    Ok(())
+}
+
+#[command]
+pub fn enable_audio_stream_cmd(audio_stream_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    settings.lock().get_config().lock().audio_stream_enabled = audio_stream_enabled;
+
+    settings.lock().save_config();
+    Ok(())
+}
+
+#[command]
+pub fn enable_deferred_responses_cmd(deferred_responses_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    settings.lock().get_config().lock().deferred_responses_enabled = deferred_responses_enabled;
+
+    settings.lock().save_config();
+    Ok(())
+}
+
+#[command]
+pub fn enable_low_power_mode_cmd(low_power_mode_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
     block_on(async {
-        let audio_device_number = if device_index < 1 {
-            None
+        settings.lock().get_config().lock().low_power_mode_enabled = low_power_mode_enabled;
+
+        let command = if low_power_mode_enabled {
+            SettingsCommand::EnableLowPowerMode
         } else {
-            Some(device_index - 1)
+            SettingsCommand::DisableLowPowerMode
         };
 
-        settings.lock().get_config().lock().audio_device_number = audio_device_number;
-        let _ = sender.broadcast((SettingsCommand::SetAudioDevice, audio_device_number)).await.unwrap();
+        let _ = sender.broadcast((command, None)).await.unwrap();
         settings.lock().save_config();
     });
+    Ok(())
 }
 
 #[command]
-pub fn enable_digiboost_cmd(digi_boost_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) {
+pub fn enable_low_latency_cmd(low_latency_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
     block_on(async {
-        settings.lock().get_config().lock().digiboost_enabled = digi_boost_enabled;
+        settings.lock().get_config().lock().low_latency_enabled = low_latency_enabled;
 
-        let command = if digi_boost_enabled {
-            SettingsCommand::EnableDigiboost
+        let command = if low_latency_enabled {
+            SettingsCommand::EnableLowLatency
         } else {
-            SettingsCommand::DisableDigiboost
+            SettingsCommand::DisableLowLatency
         };
 
         let _ = sender.broadcast((command, None)).await.unwrap();
         settings.lock().save_config();
     });
+    Ok(())
 }
 
+// without a latency the default buffer sizes are used
 #[command]
-pub fn allow_external_ip_cmd(external_ip_allowed: bool, device_state: State<'_, DeviceState>, settings: State<'_, Arc<Mutex<Settings>>>) {
-    settings.lock().get_config().lock().allow_external_connections = external_ip_allowed;
+pub fn change_audio_latency_cmd(audio_latency: Option<i32>, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    if let Some(audio_latency) = audio_latency {
+        check_range("audio_latency", audio_latency, MIN_AUDIO_LATENCY_IN_MILLIS, MAX_AUDIO_LATENCY_IN_MILLIS)?;
+    }
 
-    device_state.device_ready.store(false, Ordering::SeqCst);
-    device_state.reset();
+    block_on(async {
+        settings.lock().get_config().lock().audio_latency = audio_latency;
+
+        let _ = sender.broadcast((SettingsCommand::SetAudioLatency, audio_latency)).await.unwrap();
+        settings.lock().save_config();
+    });
+    Ok(())
+}
+
+// without a sample rate the default sample rate of the audio device is used
+#[command]
+pub fn change_sample_rate_cmd(sample_rate: Option<i32>, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    if let Some(sample_rate) = sample_rate.filter(|sample_rate| !SUPPORTED_SAMPLE_RATES.contains(sample_rate)) {
+        return Err(SettingsError::SampleRateNotSupported { sample_rate });
+    }
+
+    block_on(async {
+        settings.lock().get_config().lock().sample_rate = sample_rate;
+
+        let _ = sender.broadcast((SettingsCommand::SetSampleRate, sample_rate)).await.unwrap();
+        settings.lock().save_config();
+    });
+    Ok(())
+}
+
+#[command]
+pub fn enable_old_8580_filter_cmd(old_8580_filter_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    block_on(async {
+        settings.lock().get_config().lock().old_8580_filter_enabled = old_8580_filter_enabled;
+
+        let command = if old_8580_filter_enabled {
+            SettingsCommand::EnableOld8580Filter
+        } else {
+            SettingsCommand::DisableOld8580Filter
+        };
+
+        let _ = sender.broadcast((command, None)).await.unwrap();
+        settings.lock().save_config();
+    });
+    Ok(())
+}
+
+#[command]
+pub fn set_external_input_cmd(external_input_enabled: bool, input_device_number: Option<i32>, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    block_on(async {
+        {
+            let config = settings.lock().get_config();
+            let mut config = config.lock();
+            config.external_input_enabled = external_input_enabled;
+            config.external_input_device_number = input_device_number;
+        }
+
+        let _ = sender.broadcast((SettingsCommand::SetExternalInput, None)).await.unwrap();
+        settings.lock().save_config();
+    });
+    Ok(())
+}
+
+#[command]
+pub fn enable_loudness_normalization_cmd(loudness_normalization_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    block_on(async {
+        settings.lock().get_config().lock().loudness_normalization_enabled = loudness_normalization_enabled;
+
+        let command = if loudness_normalization_enabled {
+            SettingsCommand::EnableLoudnessNormalization
+        } else {
+            SettingsCommand::DisableLoudnessNormalization
+        };
+
+        let _ = sender.broadcast((command, None)).await.unwrap();
+        settings.lock().save_config();
+    });
+    Ok(())
+}
+
+#[command]
+pub fn enable_soft_clipping_cmd(soft_clipping_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    block_on(async {
+        settings.lock().get_config().lock().soft_clipping_enabled = soft_clipping_enabled;
+
+        let command = if soft_clipping_enabled {
+            SettingsCommand::EnableSoftClipping
+        } else {
+            SettingsCommand::DisableSoftClipping
+        };
+
+        let _ = sender.broadcast((command, None)).await.unwrap();
+        settings.lock().save_config();
+    });
+    Ok(())
+}
+
+#[command]
+pub fn enable_osc_cmd(osc_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    settings.lock().get_config().lock().osc_enabled = osc_enabled;
+
+    settings.lock().save_config();
+    Ok(())
+}
+
+#[command]
+pub fn enable_update_check_cmd(update_check_enabled: bool, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    settings.lock().get_config().lock().update_check_enabled = update_check_enabled;
 
     settings.lock().save_config();
+    Ok(())
+}
+
+#[command]
+pub fn change_digiboost_level_cmd(digiboost_level: i32, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    check_range("digiboost_level", digiboost_level, 0, MAX_DIGIBOOST_LEVEL)?;
+
+    block_on(async {
+        settings.lock().get_config().lock().digiboost_level = digiboost_level;
+
+        let _ = sender.broadcast((SettingsCommand::SetDigiboostLevel, Some(digiboost_level))).await.unwrap();
+        settings.lock().save_config();
+    });
+    Ok(())
+}
+
+#[command]
+pub fn change_master_volume_cmd(volume: i32, app_handle: AppHandle<Wry>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    check_range("master_volume", volume, 0, MAX_MASTER_VOLUME)?;
+
+    change_master_volume(&app_handle, &settings, volume);
+    Ok(())
+}
+
+#[command]
+pub fn change_mixer_channel_cmd(sid_number: i32, channel: MixerChannel, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    check_range("sid_number", sid_number, 0, MAX_SID_COUNT as i32 - 1)?;
+    check_range("gain", channel.gain, 0, MAX_MIXER_GAIN)?;
+    check_range("pan", channel.pan, MIN_MIXER_PAN, MAX_MIXER_PAN)?;
+
+    settings.lock().get_config().lock().mixer.channels[sid_number as usize] = channel;
+    apply_mixer(&settings, &sender);
+    Ok(())
+}
+
+#[command]
+pub fn change_output_channels_cmd(sid_number: i32, output_channels: [i32; 2], settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    check_range("sid_number", sid_number, 0, MAX_SID_COUNT as i32 - 1)?;
+    for output_channel in output_channels {
+        check_range("output_channel", output_channel, 0, MAX_OUTPUT_CHANNELS - 1)?;
+    }
+
+    block_on(async {
+        settings.lock().get_config().lock().output_channels[sid_number as usize] = output_channels;
+
+        let _ = sender.broadcast((SettingsCommand::SetOutputChannels, None)).await.unwrap();
+        settings.lock().save_config();
+    });
+    Ok(())
+}
+
+#[command]
+pub fn change_sid_gain_trim_cmd(sid_number: i32, gain_trim: i32, settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) -> Result<(), SettingsError> {
+    check_range("sid_number", sid_number, 0, MAX_SID_COUNT as i32 - 1)?;
+    check_range("gain_trim", gain_trim, 0, MAX_SID_GAIN_TRIM)?;
+
+    block_on(async {
+        settings.lock().get_config().lock().sid_gain_trims[sid_number as usize] = gain_trim;
+
+        let _ = sender.broadcast((SettingsCommand::SetSidGainTrims, None)).await.unwrap();
+        settings.lock().save_config();
+    });
+    Ok(())
+}
+
+#[command]
+pub fn reset_mixer_cmd(settings: State<'_, Arc<Mutex<Settings>>>, sender: State<'_, Sender<(SettingsCommand, Option<i32>)>>) {
+    {
+        let config = settings.lock().get_config();
+        let mut config = config.lock();
+        config.mixer = Mixer { persisted: config.mixer.persisted, ..Mixer::default() };
+    }
+    apply_mixer(&settings, &sender);
+}
+
+#[command]
+pub fn persist_mixer_cmd(persisted: bool, settings: State<'_, Arc<Mutex<Settings>>>) {
+    settings.lock().get_config().lock().mixer.persisted = persisted;
+    settings.lock().save_config();
 }
 
 #[command]
 pub fn get_config_cmd(settings: State<'_, Arc<Mutex<Settings>>>) -> Config {
-    *settings.lock().get_config().lock()
+    settings.lock().get_config().lock().clone()
+}
+
+#[command]
+pub fn get_runtime_info_cmd(start_time: State<'_, AppStartTime>, settings: State<'_, Arc<Mutex<Settings>>>) -> RuntimeInfo {
+    let config = settings.lock().get_config().lock().clone();
+    runtime_info::get_runtime_info(&start_time, &config)
+}
+
+#[command]
+pub fn export_diagnostics_cmd(window: Window<Wry>, start_time: State<'_, AppStartTime>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<String, String> {
+    let config = settings.lock().get_config().lock().clone();
+    let runtime_info = runtime_info::get_runtime_info(&start_time, &config);
+    let filename = diagnostics::export_diagnostics(&config, &runtime_info)?.to_string_lossy().to_string();
+
+    message(Some(&window), "SID Device - Diagnostics", format!("Diagnostics saved to:\r\r{}", filename));
+    Ok(filename)
+}
+
+#[command]
+pub fn toggle_recording_cmd(app_handle: AppHandle<Wry>) {
+    toggle_recording(&app_handle);
+}
+
+#[command]
+pub fn run_self_test_cmd(window: Window<Wry>, device_state: State<'_, DeviceState>) -> Result<(), String> {
+    let address = device_state.get_local_address().ok_or("SID Device is not running")?;
+
+    // the session takes a few seconds, so it doesn't block the window
+    let _self_test_thread = thread::spawn(move || {
+        let result = run_self_test(address);
+        match &result {
+            Ok(()) => log!("Self-test passed\r"),
+            Err(error) => log!("ERROR: Self-test failed: {}\r", error)
+        }
+
+        let msg = result.map_or_else(|error| format!("Self-test failed:\r\r{}", error), |_| "Self-test passed.".to_string());
+        message(Some(&window), "SID Device - Self-test", msg);
+    });
+    Ok(())
+}
+
+#[command]
+pub fn get_theme_cmd(app_handle: AppHandle<Wry>, settings: State<'_, Arc<Mutex<Settings>>>) -> Theme {
+    let theme_setting = settings.lock().get_config().lock().theme;
+    get_theme(&app_handle, theme_setting)
+}
+
+#[command]
+pub fn set_theme_cmd(theme: ThemeSetting, app_handle: AppHandle<Wry>, settings: State<'_, Arc<Mutex<Settings>>>) -> Result<(), SettingsError> {
+    settings.lock().get_config().lock().theme = theme;
+    settings.lock().save_config();
+
+    emit_theme(&app_handle, &settings);
+    Ok(())
+}
+
+#[command]
+pub fn open_config_dir_cmd() -> Result<(), String> {
+    file_manager::open_folder(&Settings::get_config_dir())
+}
+
+#[command]
+pub fn copy_log_cmd(app_handle: AppHandle<Wry>) -> Result<(), String> {
+    app_handle.clipboard_manager()
+        .write_text(LOG.get_tail(COPIED_LOG_LINES))
+        .map_err(|error| error.to_string())
+}
+
+#[command]
+pub fn get_translations_cmd(i18n: State<'_, I18n>) -> I18n {
+    i18n.inner().clone()
+}
+
+fn apply_mixer(settings: &Arc<Mutex<Settings>>, sender: &Sender<(SettingsCommand, Option<i32>)>) {
+    block_on(async {
+        let _ = sender.broadcast((SettingsCommand::SetMixer, None)).await.unwrap();
+        settings.lock().save_config();
+    });
+}
+
+fn check_range(setting: &'static str, value: i32, min: i32, max: i32) -> Result<(), SettingsError> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(SettingsError::OutOfRange { setting, min, max })
+    }
 }
@@ -1,54 +1,72 @@
 // Copyright (C) 2022 Wilfred Bos
 // Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
 
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use parking_lot::Mutex;
+use sid_device_core::server::ServerControl;
 
 pub struct DeviceState {
-    pub device_ready: Arc<AtomicBool>,
+    pub server: ServerControl,
     pub restart: Arc<AtomicBool>,
-    pub quit: Arc<AtomicBool>,
     pub error: Arc<AtomicBool>,
+    pub port_in_use: Arc<AtomicBool>,
+    pub sid_device_running: Arc<AtomicBool>,
     pub error_msg: Arc<Mutex<String>>
 }
 
 impl DeviceState {
     pub fn new() -> DeviceState {
         DeviceState {
-            device_ready: Arc::new(AtomicBool::new(false)),
+            server: ServerControl::new(),
             restart: Arc::new(AtomicBool::new(true)),
-            quit: Arc::new(AtomicBool::new(false)),
             error: Arc::new(AtomicBool::new(false)),
+            port_in_use: Arc::new(AtomicBool::new(false)),
+            sid_device_running: Arc::new(AtomicBool::new(false)),
             error_msg: Arc::new(Mutex::new(String::new()))
         }
     }
 
     pub fn init(&self) {
         self.restart.store(false, Ordering::SeqCst);
-        self.quit.store(false, Ordering::SeqCst);
+        self.server.reset();
     }
 
     pub fn reset(&self) {
         self.restart.store(true, Ordering::SeqCst);
-        self.quit.store(true, Ordering::SeqCst);
         self.error.store(false, Ordering::SeqCst);
+        self.port_in_use.store(false, Ordering::SeqCst);
+        self.sid_device_running.store(false, Ordering::SeqCst);
+        self.request_quit();
     }
 
-    pub fn set_error(&self, error_msg: String) {
+    pub fn request_quit(&self) {
+        self.server.stop();
+    }
+
+    // the address to connect to the running server from this machine
+    pub fn get_local_address(&self) -> Option<SocketAddr> {
+        self.server.get_local_address()
+    }
+
+    pub fn set_error(&self, error_msg: String, port_in_use: bool, sid_device_running: bool) {
         *self.error_msg.lock() = error_msg;
+        self.port_in_use.store(port_in_use, Ordering::SeqCst);
+        self.sid_device_running.store(sid_device_running, Ordering::SeqCst);
         self.error.store(true, Ordering::SeqCst);
         self.restart.store(true, Ordering::SeqCst);
-        self.device_ready.store(true, Ordering::SeqCst);
+        self.server.set_ready(true);
     }
 
     pub fn clone(&self) -> DeviceState {
         DeviceState {
-            device_ready: self.device_ready.clone(),
+            server: self.server.clone(),
             restart: self.restart.clone(),
-            quit: self.quit.clone(),
             error: self.error.clone(),
+            port_in_use: self.port_in_use.clone(),
+            sid_device_running: self.sid_device_running.clone(),
             error_msg: self.error_msg.clone()
         }
     }
@@ -0,0 +1,59 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::collections::HashMap;
+
+const DEFAULT_LANGUAGE: &str = "en";
+
+const TRANSLATIONS: [(&str, &str); 3] = [
+    ("en", include_str!("../locales/en.json")),
+    ("de", include_str!("../locales/de.json")),
+    ("nl", include_str!("../locales/nl.json"))
+];
+
+#[derive(Clone, serde::Serialize)]
+pub struct I18n {
+    language: String,
+    strings: HashMap<String, String>
+}
+
+impl I18n {
+    pub fn new() -> I18n {
+        let locale = sys_locale::get_locale().unwrap_or_default();
+        Self::for_locale(&locale)
+    }
+
+    fn for_locale(locale: &str) -> I18n {
+        let language = locale.split(|c| c == '-' || c == '_').next().unwrap_or_default().to_lowercase();
+
+        let language = if TRANSLATIONS.iter().any(|(code, _)| *code == language) {
+            language
+        } else {
+            DEFAULT_LANGUAGE.to_string()
+        };
+
+        // untranslated strings fall back to the default language
+        let mut strings = load_strings(DEFAULT_LANGUAGE);
+        strings.extend(load_strings(&language));
+
+        I18n {
+            language,
+            strings
+        }
+    }
+
+    pub fn translate(&self, key: &str) -> String {
+        self.strings.get(key).cloned().unwrap_or_else(|| key.to_string())
+    }
+
+    pub fn translate_with(&self, key: &str, args: &[&str]) -> String {
+        args.iter().fold(self.translate(key), |text, arg| text.replacen("{}", arg, 1))
+    }
+}
+
+fn load_strings(language: &str) -> HashMap<String, String> {
+    TRANSLATIONS.iter()
+        .find(|(code, _)| *code == language)
+        .and_then(|(_, json)| serde_json::from_str(json).ok())
+        .unwrap_or_default()
+}
@@ -8,19 +8,21 @@
 
 mod commands;
 mod device_state;
+mod i18n;
 mod settings;
 mod sid_device_server;
 mod utils;
 
-use std::{thread, time::Duration};
+use std::{env, thread, time::{Duration, Instant}};
 use std::process::exit;
 use std::sync::Arc;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use async_broadcast::{broadcast, Receiver, Sender};
+use futures_lite::future::block_on;
 use parking_lot::Mutex;
 use single_instance::SingleInstance;
-use tauri::api::dialog::ask;
+use tauri::api::dialog::{ask, message};
 use tauri::window::WindowBuilder;
 use tauri::{
     App,
@@ -33,6 +35,8 @@ use tauri::{
     SystemTrayHandle,
     SystemTrayMenu,
     SystemTrayMenuItem,
+    SystemTraySubmenu,
+    Theme,
     WindowEvent,
     Window,
     Wry
@@ -40,19 +44,85 @@ use tauri::{
 
 use commands::{
     get_devices_cmd,
+    get_input_devices_cmd,
+    get_audio_hosts_cmd,
+    change_audio_host_cmd,
     change_filter_bias_6581_cmd,
     toggle_launch_at_start_cmd,
     reset_to_default_cmd,
     change_audio_device_cmd,
     enable_digiboost_cmd,
+    change_digiboost_level_cmd,
     allow_external_ip_cmd,
-    get_config_cmd
+    enable_audio_stream_cmd,
+    enable_deferred_responses_cmd,
+    enable_low_power_mode_cmd,
+    enable_low_latency_cmd,
+    change_audio_latency_cmd,
+    change_sample_rate_cmd,
+    get_output_channel_count_cmd,
+    change_output_channels_cmd,
+    change_sid_gain_trim_cmd,
+    enable_loudness_normalization_cmd,
+    enable_soft_clipping_cmd,
+    enable_old_8580_filter_cmd,
+    set_external_input_cmd,
+    enable_osc_cmd,
+    enable_update_check_cmd,
+    enable_pairing_cmd,
+    get_network_interfaces_cmd,
+    set_bind_address_cmd,
+    set_port_cmd,
+    change_socket_options_cmd,
+    get_config_cmd,
+    get_runtime_info_cmd,
+    export_diagnostics_cmd,
+    run_self_test_cmd,
+    toggle_recording_cmd,
+    get_theme_cmd,
+    set_theme_cmd,
+    change_mixer_channel_cmd,
+    reset_mixer_cmd,
+    persist_mixer_cmd,
+    change_master_volume_cmd,
+    open_config_dir_cmd,
+    copy_log_cmd,
+    get_translations_cmd
 };
-use settings::Settings;
-use sid_device_server::SidDeviceServer;
+use i18n::I18n;
+use settings::{Settings, SettingsError, ThemeSetting};
+use sid_device_core::audio_host::AUDIO_HOST;
+use sid_device_core::log::set_log_handler;
+use sid_device_core::watchdog::{WATCHDOG, WatchdogEvent};
+use sid_device_server::{AppServerHandler, AUDIO_MUTED, BufferHealthMonitor, ConnectionStatsMonitor, NOW_PLAYING, PAIRING, PairingEvent, ServerError, SidDeviceServer, start_audio_stream_thread, start_buffer_tuner_thread, start_osc_thread};
 
 use crate::device_state::DeviceState;
 use crate::settings::Config;
+use crate::utils::{audio, file_manager, port};
+use crate::utils::capture::{CAPTURE, CAPTURE_LENGTH_IN_SEC, start_capture_thread};
+use crate::utils::recorder::RECORDER;
+use crate::utils::runtime_info::AppStartTime;
+use crate::utils::update_check::{self, PENDING_UPDATE, RELEASES_PAGE_URL, ReleaseVersion};
+use crate::utils::webhook::{self, WEBHOOK, WebhookEvent};
+
+const NOW_PLAYING_MENU_ID: &str = "now playing";
+const AUDIO_DEVICE_MENU_ID_PREFIX: &str = "audio device ";
+const VOLUME_MENU_ID_PREFIX: &str = "volume ";
+const VOLUME_MENU_LEVELS: [i32; 4] = [25, 50, 75, 100];
+const MODEL_COMPARISON_MENU_ID_PREFIX: &str = "model comparison ";
+const MODEL_COMPARISON_ORIGINAL_MODEL: i32 = 0;
+const MODEL_COMPARISON_BOTH_MODELS: i32 = 50;
+const MODEL_COMPARISON_OTHER_MODEL: i32 = 100;
+const BUFFER_HEALTH_INTERVAL_IN_MILLIS: u64 = 500;
+const CONNECTION_STATS_INTERVAL_IN_MILLIS: u64 = 1000;
+const PAIRING_INTERVAL_IN_MILLIS: u64 = 500;
+const WATCHDOG_INTERVAL_IN_MILLIS: u64 = 500;
+const WEBHOOK_INTERVAL_IN_MILLIS: u64 = 500;
+const NOW_PLAYING_INTERVAL_IN_MILLIS: u64 = 500;
+const HEADLESS_ARG: &str = "--headless";
+const AUDIO_STREAM_ARG: &str = "--audio-stream";
+const UPDATE_CHECK_INTERVAL_IN_SEC: u64 = 7 * 24 * 60 * 60;
+const UPDATE_SCHEDULER_INTERVAL_IN_SEC: u64 = 60 * 60;
 
 type SidDeviceChannel = (Sender<(SettingsCommand, Option<i32>)>, Receiver<(SettingsCommand, Option<i32>)>);
 
@@ -61,13 +131,37 @@ pub enum SettingsCommand {
     SetAudioDevice,
     EnableDigiboost,
     DisableDigiboost,
-    FilterBias6581
+    SetDigiboostLevel,
+    SetExternalInput,
+    EnableOld8580Filter,
+    DisableOld8580Filter,
+    EnableLowPowerMode,
+    DisableLowPowerMode,
+    EnableLowLatency,
+    DisableLowLatency,
+    SetAudioLatency,
+    SetSampleRate,
+    SetOutputChannels,
+    SetSidGainTrims,
+    FilterBias6581,
+    SetMixer,
+    SetMasterVolume,
+    SetModelComparison,
+    EnableLoudnessNormalization,
+    DisableLoudnessNormalization,
+    EnableSoftClipping,
+    DisableSoftClipping
 }
 
 fn main() {
+    // the engine and the server log to the same console and log as the app
+    set_log_handler(utils::log::write_line);
+
+    let start_time = AppStartTime(Instant::now());
+
     let instance = SingleInstance::new("sid-device").unwrap();
     if !instance.is_single() {
-        println!("ERROR: SID Device is already running\r");
+        log!("ERROR: SID Device is already running\r");
         exit(1);
     }
 
@@ -75,23 +169,82 @@ fn main() {
     device_sender.set_overflow(true);
 
     let settings = Arc::new(Mutex::new(Settings::new()));
-    let system_tray = create_system_tray(settings.lock().get_config().lock().launch_at_start_enabled);
+    AUDIO_HOST.set_host(settings.lock().get_config().lock().audio_host.as_deref());
+
+    // a headless device has no settings window to enable the audio stream with
+    if env::args().any(|arg| arg == AUDIO_STREAM_ARG) {
+        settings.lock().get_config().lock().audio_stream_enabled = true;
+    }
+
+    start_osc_thread(settings.clone(), device_receiver.clone());
+    start_audio_stream_thread(settings.clone());
+    start_buffer_tuner_thread(settings.clone());
+    start_capture_thread();
+    start_webhook_thread(settings.clone());
+
+    if env::args().any(|arg| arg == HEADLESS_ARG) {
+        start_pairing_thread(settings.clone(), None);
+        start_watchdog_thread(None);
+        run_headless(device_receiver, &settings);
+        return;
+    }
 
+    let i18n = I18n::new();
+    let system_tray = create_system_tray(&settings.lock().get_config().lock(), &i18n);
     let device_state = start_sid_device_thread(device_receiver, &settings);
 
     let app = tauri::Builder::default()
+        .manage(start_time)
+        .manage(i18n)
         .manage(device_state)
         .manage(settings.clone())
         .manage(device_sender.clone())
         .invoke_handler(tauri::generate_handler![
             get_devices_cmd,
+            get_input_devices_cmd,
+            get_audio_hosts_cmd,
+            change_audio_host_cmd,
             change_filter_bias_6581_cmd,
             toggle_launch_at_start_cmd,
             reset_to_default_cmd,
             change_audio_device_cmd,
             enable_digiboost_cmd,
+            change_digiboost_level_cmd,
             allow_external_ip_cmd,
-            get_config_cmd
+            enable_audio_stream_cmd,
+            enable_deferred_responses_cmd,
+            enable_low_power_mode_cmd,
+            enable_low_latency_cmd,
+            change_audio_latency_cmd,
+            change_sample_rate_cmd,
+            get_output_channel_count_cmd,
+            change_output_channels_cmd,
+            change_sid_gain_trim_cmd,
+            enable_loudness_normalization_cmd,
+            enable_soft_clipping_cmd,
+            enable_old_8580_filter_cmd,
+            set_external_input_cmd,
+            enable_osc_cmd,
+            enable_update_check_cmd,
+            enable_pairing_cmd,
+            get_network_interfaces_cmd,
+            set_bind_address_cmd,
+            set_port_cmd,
+            change_socket_options_cmd,
+            get_config_cmd,
+            get_runtime_info_cmd,
+            export_diagnostics_cmd,
+            run_self_test_cmd,
+            toggle_recording_cmd,
+            get_theme_cmd,
+            set_theme_cmd,
+            change_mixer_channel_cmd,
+            reset_mixer_cmd,
+            persist_mixer_cmd,
+            change_master_volume_cmd,
+            open_config_dir_cmd,
+            copy_log_cmd,
+            get_translations_cmd
         ])
         .system_tray(system_tray)
         .on_page_load(move |window, _| {
@@ -100,6 +253,13 @@ fn main() {
         .setup(move |app| {
             create_dialogs(app)?;
             setup_listeners(app);
+            start_buffer_health_thread(app.app_handle());
+            start_connection_stats_thread(app.app_handle());
+            start_pairing_thread(app.state::<Arc<Mutex<Settings>>>().inner().clone(), Some(app.app_handle()));
+            start_watchdog_thread(Some(app.app_handle()));
+            start_now_playing_thread(app.app_handle());
+            start_update_check_thread(app.app_handle());
+            start_audio_device_menu_thread(app.app_handle());
             Ok(())
         })
         .on_system_tray_event(
@@ -121,7 +281,7 @@ fn main() {
         move |app_handle, e| match e {
             RunEvent::ExitRequested { api: _, .. } => {
                 let device_state = app_handle.state::<DeviceState>();
-                device_state.quit.store(true, Ordering::SeqCst);
+                device_state.request_quit();
             }
             RunEvent::WindowEvent { label,  event: WindowEvent::CloseRequested { api, .. }, .. } => {
                 let app_handle = app_handle.clone();
@@ -134,6 +294,10 @@ fn main() {
                 // emit a blur event to fix an issue in Windows that a select box stays opened when moving the window
                 window.emit("blur", None::<String>).unwrap();
             }
+            RunEvent::WindowEvent { event: WindowEvent::ThemeChanged(_), .. } => {
+                let settings = app_handle.state::<Arc<Mutex<Settings>>>();
+                emit_theme(app_handle, &settings);
+            }
             _ => {}
         }
     });
@@ -160,25 +324,104 @@ fn start_sid_device_loop(receiver: Receiver<(SettingsCommand, Option<i32>)>, set
             thread::sleep(Duration::from_millis(500));
         }
 
-        let mut sid_device_server = SidDeviceServer::new(settings_clone.lock().get_config());
+        let mut sid_device_server = SidDeviceServer::new(Arc::new(AppServerHandler::new(settings_clone.lock().get_config())));
 
         device_state.init();
 
-        let allow_external_connections = settings_clone.lock().get_config().lock().allow_external_connections;
+        let server_result = sid_device_server.start(receiver.clone(), &device_state.server);
+
+        if let Err(server_error) = server_result {
+            log!("ERROR: {}\r", server_error);
+
+            let port_in_use = matches!(server_error, ServerError::PortInUse { .. } | ServerError::SidDeviceRunning { .. });
+            let sid_device_running = matches!(server_error, ServerError::SidDeviceRunning { .. });
+            device_state.set_error(server_error.to_string(), port_in_use, sid_device_running);
+            WEBHOOK.notify(WebhookEvent::Error { message: server_error.to_string() });
+        }
+    }
+}
+
+// runs the server without windows and tray, configured by the config file only,
+// nobody can resolve a port conflict without the GUI, so a server error ends the process
+fn run_headless(receiver: Receiver<(SettingsCommand, Option<i32>)>, settings: &Arc<Mutex<Settings>>) {
+    let device_state = DeviceState::new();
+    log!("SID Device is running headless on port {}\r", settings.lock().get_config().lock().port);
+
+    while device_state.restart.load(Ordering::SeqCst) {
+        let mut sid_device_server = SidDeviceServer::new(Arc::new(AppServerHandler::new(settings.lock().get_config())));
+
+        device_state.init();
+
+        if let Err(server_error) = sid_device_server.start(receiver.clone(), &device_state.server) {
+            log!("ERROR: {}\r", server_error);
+            exit(1);
+        }
+    }
+}
+
+fn save_capture(app_handle: &AppHandle<Wry>) {
+    let app_handle = app_handle.clone();
+
+    // writing the files can take a moment, the tray must stay responsive
+    let _save_capture_thread = thread::spawn(move || {
+        let i18n = app_handle.state::<I18n>();
+
+        match CAPTURE.save() {
+            Ok((wav_filename, writes_filename)) => {
+                log!("Capture saved to: {}\r", wav_filename.to_string_lossy());
+                let msg = i18n.translate_with("dialog.capture_saved", &[&wav_filename.to_string_lossy(), &writes_filename.to_string_lossy()]);
+                message(None::<&Window<Wry>>, i18n.translate("dialog.capture_title"), msg);
+            }
+            Err(error) => {
+                log!("ERROR: Capture could not be saved: {}\r", error);
+                let msg = i18n.translate_with("dialog.capture_failed", &[&error]);
+                message(None::<&Window<Wry>>, i18n.translate("dialog.capture_title"), msg);
+            }
+        }
+    });
+}
 
-        let server_result = sid_device_server.start(allow_external_connections,receiver.clone(), device_state.device_ready.clone(), device_state.quit.clone());
+pub fn toggle_recording(app_handle: &AppHandle<Wry>) {
+    let i18n = app_handle.state::<I18n>();
 
-        if let Err(server_result) = server_result {
-            println!("ERROR: {}\r", server_result);
-            device_state.set_error(server_result);
+    if RECORDER.is_recording() {
+        match RECORDER.stop() {
+            Ok(filename) => {
+                log!("Recording saved to: {}\r", filename.to_string_lossy());
+                let msg = i18n.translate_with("dialog.recording_saved", &[&filename.to_string_lossy()]);
+                message(None::<&Window<Wry>>, i18n.translate("dialog.recording_title"), msg);
+            }
+            Err(error) => {
+                log!("ERROR: Recording could not be saved: {}\r", error);
+                let msg = i18n.translate_with("dialog.recording_failed", &[&error]);
+                message(None::<&Window<Wry>>, i18n.translate("dialog.recording_title"), msg);
+            }
         }
+    } else if let Err(error) = RECORDER.start() {
+        log!("ERROR: Recording could not be started: {}\r", error);
+        let msg = i18n.translate_with("dialog.recording_failed", &[&error]);
+        message(None::<&Window<Wry>>, i18n.translate("dialog.recording_title"), msg);
+    }
+
+    let recording = RECORDER.is_recording();
+    app_handle.tray_handle().get_item("record").set_selected(recording).unwrap();
+    if let Some(settings_window) = app_handle.get_window("settings") {
+        let _ = settings_window.emit("recording", recording);
     }
 }
 
+fn start_audio_device_menu_thread(app_handle: AppHandle<Wry>) {
+    let _audio_device_menu_thread = thread::spawn(move || {
+        let settings = app_handle.state::<Arc<Mutex<Settings>>>().inner().clone();
+        update_system_tray_menu(&app_handle, &settings);
+    });
+}
+
 fn handle_menu_item_click(app_handle: &AppHandle<Wry>, id: &str, settings: &Arc<Mutex<Settings>>) {
     match id {
         "exit" => {
             close_window(app_handle, "about");
+            close_window(app_handle, "health");
             close_window(app_handle, "settings");
         }
         "reset" => {
@@ -193,13 +436,97 @@ fn handle_menu_item_click(app_handle: &AppHandle<Wry>, id: &str, settings: &Arc<
             hide_window(app_handle, "about");
             show_settings_window(app_handle, "settings", &settings.lock().get_config().lock());
         }
+        "health" => {
+            show_health_window(app_handle, "health");
+        }
+        "update" => {
+            open_releases_page();
+        }
+        "skip update" => {
+            if let Some(version) = PENDING_UPDATE.lock().take() {
+                settings.lock().get_config().lock().skipped_version = Some(version);
+                settings.lock().save_config();
+            }
+
+            update_system_tray_menu(app_handle, settings);
+        }
+        "check updates" => {
+            let app_handle = app_handle.clone();
+            let settings = settings.clone();
+
+            let _update_check_thread = thread::spawn(move || {
+                check_for_updates(&app_handle, &settings, true);
+            });
+        }
+        "save capture" => {
+            save_capture(app_handle);
+        }
+        "record" => {
+            toggle_recording(app_handle);
+        }
+        "mute" => {
+            let muted = !AUDIO_MUTED.load(Ordering::SeqCst);
+            AUDIO_MUTED.store(muted, Ordering::SeqCst);
+
+            app_handle.tray_handle().get_item(id).set_selected(muted).unwrap();
+        }
+        "digiboost" => {
+            let digiboost_enabled = !settings.lock().get_config().lock().digiboost_enabled;
+            enable_digiboost(app_handle, settings, digiboost_enabled);
+
+            update_settings_window(app_handle, settings);
+        }
         "launch at startup" => {
-            toggle_launch_at_start(&app_handle.tray_handle(), settings, id);
+            if let Err(error) = toggle_launch_at_start(&app_handle.tray_handle(), settings, id) {
+                log!("ERROR: {}\r", error);
+            }
 
-            let settings_window = app_handle.get_window("settings");
-            settings_window.unwrap().emit("update-settings", &*settings.lock().get_config().lock()).unwrap();
+            update_settings_window(app_handle, settings);
         }
-        _ => {}
+        _ => {
+            let device_index = id.strip_prefix(AUDIO_DEVICE_MENU_ID_PREFIX).and_then(|index| index.parse().ok());
+
+            if let Some(device_index) = device_index {
+                change_audio_device(app_handle, settings, device_index);
+
+                update_settings_window(app_handle, settings);
+            }
+
+            let volume = id.strip_prefix(VOLUME_MENU_ID_PREFIX).and_then(|volume| volume.parse().ok());
+
+            if let Some(volume) = volume {
+                change_master_volume(app_handle, settings, volume);
+
+                update_settings_window(app_handle, settings);
+            }
+
+            // the comparison is switched off by the item without a balance
+            if let Some(balance) = id.strip_prefix(MODEL_COMPARISON_MENU_ID_PREFIX) {
+                change_model_comparison(app_handle, settings, balance.parse().ok());
+
+                update_settings_window(app_handle, settings);
+            }
+        }
+    }
+}
+
+fn update_settings_window(app_handle: &AppHandle<Wry>, settings: &Arc<Mutex<Settings>>) {
+    let settings_window = app_handle.get_window("settings");
+    settings_window.unwrap().emit("update-settings", &*settings.lock().get_config().lock()).unwrap();
+}
+
+fn emit_theme(app_handle: &AppHandle<Wry>, settings: &Arc<Mutex<Settings>>) {
+    let theme_setting = settings.lock().get_config().lock().theme;
+    app_handle.emit_all("theme-changed", get_theme(app_handle, theme_setting)).unwrap();
+}
+
+fn get_theme(app_handle: &AppHandle<Wry>, theme_setting: ThemeSetting) -> Theme {
+    match theme_setting {
+        ThemeSetting::Light => Theme::Light,
+        ThemeSetting::Dark => Theme::Dark,
+        ThemeSetting::System => app_handle.get_window("settings")
+            .and_then(|window| window.theme().ok())
+            .unwrap_or(Theme::Dark)
     }
 }
 
@@ -238,14 +565,29 @@ fn create_dialogs(app: &mut App<Wry>) -> Result<(), Box<dyn std::error::Error>>
         .skip_taskbar(true)
         .build()?;
 
+    WindowBuilder::new(
+        app,
+        "health".to_string(),
+        tauri::WindowUrl::App("/pages/health/index.html".into()))
+        .title("SID Device - Buffer Health")
+        .inner_size(600.0, 420.0)
+        .min_inner_size(600.0, 420.0 + height_correction)
+        .max_inner_size(600.0, 420.0 + height_correction)
+        .center()
+        .resizable(resizable)
+        .fullscreen(false)
+        .visible(false)
+        .skip_taskbar(true)
+        .build()?;
+
     WindowBuilder::new(
         app,
         "settings".to_string(),
         tauri::WindowUrl::App("/pages/settings/index.html".into()))
         .title("SID Device - Settings")
-        .inner_size(600.0, 450.0)
-        .min_inner_size(600.0, 450.0 + height_correction)
-        .max_inner_size(600.0, 450.0 + height_correction)
+        .inner_size(600.0, 660.0)
+        .min_inner_size(600.0, 660.0 + height_correction)
+        .max_inner_size(600.0, 660.0 + height_correction)
         .center()
         .resizable(resizable)
         .fullscreen(false)
@@ -258,6 +600,7 @@ fn create_dialogs(app: &mut App<Wry>) -> Result<(), Box<dyn std::error::Error>>
 
 fn setup_listeners(app: &mut App<Wry>) {
     let about_window = app.get_window("about").unwrap();
+    let health_window = app.get_window("health").unwrap();
     let settings_window = app.get_window("settings").unwrap();
 
     let _id = settings_window.listen("device-ready", {
@@ -267,59 +610,492 @@ fn setup_listeners(app: &mut App<Wry>) {
         move |_event| {
             let device_state = app_handle.state::<DeviceState>();
 
-            if device_state.device_ready.load(Ordering::SeqCst) {
-                device_state.device_ready.store(false, Ordering::SeqCst);
+            if device_state.server.is_ready() {
+                device_state.server.set_ready(false);
 
                 if device_state.error.load(Ordering::SeqCst) {
-                    let error_clone = device_state.error.clone();
-                    let about_window_clone = about_window.clone();
-                    let settings_window_clone = settings_window_clone.clone();
+                    let windows = vec![about_window.clone(), health_window.clone(), settings_window_clone.clone()];
 
-                    let msg = device_state.error_msg.lock().to_owned() + "\r\rTry again?";
+                    if device_state.port_in_use.load(Ordering::SeqCst) {
+                        ask_port_in_use_action(&app_handle, windows);
+                    } else {
+                        let i18n = app_handle.state::<I18n>();
+                        let error_clone = device_state.error.clone();
+                        let msg = device_state.error_msg.lock().to_owned() + "\r\r" + &i18n.translate("dialog.try_again");
 
-                    ask(None::<&Window<Wry>>, "SID-Device Error", msg, move |answer| {
+                        ask(None::<&Window<Wry>>, i18n.translate("dialog.error_title"), msg, move |answer| {
+                            if answer {
+                                error_clone.store(false, Ordering::SeqCst);
+                            } else {
+                                close_windows(&windows);
+                            }
+                        });
+                    }
+                } else {
+                    settings_window_clone.emit("ready", None::<String>).unwrap();
+                }
+            }
+        }
+    });
+}
+
+fn ask_port_in_use_action(app_handle: &AppHandle<Wry>, windows: Vec<Window<Wry>>) {
+    let device_state = app_handle.state::<DeviceState>();
+    let settings = app_handle.state::<Arc<Mutex<Settings>>>().inner().clone();
+    let i18n = app_handle.state::<I18n>().inner().clone();
+    let error = device_state.error.clone();
+    let error_msg = device_state.error_msg.lock().to_owned();
+
+    // closing another SID device is rarely intended, so offer the alternate port right away
+    if device_state.sid_device_running.load(Ordering::SeqCst) {
+        ask_switch_port(settings, i18n, error, windows, &error_msg);
+        return;
+    }
+
+    let title = i18n.translate("dialog.error_title");
+    let msg = error_msg + "\r\r" + &i18n.translate("dialog.port_in_use_try_again");
+
+    ask(None::<&Window<Wry>>, title, msg, move |answer| {
+        if answer {
+            error.store(false, Ordering::SeqCst);
+        } else {
+            ask_switch_port(settings, i18n, error, windows, "");
+        }
+    });
+}
+
+fn ask_switch_port(settings: Arc<Mutex<Settings>>, i18n: I18n, error: Arc<AtomicBool>, windows: Vec<Window<Wry>>, error_msg: &str) {
+    let config = settings.lock().get_config();
+    let host = sid_device_server::get_host(&config.lock());
+    let alternate_port = port::find_available_port(host, config.lock().port);
+
+    if let Some(alternate_port) = alternate_port {
+        let switch_port_msg = i18n.translate_with("dialog.switch_port", &[&alternate_port.to_string()]);
+        let msg = if error_msg.is_empty() { switch_port_msg } else { error_msg.to_string() + "\r\r" + &switch_port_msg };
+
+        ask(None::<&Window<Wry>>, i18n.translate("dialog.error_title"), msg, move |answer| {
+            if answer {
+                settings.lock().get_config().lock().port = alternate_port;
+                settings.lock().save_config();
+                error.store(false, Ordering::SeqCst);
+            } else {
+                close_windows(&windows);
+            }
+        });
+    } else {
+        close_windows(&windows);
+    }
+}
+
+fn close_windows(windows: &[Window<Wry>]) {
+    for window in windows {
+        window.close().unwrap();
+    }
+}
+
+fn start_buffer_health_thread(app_handle: AppHandle<Wry>) {
+    let _buffer_health_thread = thread::spawn(move || {
+        let mut buffer_health_monitor = BufferHealthMonitor::new();
+
+        loop {
+            thread::sleep(Duration::from_millis(BUFFER_HEALTH_INTERVAL_IN_MILLIS));
+
+            let snapshot = buffer_health_monitor.take_snapshot();
+
+            if let Some(health_window) = app_handle.get_window("health") {
+                if health_window.is_visible().unwrap_or(false) {
+                    let _ = health_window.emit("buffer-health", snapshot);
+                }
+            } else {
+                break;
+            }
+        }
+    });
+}
+
+fn start_connection_stats_thread(app_handle: AppHandle<Wry>) {
+    let _connection_stats_thread = thread::spawn(move || {
+        let mut connection_stats_monitor = ConnectionStatsMonitor::new();
+        let mut last_connection_count = 0;
+
+        loop {
+            thread::sleep(Duration::from_millis(CONNECTION_STATS_INTERVAL_IN_MILLIS));
+
+            let health_window = match app_handle.get_window("health") {
+                Some(health_window) => health_window,
+                None => break
+            };
+
+            let connection_rates = connection_stats_monitor.take_snapshot();
+
+            // keep publishing once more after the last client disconnects so the view is cleared
+            if (!connection_rates.is_empty() || last_connection_count > 0) && health_window.is_visible().unwrap_or(false) {
+                let _ = health_window.emit("connection-stats", &connection_rates);
+            }
+            last_connection_count = connection_rates.len();
+        }
+    });
+}
+
+// the workers also run headless, where there are no dialogs, so they run as long as the process
+fn start_pairing_thread(settings: Arc<Mutex<Settings>>, app_handle: Option<AppHandle<Wry>>) {
+    let _pairing_thread = thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(PAIRING_INTERVAL_IN_MILLIS));
+
+        for event in PAIRING.take_events() {
+            match event {
+                PairingEvent::PinRequested { address, pin } => match &app_handle {
+                    Some(app_handle) => {
+                        let i18n = app_handle.state::<I18n>();
+                        let msg = i18n.translate_with("dialog.pairing_pin", &[&address.to_string(), &format!("{:04}", pin)]);
+                        message(None::<&Window<Wry>>, i18n.translate("dialog.pairing_title"), msg);
+                    }
+                    None => console!("Pairing PIN for {}: {:04}\r", address, pin)
+                },
+                PairingEvent::Paired(_) => {
+                    settings.lock().save_config();
+                }
+            }
+        }
+    });
+}
+
+fn start_watchdog_thread(app_handle: Option<AppHandle<Wry>>) {
+    let _watchdog_thread = thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(WATCHDOG_INTERVAL_IN_MILLIS));
+
+        for event in WATCHDOG.take_events() {
+            if let WatchdogEvent::RendererRestarted(error) | WatchdogEvent::AudioDeviceFailed(error) = &event {
+                WEBHOOK.notify(WebhookEvent::Error { message: error.clone() });
+            }
+
+            // the events are logged already, so headless there is nothing left to show
+            let app_handle = match &app_handle {
+                Some(app_handle) => app_handle,
+                None => continue
+            };
+            let i18n = app_handle.state::<I18n>();
+
+            match event {
+                WatchdogEvent::RendererRestarted(reason) => {
+                    let msg = i18n.translate_with("dialog.renderer_restarted", &[&reason]);
+                    message(None::<&Window<Wry>>, i18n.translate("dialog.renderer_restarted_title"), msg);
+                }
+                WatchdogEvent::AudioDeviceRemoved(device_name) => {
+                    let msg = i18n.translate_with("dialog.audio_device_removed", &[&device_name]);
+                    message(None::<&Window<Wry>>, i18n.translate("dialog.audio_device_removed_title"), msg);
+                }
+                WatchdogEvent::AudioDeviceFailed(error) => {
+                    let msg = i18n.translate_with("dialog.audio_device_failed", &[&error]);
+                    message(None::<&Window<Wry>>, i18n.translate("dialog.error_title"), msg);
+                }
+            }
+        }
+    });
+}
+
+fn start_webhook_thread(settings: Arc<Mutex<Settings>>) {
+    let _webhook_thread = thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(WEBHOOK_INTERVAL_IN_MILLIS));
+
+        let webhook_url = settings.lock().get_config().lock().webhook_url.clone();
+        WEBHOOK.set_enabled(webhook_url.is_some());
+
+        if let Some(webhook_url) = webhook_url {
+            for event in WEBHOOK.take_events() {
+                if let Err(error) = webhook::post_event(&webhook_url, &event) {
+                    log!("ERROR: Webhook notification failed: {}\r", error);
+                }
+            }
+        }
+    });
+}
+
+// tauri has no tray tooltip, so the tune is shown as the first item of the tray menu,
+// only its title is updated to not enumerate the audio devices on every tune change
+fn start_now_playing_thread(app_handle: AppHandle<Wry>) {
+    let _now_playing_thread = thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(NOW_PLAYING_INTERVAL_IN_MILLIS));
+
+        let settings_window = match app_handle.get_window("settings") {
+            Some(settings_window) => settings_window,
+            None => break
+        };
+
+        if NOW_PLAYING.take_changed() {
+            let _ = settings_window.emit("now-playing", NOW_PLAYING.get());
+            let _ = app_handle.tray_handle().get_item(NOW_PLAYING_MENU_ID).set_title(get_now_playing_title(&app_handle.state::<I18n>()));
+        }
+    });
+}
+
+fn start_update_check_thread(app_handle: AppHandle<Wry>) {
+    let _update_check_thread = thread::spawn(move || {
+        let settings = app_handle.state::<Arc<Mutex<Settings>>>().inner().clone();
+
+        loop {
+            let (update_check_enabled, last_update_check) = {
+                let config = settings.lock().get_config().lock().clone();
+                (config.update_check_enabled, config.last_update_check.unwrap_or(0))
+            };
+
+            if update_check_enabled && update_check::get_current_time_in_sec().saturating_sub(last_update_check) >= UPDATE_CHECK_INTERVAL_IN_SEC {
+                check_for_updates(&app_handle, &settings, false);
+            }
+
+            thread::sleep(Duration::from_secs(UPDATE_SCHEDULER_INTERVAL_IN_SEC));
+
+            if app_handle.get_window("settings").is_none() {
+                break;
+            }
+        }
+    });
+}
+
+fn check_for_updates(app_handle: &AppHandle<Wry>, settings: &Arc<Mutex<Settings>>, manual_check: bool) {
+    let latest_version = update_check::fetch_latest_version();
+
+    settings.lock().get_config().lock().last_update_check = Some(update_check::get_current_time_in_sec());
+    settings.lock().save_config();
+
+    let i18n = app_handle.state::<I18n>();
+    let title = i18n.translate("dialog.update_title");
+
+    match latest_version {
+        Ok(latest_version) => {
+            let skipped_version = settings.lock().get_config().lock().skipped_version;
+            let update_available = latest_version > ReleaseVersion::current();
+
+            // a manual check also reports a version that was skipped before
+            let show_update = update_available && (manual_check || skipped_version != Some(latest_version));
+            *PENDING_UPDATE.lock() = if show_update { Some(latest_version) } else { None };
+
+            update_system_tray_menu(app_handle, settings);
+
+            if manual_check {
+                if update_available {
+                    let msg = i18n.translate_with("dialog.update_available", &[&latest_version.to_string()]);
+                    ask(None::<&Window<Wry>>, title, msg, |answer| {
                         if answer {
-                            error_clone.store(false, Ordering::SeqCst);
-                        } else {
-                            about_window_clone.close().unwrap();
-                            settings_window_clone.close().unwrap();
+                            open_releases_page();
                         }
                     });
                 } else {
-                    settings_window_clone.emit("ready", None::<String>).unwrap();
+                    message(None::<&Window<Wry>>, title, i18n.translate("dialog.up_to_date"));
                 }
             }
         }
-    });
+        Err(error) => {
+            log!("ERROR: {}\r", error);
+
+            if manual_check {
+                message(None::<&Window<Wry>>, title, i18n.translate_with("dialog.update_check_failed", &[&error]));
+            }
+        }
+    }
 }
 
-fn toggle_launch_at_start(system_tray_handle: &SystemTrayHandle<Wry>, settings: &Arc<Mutex<Settings>>, menu_id: &str) {
-    let launch_at_start = settings.lock().toggle_launch_at_start();
+fn open_releases_page() {
+    if let Err(error) = file_manager::open_url(RELEASES_PAGE_URL) {
+        log!("ERROR: {}\r", error);
+    }
+}
+
+fn toggle_launch_at_start(system_tray_handle: &SystemTrayHandle<Wry>, settings: &Arc<Mutex<Settings>>, menu_id: &str) -> Result<(), SettingsError> {
+    let launch_at_start = settings.lock().toggle_launch_at_start()?;
 
     let item_handle = system_tray_handle.get_item(menu_id);
     item_handle.set_selected(launch_at_start).unwrap();
+    Ok(())
 }
 
-fn create_system_tray(auto_launch_enabled: bool) -> SystemTray {
-    let menu_item_about = CustomMenuItem::new("about".to_string(), "About");
-    let menu_item_settings = CustomMenuItem::new("settings".to_string(), "Settings...");
-    let mut menu_item_launch_startup = CustomMenuItem::new("launch at startup".to_string(), "Launch at startup");
-    menu_item_launch_startup.selected = auto_launch_enabled;
+fn change_audio_device(app_handle: &AppHandle<Wry>, settings: &Arc<Mutex<Settings>>, device_index: i32) {
+    let audio_device_number = if device_index < 1 {
+        None
+    } else {
+        Some(device_index - 1)
+    };
+
+    block_on(async {
+        settings.lock().get_config().lock().audio_device_number = audio_device_number;
+
+        let sender = app_handle.state::<Sender<(SettingsCommand, Option<i32>)>>();
+        let _ = sender.broadcast((SettingsCommand::SetAudioDevice, audio_device_number)).await.unwrap();
+        settings.lock().save_config();
+    });
+
+    update_system_tray_menu(app_handle, settings);
+}
+
+fn change_master_volume(app_handle: &AppHandle<Wry>, settings: &Arc<Mutex<Settings>>, volume: i32) {
+    block_on(async {
+        settings.lock().get_config().lock().master_volume = volume;
+
+        let sender = app_handle.state::<Sender<(SettingsCommand, Option<i32>)>>();
+        let _ = sender.broadcast((SettingsCommand::SetMasterVolume, Some(volume))).await.unwrap();
+        settings.lock().save_config();
+    });
+
+    update_system_tray_menu(app_handle, settings);
+}
+
+fn change_model_comparison(app_handle: &AppHandle<Wry>, settings: &Arc<Mutex<Settings>>, balance: Option<i32>) {
+    block_on(async {
+        settings.lock().get_config().lock().model_comparison_balance = balance;
+
+        let sender = app_handle.state::<Sender<(SettingsCommand, Option<i32>)>>();
+        let _ = sender.broadcast((SettingsCommand::SetModelComparison, balance)).await.unwrap();
+        settings.lock().save_config();
+    });
+
+    update_system_tray_menu(app_handle, settings);
+}
+
+fn enable_digiboost(app_handle: &AppHandle<Wry>, settings: &Arc<Mutex<Settings>>, digiboost_enabled: bool) {
+    block_on(async {
+        settings.lock().get_config().lock().digiboost_enabled = digiboost_enabled;
+
+        let command = if digiboost_enabled {
+            SettingsCommand::EnableDigiboost
+        } else {
+            SettingsCommand::DisableDigiboost
+        };
+
+        let sender = app_handle.state::<Sender<(SettingsCommand, Option<i32>)>>();
+        let _ = sender.broadcast((command, None)).await.unwrap();
+        settings.lock().save_config();
+    });
+
+    app_handle.tray_handle().get_item("digiboost").set_selected(digiboost_enabled).unwrap();
+}
+
+fn update_system_tray_menu(app_handle: &AppHandle<Wry>, settings: &Arc<Mutex<Settings>>) {
+    let config = settings.lock().get_config().lock().clone();
+    let i18n = app_handle.state::<I18n>();
+    let audio_devices = audio::get_available_audio_output_device_names();
+    app_handle.tray_handle().set_menu(create_system_tray_menu(&config, Some(audio_devices), &i18n)).unwrap();
+}
+
+// the audio devices are listed afterwards, so a slow audio stack doesn't delay the tray icon
+fn create_system_tray(config: &Config, i18n: &I18n) -> SystemTray {
+    SystemTray::new().with_menu(create_system_tray_menu(config, None, i18n))
+}
+
+fn create_system_tray_menu(config: &Config, audio_devices: Option<(Vec<String>, i32)>, i18n: &I18n) -> SystemTrayMenu {
+    let menu_item_about = CustomMenuItem::new("about".to_string(), i18n.translate("tray.about"));
+    let menu_item_settings = CustomMenuItem::new("settings".to_string(), i18n.translate("tray.settings"));
+    let menu_item_health = CustomMenuItem::new("health".to_string(), i18n.translate("tray.health"));
+    let menu_item_save_capture = CustomMenuItem::new("save capture".to_string(), i18n.translate_with("tray.save_capture", &[&CAPTURE_LENGTH_IN_SEC.to_string()]));
+    let mut menu_item_record = CustomMenuItem::new("record".to_string(), i18n.translate("tray.record"));
+    menu_item_record.selected = RECORDER.is_recording();
+    let mut menu_item_mute = CustomMenuItem::new("mute".to_string(), i18n.translate("tray.mute"));
+    menu_item_mute.selected = AUDIO_MUTED.load(Ordering::SeqCst);
+
+    let mut menu_item_digiboost = CustomMenuItem::new("digiboost".to_string(), i18n.translate("tray.digiboost"));
+    menu_item_digiboost.selected = config.digiboost_enabled;
+
+    let mut menu_item_launch_startup = CustomMenuItem::new("launch at startup".to_string(), i18n.translate("tray.launch_at_startup"));
+    menu_item_launch_startup.selected = config.launch_at_start_enabled;
+
+    let menu_item_reset_connections = CustomMenuItem::new("reset".to_string(), i18n.translate("tray.reset_connections"));
+    let menu_item_check_updates = CustomMenuItem::new("check updates".to_string(), i18n.translate("tray.check_updates"));
+    let menu_item_exit = CustomMenuItem::new("exit".to_string(), i18n.translate("tray.exit"));
+    let menu_item_now_playing = CustomMenuItem::new(NOW_PLAYING_MENU_ID.to_string(), get_now_playing_title(i18n)).disabled();
+
+    let mut menu = SystemTrayMenu::new()
+        .add_item(menu_item_now_playing)
+        .add_native_item(SystemTrayMenuItem::Separator);
 
-    let menu_item_reset_connections = CustomMenuItem::new("reset".to_string(), "Reset connections");
-    let menu_item_exit = CustomMenuItem::new("exit".to_string(), "Exit");
+    if let Some(version) = *PENDING_UPDATE.lock() {
+        let menu_item_update = CustomMenuItem::new("update".to_string(), i18n.translate_with("tray.update_available", &[&version.to_string()]));
+        let menu_item_skip_update = CustomMenuItem::new("skip update".to_string(), i18n.translate("tray.skip_update"));
 
-    let tray_menu = SystemTrayMenu::new()
+        menu = menu
+            .add_item(menu_item_update)
+            .add_item(menu_item_skip_update)
+            .add_native_item(SystemTrayMenuItem::Separator);
+    }
+
+    menu
         .add_item(menu_item_about)
         .add_item(menu_item_settings)
+        .add_item(menu_item_health)
+        .add_item(menu_item_check_updates)
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_submenu(create_audio_device_submenu(config.audio_device_number, audio_devices, i18n))
+        .add_submenu(create_volume_submenu(config.master_volume, i18n))
+        .add_item(menu_item_mute)
+        .add_item(menu_item_save_capture)
+        .add_item(menu_item_record)
+        .add_item(menu_item_digiboost)
+        .add_submenu(create_model_comparison_submenu(config.model_comparison_balance, i18n))
         .add_native_item(SystemTrayMenuItem::Separator)
         .add_item(menu_item_launch_startup)
         .add_native_item(SystemTrayMenuItem::Separator)
         .add_item(menu_item_reset_connections)
         .add_native_item(SystemTrayMenuItem::Separator)
-        .add_item(menu_item_exit);
+        .add_item(menu_item_exit)
+}
+
+fn get_now_playing_title(i18n: &I18n) -> String {
+    match NOW_PLAYING.get() {
+        Some(tune_info) => i18n.translate_with("tray.now_playing", &[&tune_info.title, &tune_info.author]),
+        None => i18n.translate("tray.nothing_playing")
+    }
+}
+
+fn create_audio_device_submenu(audio_device_number: Option<i32>, audio_devices: Option<(Vec<String>, i32)>, i18n: &I18n) -> SystemTraySubmenu {
+    let selected_device_index = audio_device_number.map_or(0, |device_number| device_number + 1);
+
+    let default_device_item = match &audio_devices {
+        Some((devices, default_device)) => {
+            let default_device_name = devices.get(*default_device as usize).cloned().unwrap_or_default();
+            i18n.translate_with("tray.default_sound_driver", &[&default_device_name])
+        }
+        None => i18n.translate("tray.default_sound_driver_pending")
+    };
+    let devices = audio_devices.map(|(devices, _)| devices).unwrap_or_default();
+
+    let device_names = std::iter::once(default_device_item).chain(devices);
+
+    let mut device_menu = SystemTrayMenu::new();
+    for (index, device_name) in device_names.enumerate() {
+        let mut menu_item_device = CustomMenuItem::new(format!("{}{}", AUDIO_DEVICE_MENU_ID_PREFIX, index), device_name);
+        menu_item_device.selected = index as i32 == selected_device_index;
+        device_menu = device_menu.add_item(menu_item_device);
+    }
+
+    SystemTraySubmenu::new(i18n.translate("tray.audio_device"), device_menu)
+}
 
-    SystemTray::new().with_menu(tray_menu)
+fn create_volume_submenu(master_volume: i32, i18n: &I18n) -> SystemTraySubmenu {
+    let mut volume_menu = SystemTrayMenu::new();
+    for volume in VOLUME_MENU_LEVELS {
+        let mut menu_item_volume = CustomMenuItem::new(format!("{}{}", VOLUME_MENU_ID_PREFIX, volume), format!("{}%", volume));
+        menu_item_volume.selected = volume == master_volume;
+        volume_menu = volume_menu.add_item(menu_item_volume);
+    }
+
+    SystemTraySubmenu::new(i18n.translate("tray.volume"), volume_menu)
+}
+
+// the balance is the share of the other chip model in the mix
+fn create_model_comparison_submenu(model_comparison_balance: Option<i32>, i18n: &I18n) -> SystemTraySubmenu {
+    let items = [
+        ("off".to_string(), i18n.translate("tray.model_comparison_off"), None),
+        (MODEL_COMPARISON_ORIGINAL_MODEL.to_string(), i18n.translate("tray.model_comparison_original"), Some(MODEL_COMPARISON_ORIGINAL_MODEL)),
+        (MODEL_COMPARISON_OTHER_MODEL.to_string(), i18n.translate("tray.model_comparison_other"), Some(MODEL_COMPARISON_OTHER_MODEL)),
+        (MODEL_COMPARISON_BOTH_MODELS.to_string(), i18n.translate("tray.model_comparison_both"), Some(MODEL_COMPARISON_BOTH_MODELS))
+    ];
+
+    let mut model_comparison_menu = SystemTrayMenu::new();
+    for (id, title, balance) in items {
+        let mut menu_item_model_comparison = CustomMenuItem::new(format!("{}{}", MODEL_COMPARISON_MENU_ID_PREFIX, id), title);
+        menu_item_model_comparison.selected = balance == model_comparison_balance;
+        model_comparison_menu = model_comparison_menu.add_item(menu_item_model_comparison);
+    }
+
+    SystemTraySubmenu::new(i18n.translate("tray.model_comparison"), model_comparison_menu)
 }
 
 fn show_about_window(app: &AppHandle<Wry>, title: &str) {
@@ -332,6 +1108,16 @@ fn show_about_window(app: &AppHandle<Wry>, title: &str) {
     }
 }
 
+fn show_health_window(app: &AppHandle<Wry>, title: &str) {
+    let popup_window = app.get_window(title);
+
+    if let Some(popup_window) = popup_window {
+        popup_window.emit_to(title, "show", None::<String>).unwrap();
+
+        show_window(&popup_window, "SID Device - Buffer Health");
+    }
+}
+
 fn show_settings_window(app: &AppHandle<Wry>, title: &str, config: &Config) {
     let popup_window = app.get_window(title);
 
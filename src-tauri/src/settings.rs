@@ -1,8 +1,11 @@
 // Copyright (C) 2022 Wilfred Bos
 // Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
 
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -12,38 +15,330 @@ use std::time::{Duration, Instant};
 use app_dirs2::*;
 use auto_launch::{AutoLaunch, AutoLaunchBuilder};
 use parking_lot::Mutex;
+use typed_builder::TypedBuilder;
+
+pub use sid_device_core::MAX_SID_COUNT;
+pub use sid_device_core::server::{DEFAULT_PORT, RegisterTransform};
+
+use crate::utils::update_check::ReleaseVersion;
 
 const APP_INFO: AppInfo = AppInfo{ name: "siddevice", author: "siddevice" };
 const CONFIG_FILE_NAME: &str = "config.json";
 const DEFAULT_FILTER_BIAS_6581: i32 = 24;
+pub const MIN_PORT: i32 = 1;
+pub const MAX_PORT: i32 = 65535;
+pub const DEFAULT_AUDIO_STREAM_PORT: u16 = 6582;
+pub const DEFAULT_OSC_PORT: u16 = 6583;
+pub const DEFAULT_KEEPALIVE_INTERVAL_IN_SEC: u32 = 30;
+pub const MIN_KEEPALIVE_INTERVAL_IN_SEC: i32 = 1;
+pub const MAX_KEEPALIVE_INTERVAL_IN_SEC: i32 = 3600;
+pub const MAX_MASTER_VOLUME: i32 = 100;
+pub const MAX_DIGIBOOST_LEVEL: i32 = 100;
+pub const MIN_AUDIO_LATENCY_IN_MILLIS: i32 = 20;
+pub const MAX_AUDIO_LATENCY_IN_MILLIS: i32 = 200;
+pub const SUPPORTED_SAMPLE_RATES: [i32; 3] = [44_100, 48_000, 96_000];
+pub const MAX_OUTPUT_CHANNELS: i32 = 8;
+pub const MAX_SID_GAIN_TRIM: i32 = 200;
+pub const MAX_MIXER_GAIN: i32 = 200;
+pub const MIN_MIXER_PAN: i32 = -100;
+pub const MAX_MIXER_PAN: i32 = 100;
+const VOICE_COUNT: usize = 3;
+const MAX_PAIRED_HOSTS: usize = 16;
+pub const MIN_FILTER_BIAS_6581: i32 = -100;
+pub const MAX_FILTER_BIAS_6581: i32 = 100;
 const WRITE_CONFIG_DELAY_IN_SEC: u64 = 2;
 
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeSetting {
+    System,
+    Light,
+    Dark
+}
+
+impl Default for ThemeSetting {
+    fn default() -> Self {
+        ThemeSetting::System
+    }
+}
+
 #[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct MixerChannel {
+    pub gain: i32,
+    pub pan: i32,
+    pub voice_muted: [bool; VOICE_COUNT],
+    pub voice_solo: [bool; VOICE_COUNT]
+}
+
+impl Default for MixerChannel {
+    fn default() -> Self {
+        MixerChannel {
+            gain: 100,
+            pan: 0,
+            voice_muted: [false; VOICE_COUNT],
+            voice_solo: [false; VOICE_COUNT]
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+pub struct Mixer {
+    pub channels: [MixerChannel; MAX_SID_COUNT],
+    pub persisted: bool
+}
+
+impl Mixer {
+    pub fn get_voice_mask(&self, sid_number: usize) -> u32 {
+        // a solo on any voice silences all voices that are not soloed, across all SIDs
+        let solo_active = self.channels.iter().any(|channel| channel.voice_solo.contains(&true));
+        let channel = &self.channels[sid_number];
+
+        (0..VOICE_COUNT)
+            .filter(|&voice| if solo_active { channel.voice_solo[voice] } else { !channel.voice_muted[voice] })
+            .fold(0, |mask, voice| mask | 1 << voice)
+    }
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
 pub struct Config {
     pub digiboost_enabled: bool,
     pub allow_external_connections: bool,
     pub audio_device_number: Option<i32>,
     pub filter_bias_6581: Option<i32>,
     pub default_filter_bias_6581: i32,
-    pub launch_at_start_enabled: bool
+    pub launch_at_start_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub theme: ThemeSetting,
+
+    #[serde(default = "default_port")]
+    #[builder(default = DEFAULT_PORT)]
+    pub port: u16,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub mixer: Mixer,
+
+    #[serde(default = "default_master_volume")]
+    #[builder(default = MAX_MASTER_VOLUME)]
+    pub master_volume: i32,
+
+    #[serde(default = "default_digiboost_level")]
+    #[builder(default = MAX_DIGIBOOST_LEVEL)]
+    pub digiboost_level: i32,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub last_update_check: Option<u64>,
+
+    #[serde(default = "default_update_check_enabled")]
+    #[builder(default = true)]
+    pub update_check_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub skipped_version: Option<ReleaseVersion>,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub audio_stream_enabled: bool,
+
+    #[serde(default = "default_audio_stream_port")]
+    #[builder(default = DEFAULT_AUDIO_STREAM_PORT)]
+    pub audio_stream_port: u16,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub osc_enabled: bool,
+
+    #[serde(default = "default_osc_port")]
+    #[builder(default = DEFAULT_OSC_PORT)]
+    pub osc_port: u16,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub paired_hosts: [Option<IpAddr>; MAX_PAIRED_HOSTS],
+
+    #[serde(default)]
+    #[builder(default)]
+    pub pairing_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub bind_address: Option<IpAddr>,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub tcp_nodelay_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub keepalive_enabled: bool,
+
+    #[serde(default = "default_keepalive_interval")]
+    #[builder(default = DEFAULT_KEEPALIVE_INTERVAL_IN_SEC)]
+    pub keepalive_interval_in_sec: u32,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub deferred_responses_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub low_power_mode_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub low_latency_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub audio_latency: Option<i32>,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub sample_rate: Option<i32>,
+
+    // the channels of the audio device the left and right output of every SID go to
+    #[serde(default = "default_output_channels")]
+    #[builder(default = default_output_channels())]
+    pub output_channels: [[i32; 2]; MAX_SID_COUNT],
+
+    // unlike the mixer, the gain trims are always persisted
+    #[serde(default = "default_sid_gain_trims")]
+    #[builder(default = [100; MAX_SID_COUNT])]
+    pub sid_gain_trims: [i32; MAX_SID_COUNT],
+
+    #[serde(default)]
+    #[builder(default)]
+    pub model_comparison_balance: Option<i32>,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub loudness_normalization_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub soft_clipping_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub old_8580_filter_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub external_input_enabled: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub external_input_device_number: Option<i32>,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub audio_host: Option<String>,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub forward_address: Option<SocketAddr>,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub forward_render_locally: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub webhook_url: Option<String>,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub learned_buffer_targets: HashMap<String, u32>,
+
+    // the register transforms can only be set in the config file
+    #[serde(default)]
+    #[builder(default)]
+    pub register_transforms: Vec<RegisterTransform>,
+
+    // a relative path of a register script is relative to the config dir
+    #[serde(default)]
+    #[builder(default)]
+    pub register_script: Option<PathBuf>
 }
 
 impl Config {
-    pub fn new(
-        digiboost_enabled: bool,
-        launch_at_start_enabled: bool,
-        allow_external_connections: bool,
-        audio_device_number: Option<i32>,
-        filter_bias_6581: Option<i32>,
-        default_filter_bias_6581: i32
-    ) -> Config {
-        Config {
-            digiboost_enabled,
-            launch_at_start_enabled,
-            allow_external_connections,
-            audio_device_number,
-            filter_bias_6581,
-            default_filter_bias_6581
+    // without pairing every host that is allowed to connect counts as paired,
+    // so clients that don't know the TryPair command can still play
+    pub fn is_paired_host(&self, address: IpAddr) -> bool {
+        (!self.pairing_enabled && self.allow_external_connections) || self.paired_hosts.contains(&Some(address))
+    }
+
+    pub fn add_paired_host(&mut self, address: IpAddr) {
+        if self.paired_hosts.contains(&Some(address)) {
+            return;
+        }
+
+        // the oldest paired host is forgotten when all slots are taken
+        self.paired_hosts.rotate_right(1);
+        self.paired_hosts[0] = Some(address);
+    }
+}
+
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+fn default_audio_stream_port() -> u16 {
+    DEFAULT_AUDIO_STREAM_PORT
+}
+
+fn default_osc_port() -> u16 {
+    DEFAULT_OSC_PORT
+}
+
+fn default_keepalive_interval() -> u32 {
+    DEFAULT_KEEPALIVE_INTERVAL_IN_SEC
+}
+
+fn default_update_check_enabled() -> bool {
+    true
+}
+
+fn default_master_volume() -> i32 {
+    MAX_MASTER_VOLUME
+}
+
+fn default_digiboost_level() -> i32 {
+    MAX_DIGIBOOST_LEVEL
+}
+
+fn default_output_channels() -> [[i32; 2]; MAX_SID_COUNT] {
+    [[0, 1]; MAX_SID_COUNT]
+}
+
+fn default_sid_gain_trims() -> [i32; MAX_SID_COUNT] {
+    [100; MAX_SID_COUNT]
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SettingsError {
+    OutOfRange { setting: &'static str, min: i32, max: i32 },
+    DeviceMissing { device_index: i32 },
+    AudioHostMissing { host_index: i32 },
+    SampleRateNotSupported { sample_rate: i32 },
+    LaunchAtStartFailed { message: String },
+    PortBusy { port: u16 }
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::OutOfRange { setting, min, max } => write!(f, "{} should be in range {}..{}", setting, min, max),
+            SettingsError::DeviceMissing { device_index } => write!(f, "Audio device {} is not available", device_index),
+            SettingsError::AudioHostMissing { host_index } => write!(f, "Audio host {} is not available", host_index),
+            SettingsError::SampleRateNotSupported { sample_rate } => write!(f, "Sample rate {} is not supported", sample_rate),
+            SettingsError::LaunchAtStartFailed { message } => write!(f, "Launch at startup could not be changed: {}", message),
+            SettingsError::PortBusy { port } => write!(f, "Port {} is already in use", port)
         }
     }
 }
@@ -116,23 +411,29 @@ impl Settings {
         self.save_config();
     }
 
-    pub fn toggle_launch_at_start(&mut self) -> bool {
-        let auto_launch_enabled = self.auto_launch.is_enabled().unwrap();
+    pub fn toggle_launch_at_start(&mut self) -> Result<bool, SettingsError> {
+        let to_settings_error = |error: auto_launch::Error| SettingsError::LaunchAtStartFailed { message: error.to_string() };
+
+        let auto_launch_enabled = self.auto_launch.is_enabled().map_err(to_settings_error)?;
         if auto_launch_enabled {
-            self.auto_launch.disable().unwrap();
+            self.auto_launch.disable().map_err(to_settings_error)?;
         } else {
-            self.auto_launch.enable().unwrap();
+            self.auto_launch.enable().map_err(to_settings_error)?;
         }
 
         let mut config = self.config.lock();
         config.launch_at_start_enabled = !auto_launch_enabled;
 
-        !auto_launch_enabled
+        Ok(!auto_launch_enabled)
+    }
+
+    pub fn get_config_dir() -> PathBuf {
+        app_root(AppDataType::UserConfig, &APP_INFO).unwrap()
     }
 
     fn get_config_filename() -> PathBuf {
-        let app_root = app_root(AppDataType::UserConfig, &APP_INFO).unwrap();
-        let path = Path::new(app_root.as_os_str());
+        let config_dir = Self::get_config_dir();
+        let path = Path::new(config_dir.as_os_str());
         path.join(CONFIG_FILE_NAME)
     }
 
@@ -149,6 +450,10 @@ impl Settings {
                 }
                 config.default_filter_bias_6581 = DEFAULT_FILTER_BIAS_6581;
 
+                if !config.mixer.persisted {
+                    config.mixer = Mixer::default();
+                }
+
                 config.launch_at_start_enabled = auto_launch_enabled;
                 return config;
             }
@@ -156,14 +461,45 @@ impl Settings {
         Self::get_default_config(auto_launch_enabled)
     }
 
-    fn get_default_config(auto_launch_enabled: bool) -> Config {
-        Config::new(
-            false,
-            auto_launch_enabled,
-            false,
-            None,
-            Some(DEFAULT_FILTER_BIAS_6581),
-            DEFAULT_FILTER_BIAS_6581
-        )
+    pub(crate) fn get_default_config(auto_launch_enabled: bool) -> Config {
+        Config::builder()
+            .digiboost_enabled(false)
+            .launch_at_start_enabled(auto_launch_enabled)
+            .allow_external_connections(false)
+            .audio_device_number(None)
+            .filter_bias_6581(Some(DEFAULT_FILTER_BIAS_6581))
+            .default_filter_bias_6581(DEFAULT_FILTER_BIAS_6581)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const REMOTE_HOST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+
+    #[test]
+    fn without_pairing_allowed_hosts_are_paired() {
+        let mut config = Settings::get_default_config(false);
+        assert!(!config.pairing_enabled);
+        assert!(!config.is_paired_host(REMOTE_HOST));
+
+        config.allow_external_connections = true;
+        assert!(config.is_paired_host(REMOTE_HOST));
+    }
+
+    #[test]
+    fn with_pairing_only_paired_hosts_are_paired() {
+        let mut config = Settings::get_default_config(false);
+        config.allow_external_connections = true;
+        config.pairing_enabled = true;
+        assert!(!config.is_paired_host(REMOTE_HOST));
+
+        config.add_paired_host(REMOTE_HOST);
+        assert!(config.is_paired_host(REMOTE_HOST));
+        assert!(!config.is_paired_host(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 11))));
     }
 }
@@ -1,433 +1,322 @@
 // Copyright (C) 2021 - 2022 Wilfred Bos
 // Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
 
-mod player;
-
-use std::io::{self, ErrorKind, Read, Write};
-use std::net::{TcpListener, TcpStream, Shutdown};
+mod audio_stream_server;
+mod buffer_tuner;
+mod now_playing;
+mod osc_server;
+mod pairing;
+mod self_test;
+
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
-use std::{thread, time::Duration};
 
-use async_broadcast::Receiver;
 use parking_lot::Mutex;
+use sid_device_core::player::{enable_low_latency, set_output_latency, set_output_sample_rate, Player};
+use sid_device_core::server::{RegisterTransform, ServerEvent, ServerHandler, ServerSettings};
 
-use player::Player;
+use buffer_tuner::BUFFER_TUNER;
 use crate::{Config, SettingsCommand};
+use crate::log;
+use crate::settings::{MAX_SID_COUNT, Mixer, Settings};
+use crate::utils::capture::CAPTURE;
+use crate::utils::port;
+use crate::utils::webhook::{WEBHOOK, WebhookEvent};
+
+pub use audio_stream_server::start_audio_stream_thread;
+pub use buffer_tuner::start_buffer_tuner_thread;
+pub use now_playing::NOW_PLAYING;
+pub use osc_server::start_osc_thread;
+pub use pairing::{PAIRING, PairingEvent};
+pub use self_test::run_self_test;
+pub use sid_device_core::buffer_health::BufferHealthMonitor;
+pub use sid_device_core::player::AUDIO_MUTED;
+pub use sid_device_core::server::{ConnectionStatsMonitor, ServerControl, ServerError, SidDeviceServer};
+
+// has to be above the minimum drain target of the player, otherwise low power mode has no effect
+const LOW_POWER_BUFFER_TARGET: u32 = 1_000_000;
+
+// connects the server of the core to the config, the pairing dialogs and the webhook
+pub struct AppServerHandler {
+    config: Arc<Mutex<Config>>
+}
 
-const LOCAL_HOST: &str = "127.0.0.1";
-const ALLOW_ALL_HOST: &str = "0.0.0.0";
-const DEFAULT_PORT_NUMBER: &str = "6581";
+impl AppServerHandler {
+    pub fn new(config: Arc<Mutex<Config>>) -> AppServerHandler {
+        AppServerHandler {
+            config
+        }
+    }
+}
 
-const PROTOCOL_VERSION: u8 = 4;
-const NUMBER_OF_DEVICES: u8 = 2;
-const SID_WRITE_SIZE: usize = 4;
+impl ServerHandler for AppServerHandler {
+    type Command = (SettingsCommand, Option<i32>);
+
+    fn settings(&self) -> ServerSettings {
+        let config = self.config.lock();
+
+        ServerSettings {
+            host: get_host(&config),
+            port: config.port,
+            forward_address: config.forward_address,
+            forward_render_locally: config.forward_render_locally,
+            tcp_nodelay_enabled: config.tcp_nodelay_enabled,
+            keepalive_enabled: config.keepalive_enabled,
+            keepalive_interval_in_sec: config.keepalive_interval_in_sec,
+            deferred_responses_enabled: config.deferred_responses_enabled,
+            min_buffer_target: get_min_buffer_target(&config, BUFFER_TUNER.get_target())
+        }
+    }
 
-#[allow(dead_code)]
-#[derive(Copy, Clone)]
-pub enum SidClock {
-    Pal = 0,
-    Ntsc = 1
-}
+    fn create_player(&self) -> Result<Player, String> {
+        create_player(&self.config.lock())
+    }
 
-#[allow(dead_code)]
-#[derive(Copy, Clone)]
-pub enum SamplingMethod {
-    Best = 0,
-    Fast = 1
-}
+    fn apply_command(&self, player: &mut Player, (command, param1): (SettingsCommand, Option<i32>)) {
+        apply_settings_command(player, &self.config, command, param1);
+    }
 
-#[allow(dead_code)]
-#[derive(Copy, Clone)]
-enum CommandResponse {
-    Ok = 0,
-    Busy,
-    Error,
-    Read,
-    Version,
-    Count,
-    Info
-}
+    fn register_transforms(&self) -> Vec<RegisterTransform> {
+        self.config.lock().register_transforms.clone()
+    }
 
-#[allow(dead_code)]
-#[derive(Copy, Clone)]
-enum Command {
-    Flush = 0,
-    TrySetSidCount,
-    Mute,
-    TryReset,
-    TryDelay,
-    TryWrite,
-    TryRead,
-    GetVersion,
-    TrySetSampling,
-    TrySetClock,
-    GetConfigCount,
-    GetConfigInfo,
-    SetSidPosition,
-    SetSidLevel,
-    TrySetSidModel,
-    SetDelay,
-    SetFadeIn,
-    SetFadeOut,
-    SetPsidHeader
-}
+    fn register_script(&self) -> Option<String> {
+        let path = Settings::get_config_dir().join(self.config.lock().register_script.as_ref()?);
 
-impl Command {
-    pub fn from_u8(value: u8) -> Command {
-        match value {
-            0 => Command::Flush,
-            1 => Command::TrySetSidCount,
-            2 => Command::Mute,
-            3 => Command::TryReset,
-            4 => Command::TryDelay,
-            5 => Command::TryWrite,
-            6 => Command::TryRead,
-            7 => Command::GetVersion,
-            8 => Command::TrySetSampling,
-            9 => Command::TrySetClock,
-            10 => Command::GetConfigCount,
-            11 => Command::GetConfigInfo,
-            12 => Command::SetSidPosition,
-            13 => Command::SetSidLevel,
-            14 => Command::TrySetSidModel,
-            15 => Command::SetDelay,
-            16 => Command::SetFadeIn,
-            17 => Command::SetFadeOut,
-            18 => Command::SetPsidHeader,
-            _ => panic!("Unknown value: {}", value),
-        }
+        fs::read_to_string(&path).map_err(|error| {
+            log!("ERROR: Cannot read register script {}: {}\r", path.display(), error);
+        }).ok()
     }
-}
 
-pub struct SidDeviceServer {
-    config: Arc<Mutex<Config>>,
-    connection_count: Arc<AtomicI32>
-}
+    fn is_paired_host(&self, address: IpAddr) -> bool {
+        self.config.lock().is_paired_host(address)
+    }
 
-impl SidDeviceServer {
-    pub fn new(config: Arc<Mutex<Config>>) -> SidDeviceServer {
-        let connection_count = Arc::new(AtomicI32::new(0));
-        SidDeviceServer {
-            config,
-            connection_count
-        }
+    fn request_pin(&self, address: IpAddr) {
+        PAIRING.request_pin(address);
     }
 
-    pub fn start(
-            &mut self,
-            allow_external_connections: bool,
-            receiver: Receiver<(SettingsCommand, Option<i32>)>,
-            device_ready: Arc<AtomicBool>,
-            quit: Arc<AtomicBool>) -> Result<(), String> {
-        let host = if allow_external_connections {
-            ALLOW_ALL_HOST
-        } else {
-            LOCAL_HOST
-        };
-
-        let listener = TcpListener::bind([host, DEFAULT_PORT_NUMBER].join(":"));
-        if let Err(error) = listener {
-            return Err(
-                if error.kind() == ErrorKind::AddrInUse || error.kind() == ErrorKind::PermissionDenied {
-                    "Another SID device seems to be already running on port 6581. Please close it and try again.".to_string()
-                } else {
-                    error.to_string()
-                }
-            );
+    fn verify_pin(&self, address: IpAddr, pin: u16) -> bool {
+        let verified = PAIRING.verify_pin(address, pin);
+        if verified {
+            self.config.lock().add_paired_host(address);
         }
+        verified
+    }
 
-        let listener = listener.unwrap();
-        listener.set_nonblocking(true).expect("Cannot set non-blocking");
-
-        println!("Listening on: {}\r", listener.local_addr().unwrap());
-
-        device_ready.store(true, Ordering::SeqCst);
-
-        loop {
-            match listener.accept() {
-                Ok((stream, address)) => {
-                    println!("New client connected: {}\r", address);
-
-                    let local_quit = quit.clone();
-                    let receiver_clone: Receiver<(SettingsCommand, Option<i32>)> = receiver.clone();
-                    let local_connection_count = self.connection_count.clone();
-                    let config = self.config.clone();
-
-                    let _ = thread::spawn(move || {
-                        local_connection_count.fetch_add(1, Ordering::SeqCst);
-                        let mut sid_device_thread = SidDeviceServerThread::new(config);
-                        sid_device_thread.handle_client(stream, receiver_clone, local_quit);
-                        local_connection_count.fetch_sub(1, Ordering::SeqCst);
-                    });
-                }
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    if quit.load(Ordering::SeqCst) {
-                        println!("User interruption. Quitting...\r");
-                        break;
-                    }
-                    thread::sleep(Duration::from_millis(10));
-                    continue;
-                }
-                Err(e) => {
-                    println!("ERROR: {}\r", e);
-                    break;
-                }
+    fn notify(&self, event: ServerEvent) {
+        match event {
+            ServerEvent::ClientConnected(address) => {
+                WEBHOOK.notify(WebhookEvent::ClientConnected { address: address.to_string() });
+            }
+            ServerEvent::ClientDisconnected(address) => {
+                WEBHOOK.notify(WebhookEvent::ClientDisconnected { address: address.to_string() });
+                NOW_PLAYING.clear(address.ip());
+            }
+            ServerEvent::TuneChanged { address, tune_info } => {
+                WEBHOOK.notify(WebhookEvent::NowPlaying {
+                    title: tune_info.title.clone(),
+                    author: tune_info.author.clone(),
+                    released: tune_info.released.clone()
+                });
+                NOW_PLAYING.set(address, tune_info);
+            }
+            ServerEvent::AudioError => {
+                WEBHOOK.notify(WebhookEvent::Error { message: "Audio error occurred".to_string() });
             }
         }
+    }
 
-        // wait for connections to close
-        while self.connection_count.load(Ordering::SeqCst) > 0 {
-            thread::sleep(Duration::from_millis(10));
-        }
-        Ok(())
+    fn record_writes(&self, client_address: SocketAddr, data: &[u8]) {
+        CAPTURE.record_writes(client_address, data);
+    }
+
+    fn record_delay(&self, client_address: SocketAddr, sid_number: u8, cycles: u16) {
+        CAPTURE.record_delay(client_address, sid_number, cycles);
     }
-}
 
-pub struct SidDeviceServerThread {
-    player: Player
+    fn find_port_owner(&self, port: u16) -> Option<String> {
+        port::find_port_owner(port)
+    }
 }
 
-impl SidDeviceServerThread {
-    pub fn new(config: Arc<Mutex<Config>>) -> SidDeviceServerThread {
-        let config = config.lock();
-        let device_numer = config.audio_device_number;
+fn create_player(config: &Config) -> Result<Player, String> {
+    // the buffer size and the sample rate are set before the player opens the output stream
+    enable_low_latency(config.low_latency_enabled);
+    set_output_latency(config.audio_latency);
+    set_output_sample_rate(config.sample_rate);
+
+    let mut player = Player::new(config.audio_device_number)?;
+    player.enable_digiboost(config.digiboost_enabled);
+    player.set_digiboost_level(config.digiboost_level);
+    player.set_external_input(config.external_input_enabled, config.external_input_device_number);
+    player.enable_old_8580_filter(config.old_8580_filter_enabled);
+    player.enable_low_power_mode(config.low_power_mode_enabled);
+    player.set_latency(config.audio_latency);
+    player.set_filter_bias_6581(config.filter_bias_6581);
+    set_mixer(&mut player, &config.mixer);
+    set_output_channels(&mut player, &config.output_channels);
+    set_sid_gain_trims(&mut player, &config.sid_gain_trims);
+    player.set_master_volume(config.master_volume);
+    set_model_comparison(&mut player, config.model_comparison_balance);
+    player.enable_loudness_normalization(config.loudness_normalization_enabled);
+    player.enable_soft_clipping(config.soft_clipping_enabled);
+    Ok(player)
+}
 
-        let mut player = Player::new(device_numer);
-        player.enable_digiboost(config.digiboost_enabled);
-        player.set_filter_bias_6581(config.filter_bias_6581);
+fn apply_settings_command(player: &mut Player, config: &Arc<Mutex<Config>>, command: SettingsCommand, param1: Option<i32>) {
+    match command {
+        SettingsCommand::SetAudioDevice => {
+            player.set_audio_device(param1);
 
-        SidDeviceServerThread {
-            player
+            // the input device is opened with the audio host of the output device, which may have changed
+            set_external_input(player, config);
         }
-    }
-
-    fn handle_client(&mut self, mut stream: TcpStream, mut receiver: Receiver<(SettingsCommand, Option<i32>)>, quit: Arc<AtomicBool>) {
-        let mut data = [0u8; 4096];
-        stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
-        stream.set_write_timeout(Some(Duration::from_millis(100))).unwrap();
-        stream.set_nonblocking(false).unwrap();
-
-        loop {
-            if quit.load(Ordering::SeqCst) {
-                stream.shutdown(Shutdown::Both).unwrap();
-                self.player.flush();
-                break;
-            }
-
-            if let Ok((command, param1)) = receiver.try_recv() {
-                match command {
-                    SettingsCommand::SetAudioDevice => {
-                        self.player.set_audio_device(param1);
-                    }
-                    SettingsCommand::EnableDigiboost => {
-                        self.player.enable_digiboost(true);
-                    }
-                    SettingsCommand::DisableDigiboost => {
-                        self.player.enable_digiboost(false);
-                    }
-                    SettingsCommand::FilterBias6581 => {
-                        self.player.set_filter_bias_6581(param1);
-                    }
-                }
+        SettingsCommand::EnableDigiboost => {
+            player.enable_digiboost(true);
+        }
+        SettingsCommand::DisableDigiboost => {
+            player.enable_digiboost(false);
+        }
+        SettingsCommand::SetDigiboostLevel => {
+            if let Some(level) = param1 {
+                player.set_digiboost_level(level);
             }
-
-            match stream.read(&mut data) {
-                Ok(size) => {
-                    if size >= 4 {
-                        self.process_command(&mut stream, &data[0..size]).unwrap();
-                    } else if size == 0 {
-                        println!("Client disconnected: {}\r", stream.peer_addr().unwrap());
-                        stream.shutdown(Shutdown::Both).unwrap();
-                        break;
-                    }
-                }
-                Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => {
-                    continue;
-                }
-                Err(e) => {
-                    println!("ERROR: {}, {:?}\r", e, e.kind());
-                    println!("Terminating connection for client: {}\r", stream.peer_addr().unwrap());
-                    stream.shutdown(Shutdown::Both).unwrap();
-                    break;
-                }
+        }
+        SettingsCommand::SetExternalInput => {
+            set_external_input(player, config);
+        }
+        SettingsCommand::EnableOld8580Filter => {
+            player.enable_old_8580_filter(true);
+        }
+        SettingsCommand::DisableOld8580Filter => {
+            player.enable_old_8580_filter(false);
+        }
+        SettingsCommand::EnableLowPowerMode => {
+            player.enable_low_power_mode(true);
+        }
+        SettingsCommand::DisableLowPowerMode => {
+            player.enable_low_power_mode(false);
+        }
+        SettingsCommand::EnableLowLatency => {
+            enable_low_latency(true);
+        }
+        SettingsCommand::DisableLowLatency => {
+            enable_low_latency(false);
+        }
+        SettingsCommand::SetAudioLatency => {
+            set_output_latency(param1);
+            player.set_latency(param1);
+        }
+        SettingsCommand::SetSampleRate => {
+            set_output_sample_rate(param1);
+        }
+        SettingsCommand::SetOutputChannels => {
+            let output_channels = config.lock().output_channels;
+            set_output_channels(player, &output_channels);
+        }
+        SettingsCommand::SetSidGainTrims => {
+            let sid_gain_trims = config.lock().sid_gain_trims;
+            set_sid_gain_trims(player, &sid_gain_trims);
+        }
+        SettingsCommand::FilterBias6581 => {
+            player.set_filter_bias_6581(param1);
+        }
+        SettingsCommand::SetMasterVolume => {
+            if let Some(volume) = param1 {
+                player.set_master_volume(volume);
             }
         }
+        SettingsCommand::SetMixer => {
+            let mixer = config.lock().mixer;
+            set_mixer(player, &mixer);
+        }
+        SettingsCommand::SetModelComparison => {
+            set_model_comparison(player, param1);
+        }
+        SettingsCommand::EnableLoudnessNormalization => {
+            player.enable_loudness_normalization(true);
+        }
+        SettingsCommand::DisableLoudnessNormalization => {
+            player.enable_loudness_normalization(false);
+        }
+        SettingsCommand::EnableSoftClipping => {
+            player.enable_soft_clipping(true);
+        }
+        SettingsCommand::DisableSoftClipping => {
+            player.enable_soft_clipping(false);
+        }
     }
+}
 
-    fn process_command(&mut self, stream: &mut TcpStream, data: &[u8]) -> io::Result<()> {
-        let command: Command = Command::from_u8(data[0]);
+fn set_external_input(player: &mut Player, config: &Arc<Mutex<Config>>) {
+    let (enabled, input_device_number) = {
+        let config = config.lock();
+        (config.external_input_enabled, config.external_input_device_number)
+    };
+    player.set_external_input(enabled, input_device_number);
+}
 
-        let sid_number: u8 = data[1];
-        let data_length: usize = ((data[2] as usize) << 8) + (data[3] as usize);
+// without a balance the comparison is off
+fn set_model_comparison(player: &mut Player, balance: Option<i32>) {
+    if let Some(balance) = balance {
+        player.set_model_comparison_balance(balance);
+    }
+    player.enable_model_comparison(balance.is_some());
+}
 
-        if data_length > data.len() - 4 && !matches!(command, Command::Flush) {
-            println!("ERROR: Not all data is retrieved. {} {} {}\r", command as u8, data_length, data.len() - 4);
-            stream.write_all(&[CommandResponse::Error as u8])?;
-            stream.flush()?;
-            return Ok(());
-        }
+fn set_mixer(player: &mut Player, mixer: &Mixer) {
+    for (sid_number, channel) in mixer.channels.iter().enumerate() {
+        let voice_mask = mixer.get_voice_mask(sid_number) as i32;
+        player.set_mixer_channel(sid_number as i32, channel.gain, channel.pan, voice_mask);
+    }
+}
 
-        match command {
-            Command::TryWrite => {
-                if self.player.has_error() {
-                    println!("ERROR: Audio error occurred.\r");
-                    stream.shutdown(Shutdown::Both)?;
-                } else if data_length % 4 != 0 {
-                    println!("ERROR: TryWrite write data size for write data.\r");
-                    stream.write_all(&[CommandResponse::Error as u8])?;
-                } else if !self.player.has_max_data_in_buffer() {
-                    if data_length >= 4 {
-                        let _ = self.process_writes(&data[4..]);
-                    }
-                    stream.write_all(&[CommandResponse::Ok as u8])?;
-                } else {
-                    stream.write_all(&[CommandResponse::Busy as u8])?;
-                }
-            }
-            Command::TryRead => {
-                if self.player.has_error() {
-                    println!("ERROR: Audio error occurred.\r");
-                    stream.shutdown(Shutdown::Both)?;
-                } else if data_length < 3 || (data_length - 3) % 4 != 0 {
-                    println!("ERROR: TryRead missing read data.\r");
-                    stream.write_all(&[CommandResponse::Error as u8])?;
-                } else if !self.player.has_max_data_in_buffer() {
-                    let read_value = self.process_writes(&data[4..]);
-                    stream.write_all(&[CommandResponse::Read as u8, read_value])?;
-                } else {
-                    stream.write_all(&[CommandResponse::Busy as u8])?;
-                }
-            }
-            Command::TryDelay => {
-                if self.player.has_error() {
-                    println!("ERROR: Audio error occurred.\r");
-                    stream.shutdown(Shutdown::Both)?;
-                } else if data_length < 2 {
-                    println!("ERROR: TryDelay missing cycle data.\r");
-                    stream.write_all(&[CommandResponse::Error as u8])?;
-                } else if !self.player.has_max_data_in_buffer() {
-                    let cycles = ((data[4] as u16) << 8) + data[5] as u16;
-                    self.player.write_to_sid(0x1e + sid_number * 0x20, 0, cycles);
-                    if self.player.has_min_data_in_buffer() {
-                        self.player.start_draining();
-                    }
-                    stream.write_all(&[CommandResponse::Ok as u8])?;
-                } else {
-                    stream.write_all(&[CommandResponse::Busy as u8])?;
-                }
-            }
-            Command::TryReset => {
-                if data_length == 1 {
-                    if !self.player.has_max_data_in_buffer() {
-                        self.player.reset();
-                        stream.write_all(&[CommandResponse::Ok as u8])?;
-                    } else {
-                        stream.write_all(&[CommandResponse::Busy as u8])?;
-                    }
-                } else {
-                    println!("ERROR: TryReset missing data for volume.\r");
-                    stream.write_all(&[CommandResponse::Error as u8])?;
-                }
-            }
-            Command::GetVersion => {
-                stream.write_all(&[CommandResponse::Version as u8, PROTOCOL_VERSION])?;
-            }
-            Command::GetConfigCount => {
-                stream.write_all(&[CommandResponse::Count as u8, NUMBER_OF_DEVICES])?;
-            }
-            Command::GetConfigInfo => {
-                let mut response = vec![CommandResponse::Info as u8, sid_number & 0x01];
-                if sid_number == 0 {
-                    response.append(&mut b"reSID Device (6581)\0".to_vec());
-                } else {
-                    response.append(&mut b"reSID Device (8580)\0".to_vec());
-                }
-                stream.write_all(response.as_slice())?;
-            }
-            Command::Flush => {
-                self.player.flush();
-                stream.write_all(&[CommandResponse::Ok as u8])?;
-            }
-            Command::TrySetSidCount => {
-                if sid_number > 0 && sid_number <= 8 {
-                    self.player.set_sid_count(sid_number as i32);
-                    stream.write_all(&[CommandResponse::Ok as u8])?;
-                } else {
-                    println!("ERROR: TrySetSidCount sid count should be in range 1..8.\r");
-                    stream.write_all(&[CommandResponse::Error as u8])?;
-                }
-            }
-            Command::TrySetSidModel => {
-                if data_length == 1 {
-                    let sid_model = data[4];
-                    self.player.set_model(((sid_number as i32) << 8) | sid_model as i32);
-                    stream.write_all(&[CommandResponse::Ok as u8])?;
-                } else {
-                    println!("ERROR: TrySetSidModel missing data for SID model.\r");
-                    stream.write_all(&[CommandResponse::Error as u8])?;
-                }
-            }
-            Command::TrySetClock => {
-                if data_length == 1 {
-                    let sid_clock = data[4];
-                    self.player.set_clock(sid_clock as i32);
-                    stream.write_all(&[CommandResponse::Ok as u8])?;
-                } else {
-                    println!("ERROR: TrySetClock missing data for clock.\r");
-                    stream.write_all(&[CommandResponse::Error as u8])?;
-                }
-            }
-            Command::SetSidPosition => {
-                if data_length == 1 {
-                    let position = data[4];
-                    self.player.set_position(((sid_number as i32) << 8) | position as i32);
-                    stream.write_all(&[CommandResponse::Ok as u8])?;
-                } else {
-                    println!("ERROR: SetSidPosition missing data for SID position.\r");
-                    stream.write_all(&[CommandResponse::Error as u8])?;
-                }
-            }
-            Command::TrySetSampling => {
-                if data_length == 1 {
-                    let sampling_method = data[4];
-                    self.player.set_sampling_method(sampling_method as i32);
-                    stream.write_all(&[CommandResponse::Ok as u8])?;
-                } else {
-                    println!("ERROR: TrySetSampling missing data for sampling method.\r");
-                    stream.write_all(&[CommandResponse::Error as u8])?;
-                }
-            }
-            _ => {
-                // return Ok for not implemented methods
-                stream.write_all(&[CommandResponse::Ok as u8])?;
-            }
-        }
-        stream.flush()?;
-        Ok(())
+fn set_output_channels(player: &mut Player, output_channels: &[[i32; 2]; MAX_SID_COUNT]) {
+    for (sid_number, &[channel_left, channel_right]) in output_channels.iter().enumerate() {
+        player.set_output_channels(sid_number as i32, channel_left, channel_right);
     }
+}
 
-    fn process_writes(&mut self, data: &[u8]) -> u8 {
-        let number_of_sid_writes = data.len() / SID_WRITE_SIZE;
-        let write_data_length = number_of_sid_writes * SID_WRITE_SIZE;
+fn set_sid_gain_trims(player: &mut Player, sid_gain_trims: &[i32; MAX_SID_COUNT]) {
+    for (sid_number, &gain_trim) in sid_gain_trims.iter().enumerate() {
+        player.set_gain_trim(sid_number as i32, gain_trim);
+    }
+}
 
-        for n in (0..write_data_length).step_by(SID_WRITE_SIZE) {
-            let cycles = ((data[n] as u16) << 8) + data[n + 1] as u16;
-            let reg = data[n + 2];
-            let val = data[n + 3];
-            self.player.write_to_sid(reg, val, cycles);
-        }
+pub fn get_host(config: &Config) -> IpAddr {
+    if !config.allow_external_connections {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    } else {
+        config.bind_address.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+}
 
-        if self.player.has_min_data_in_buffer() {
-            self.player.start_draining();
-        }
+fn get_min_buffer_target(config: &Config, tuned_target: u32) -> u32 {
+    // a slow machine needs a larger buffer to ride out hiccups in the emulation
+    let low_power_target = if config.low_power_mode_enabled { LOW_POWER_BUFFER_TARGET } else { 0 };
+    tuned_target.max(low_power_target)
+}
 
-        if data.len() == write_data_length + 3 {
-            let cycles = ((data[write_data_length] as u16) << 8) + data[write_data_length + 1] as u16;
-            let reg = data[write_data_length + 2];
-            self.player.read_from_sid(reg, cycles)
-        } else {
-            0
-        }
+// OSC and the audio stream have no pairing handshake, so remote hosts have to be paired over the SID device protocol first
+pub fn is_allowed_host(config: &Config, address: IpAddr) -> bool {
+    address.is_loopback() || config.is_paired_host(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use sid_device_core::player::MIN_CYCLES_TO_DRAIN_QUEUE;
+
+    use super::*;
+
+    #[test]
+    fn low_power_mode_raises_drain_target() {
+        let mut config = Settings::get_default_config(false);
+        assert!(get_min_buffer_target(&config, 0) <= MIN_CYCLES_TO_DRAIN_QUEUE);
+
+        config.low_power_mode_enabled = true;
+        assert!(get_min_buffer_target(&config, 0) > MIN_CYCLES_TO_DRAIN_QUEUE);
+        assert!(get_min_buffer_target(&config, MIN_CYCLES_TO_DRAIN_QUEUE) > MIN_CYCLES_TO_DRAIN_QUEUE);
     }
 }
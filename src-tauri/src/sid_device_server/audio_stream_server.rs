@@ -0,0 +1,115 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, Shutdown, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::{thread, time::Duration};
+
+use crossbeam_channel::RecvTimeoutError;
+use parking_lot::Mutex;
+use sid_device_core::audio_tap::{AUDIO_TAP, AUDIO_TAP_CHANNELS};
+
+use crate::log;
+use crate::settings::Settings;
+use crate::sid_device_server::{get_host, is_allowed_host};
+use crate::utils::wav;
+
+const SETTINGS_POLL_INTERVAL_IN_MILLIS: u64 = 500;
+const ACCEPT_INTERVAL_IN_MILLIS: u64 = 50;
+const SILENCE_INTERVAL_IN_MILLIS: u64 = 100;
+const REQUEST_TIMEOUT_IN_MILLIS: u64 = 2000;
+
+pub fn start_audio_stream_thread(settings: Arc<Mutex<Settings>>) {
+    let _audio_stream_thread = thread::spawn(move || {
+        let mut failed_stream_settings = None;
+
+        loop {
+            let stream_settings = get_stream_settings(&settings);
+            let (enabled, host, port) = stream_settings;
+
+            if enabled && failed_stream_settings != Some(stream_settings) {
+                match TcpListener::bind((host, port)) {
+                    Ok(listener) => {
+                        failed_stream_settings = None;
+                        if let Ok(address) = listener.local_addr() {
+                            log!("Audio stream available on: http://{}\r", address);
+                        }
+                        accept_listeners(&listener, &settings, stream_settings);
+                    }
+                    Err(error) => {
+                        // only retry binding after the settings have changed
+                        failed_stream_settings = Some(stream_settings);
+                        log!("ERROR: Audio stream port {} could not be opened: {}\r", port, error);
+                    }
+                }
+            }
+
+            thread::sleep(Duration::from_millis(SETTINGS_POLL_INTERVAL_IN_MILLIS));
+        }
+    });
+}
+
+fn get_stream_settings(settings: &Arc<Mutex<Settings>>) -> (bool, IpAddr, u16) {
+    let config = settings.lock().get_config();
+    let config = config.lock();
+    (config.audio_stream_enabled, get_host(&config), config.audio_stream_port)
+}
+
+fn accept_listeners(listener: &TcpListener, settings: &Arc<Mutex<Settings>>, stream_settings: (bool, IpAddr, u16)) {
+    listener.set_nonblocking(true).expect("Cannot set non-blocking");
+
+    // restart the listener when the stream is disabled or moved to another host or port
+    while get_stream_settings(settings) == stream_settings {
+        match listener.accept() {
+            Ok((stream, address)) if !is_allowed_host(&settings.lock().get_config().lock(), address.ip()) => {
+                log!("ERROR: Audio stream listener {} is not paired\r", address);
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            Ok((stream, address)) => {
+                log!("Audio stream listener connected: {}\r", address);
+
+                let _ = thread::spawn(move || {
+                    if let Err(error) = stream_audio(stream) {
+                        log!("Audio stream listener disconnected: {} ({})\r", address, error);
+                    }
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(ACCEPT_INTERVAL_IN_MILLIS));
+            }
+            Err(e) => {
+                log!("ERROR: {}\r", e);
+                break;
+            }
+        }
+    }
+}
+
+fn stream_audio(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+
+    // a listener that never sends its request must not keep the thread alive
+    stream.set_read_timeout(Some(Duration::from_millis(REQUEST_TIMEOUT_IN_MILLIS)))?;
+
+    // the request itself is not relevant, every path serves the same stream
+    let mut request = [0u8; 1024];
+    let _ = stream.read(&mut request)?;
+
+    let sample_rate = AUDIO_TAP.get_sample_rate();
+    let audio_receiver = AUDIO_TAP.subscribe();
+
+    stream.write_all(b"HTTP/1.0 200 OK\r\nContent-Type: audio/wav\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n")?;
+    stream.write_all(&wav::create_header(sample_rate, AUDIO_TAP_CHANNELS, u32::MAX - wav::WAV_HEADER_SIZE as u32))?;
+
+    let silence = vec![0i16; (sample_rate as u64 * SILENCE_INTERVAL_IN_MILLIS / 1000) as usize * AUDIO_TAP_CHANNELS as usize];
+
+    loop {
+        match audio_receiver.recv_timeout(Duration::from_millis(SILENCE_INTERVAL_IN_MILLIS)) {
+            Ok(samples) => stream.write_all(&wav::samples_to_bytes(&samples))?,
+            // keep the connection alive while no client is playing
+            Err(RecvTimeoutError::Timeout) => stream.write_all(&wav::samples_to_bytes(&silence))?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(())
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::{thread, time::Duration};
+
+use parking_lot::Mutex;
+use sid_device_core::buffer_health::BufferHealthMonitor;
+use sid_device_core::player::{get_active_audio_device_name, MAX_CYCLES_TO_DRAIN_QUEUE};
+
+use crate::log;
+use crate::settings::Settings;
+
+const TUNING_INTERVAL_IN_MILLIS: u64 = 1000;
+const MIN_TUNED_CYCLES: u32 = 500_000;
+const STABLE_INTERVALS_TO_SHRINK: u32 = 30;
+
+pub static BUFFER_TUNER: BufferTuner = BufferTuner::new();
+
+// the learned target is a lower bound for the buffer target of every connection
+pub struct BufferTuner {
+    target: AtomicU32
+}
+
+impl BufferTuner {
+    const fn new() -> BufferTuner {
+        BufferTuner {
+            target: AtomicU32::new(0)
+        }
+    }
+
+    pub fn get_target(&self) -> u32 {
+        self.target.load(Ordering::Relaxed)
+    }
+
+    fn set_target(&self, target: u32) {
+        self.target.store(target, Ordering::Relaxed);
+    }
+}
+
+pub fn start_buffer_tuner_thread(settings: Arc<Mutex<Settings>>) {
+    let _buffer_tuner_thread = thread::spawn(move || {
+        let mut buffer_health_monitor = BufferHealthMonitor::new();
+        let mut device_name = None;
+        let mut stable_intervals = 0;
+
+        loop {
+            thread::sleep(Duration::from_millis(TUNING_INTERVAL_IN_MILLIS));
+
+            let snapshot = buffer_health_monitor.take_snapshot();
+            let config = settings.lock().get_config();
+
+            // targets are learned per audio device, since every driver has its own timing
+            let current_device_name = get_active_audio_device_name();
+            if current_device_name.is_none() {
+                continue;
+            }
+
+            if current_device_name != device_name {
+                device_name = current_device_name;
+                stable_intervals = 0;
+
+                let learned_target = device_name.as_ref().and_then(|name| config.lock().learned_buffer_targets.get(name).copied());
+                BUFFER_TUNER.set_target(learned_target.unwrap_or(0));
+                continue;
+            }
+
+            let target = BUFFER_TUNER.get_target();
+            let new_target = if snapshot.underruns_per_sec() > 0.0 {
+                stable_intervals = 0;
+                (target.max(MIN_TUNED_CYCLES) * 5 / 4).min(MAX_CYCLES_TO_DRAIN_QUEUE)
+            } else if snapshot.busy_responses_per_sec() > 0.0 {
+                // a full buffer without underruns is stable, so it may try to shrink toward lower latency
+                stable_intervals += 1;
+                if stable_intervals < STABLE_INTERVALS_TO_SHRINK {
+                    continue;
+                }
+                stable_intervals = 0;
+
+                let shrunk_target = target * 9 / 10;
+                if shrunk_target < MIN_TUNED_CYCLES { 0 } else { shrunk_target }
+            } else {
+                continue;
+            };
+
+            if new_target != target {
+                if new_target > target {
+                    log!("Playback starved, raising learned buffer target to {} cycles\r", new_target);
+                }
+                BUFFER_TUNER.set_target(new_target);
+
+                if let Some(device_name) = &device_name {
+                    config.lock().learned_buffer_targets.insert(device_name.clone(), new_target);
+                    settings.lock().save_config();
+                }
+            }
+        }
+    });
+}
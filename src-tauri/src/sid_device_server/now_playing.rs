@@ -0,0 +1,46 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use parking_lot::{const_mutex, Mutex};
+use sid_device_core::protocol::TuneInfo;
+
+pub static NOW_PLAYING: NowPlaying = NowPlaying::new();
+
+// the tune of the client that sent the last PSID header, until that client disconnects
+pub struct NowPlaying {
+    tune: Mutex<Option<(Option<IpAddr>, TuneInfo)>>,
+    changed: AtomicBool
+}
+
+impl NowPlaying {
+    const fn new() -> NowPlaying {
+        NowPlaying {
+            tune: const_mutex(None),
+            changed: AtomicBool::new(false)
+        }
+    }
+
+    pub fn set(&self, address: Option<IpAddr>, tune_info: TuneInfo) {
+        *self.tune.lock() = Some((address, tune_info));
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self, address: IpAddr) {
+        let mut tune = self.tune.lock();
+        if tune.as_ref().map_or(false, |(tune_address, _)| *tune_address == Some(address)) {
+            *tune = None;
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn get(&self) -> Option<TuneInfo> {
+        self.tune.lock().as_ref().map(|(_, tune_info)| tune_info.clone())
+    }
+
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::io::ErrorKind;
+use std::net::{IpAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::thread;
+
+use async_broadcast::Receiver;
+use parking_lot::Mutex;
+use sid_device_core::MAX_SID_COUNT;
+use sid_device_core::clock::CLOCK;
+use sid_device_core::player::Player;
+use sid_device_core::sid_backend::SidBackend;
+
+use crate::SettingsCommand;
+use crate::log;
+use crate::settings::Settings;
+use crate::sid_device_server::{apply_settings_command, create_player, get_host, is_allowed_host};
+use crate::utils::osc::{self, OscMessage};
+
+const SETTINGS_POLL_INTERVAL_IN_MILLIS: u64 = 500;
+const RECEIVE_TIMEOUT_IN_MILLIS: u64 = 5;
+const MAX_PACKET_SIZE: usize = 4096;
+const PAL_CLOCK_FREQUENCY: u64 = 985_248;
+const LEAD_TIME_IN_MILLIS: u64 = 40;
+const MAX_CLOCK_STEP_IN_MILLIS: u64 = 100;
+const IDLE_TIME_IN_SEC: u64 = 5;
+const REGISTER_COUNT: usize = 0x19;
+const VOICE_COUNT: u8 = 3;
+const VOICE_REGISTER_SIZE: u8 = 7;
+const VOICE_CONTROL_REGISTER: u8 = 4;
+const GATE_BIT: u8 = 0x01;
+
+pub fn start_osc_thread(settings: Arc<Mutex<Settings>>, mut receiver: Receiver<(SettingsCommand, Option<i32>)>) {
+    let _osc_thread = thread::spawn(move || {
+        let mut failed_osc_settings = None;
+
+        loop {
+            let osc_settings = get_osc_settings(&settings);
+            let (enabled, host, port) = osc_settings;
+
+            if enabled && failed_osc_settings != Some(osc_settings) {
+                match UdpSocket::bind((host, port)) {
+                    Ok(socket) => {
+                        failed_osc_settings = None;
+                        if let Ok(address) = socket.local_addr() {
+                            log!("OSC server listening on: {}\r", address);
+                        }
+
+                        if let Err(error) = receive_messages(&socket, &settings, osc_settings, &mut receiver) {
+                            // the audio device is not retried for every message, only after the settings have changed
+                            failed_osc_settings = Some(osc_settings);
+                            log!("ERROR: OSC server stopped: {}\r", error);
+                        }
+                    }
+                    Err(error) => {
+                        // only retry binding after the settings have changed
+                        failed_osc_settings = Some(osc_settings);
+                        log!("ERROR: OSC port {} could not be opened: {}\r", port, error);
+                    }
+                }
+            }
+
+            thread::sleep(Duration::from_millis(SETTINGS_POLL_INTERVAL_IN_MILLIS));
+        }
+    });
+}
+
+fn get_osc_settings(settings: &Arc<Mutex<Settings>>) -> (bool, IpAddr, u16) {
+    let config = settings.lock().get_config();
+    let config = config.lock();
+    (config.osc_enabled, get_host(&config), config.osc_port)
+}
+
+fn receive_messages(
+        socket: &UdpSocket,
+        settings: &Arc<Mutex<Settings>>,
+        osc_settings: (bool, IpAddr, u16),
+        receiver: &mut Receiver<(SettingsCommand, Option<i32>)>) -> Result<(), String> {
+    socket.set_read_timeout(Some(Duration::from_millis(RECEIVE_TIMEOUT_IN_MILLIS))).expect("Cannot set read timeout");
+
+    // the player is only created on the first message, so an idle OSC server does not claim the audio device
+    let mut osc_player: Option<OscPlayer> = None;
+    let mut packet = [0u8; MAX_PACKET_SIZE];
+    let mut last_settings_check = Instant::now();
+
+    loop {
+        // restart the server when it is disabled or moved to another host or port
+        if last_settings_check.elapsed() >= Duration::from_millis(SETTINGS_POLL_INTERVAL_IN_MILLIS) {
+            if get_osc_settings(settings) != osc_settings {
+                return Ok(());
+            }
+            last_settings_check = Instant::now();
+        }
+
+        if let Ok((command, param1)) = receiver.try_recv() {
+            if let Some(osc_player) = osc_player.as_mut() {
+                let config = settings.lock().get_config();
+                apply_settings_command(&mut osc_player.player, &config, command, param1);
+            }
+        }
+
+        match socket.recv_from(&mut packet) {
+            Ok((size, address)) => {
+                if is_allowed_host(&settings.lock().get_config().lock(), address.ip()) {
+                    if osc_player.is_none() {
+                        log!("OSC client connected: {}\r", address);
+                        osc_player = Some(OscPlayer::new(create_player(&settings.lock().get_config().lock())?));
+                    }
+
+                    for message in osc::parse_packet(&packet[0..size]) {
+                        osc_player.as_mut().unwrap().handle_message(&message);
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(e) => return Err(e.to_string())
+        }
+
+        if let Some(osc_player) = osc_player.as_mut() {
+            osc_player.player.check_renderer();
+            osc_player.advance_clock();
+        }
+    }
+}
+
+struct OscPlayer {
+    player: Player,
+    sid_count: usize,
+    registers: [[u8; REGISTER_COUNT]; MAX_SID_COUNT],
+    clock_time: Option<Instant>,
+    last_message: Instant
+}
+
+impl OscPlayer {
+    fn new(player: Player) -> OscPlayer {
+        OscPlayer {
+            player,
+            sid_count: 1,
+            registers: [[0; REGISTER_COUNT]; MAX_SID_COUNT],
+            clock_time: None,
+            last_message: CLOCK.now()
+        }
+    }
+
+    fn handle_message(&mut self, message: &OscMessage) {
+        let path: Vec<&str> = message.address[1..].split('/').collect();
+        let arguments: Vec<i32> = message.arguments.iter().map(|argument| argument.as_i32()).collect();
+
+        let sid_number = match path.get(..2) {
+            Some(["sid", sid_number]) => match sid_number.parse::<usize>() {
+                Ok(sid_number) if sid_number < MAX_SID_COUNT => sid_number,
+                _ => return
+            },
+            _ => return
+        };
+
+        match (&path[2..], arguments.as_slice()) {
+            (["reg", register], [value]) => {
+                if let Ok(register) = register.parse::<u8>() {
+                    if (register as usize) < REGISTER_COUNT {
+                        self.write(sid_number, register, *value as u8);
+                    }
+                }
+            }
+            (["gate"], [voice, gate]) if (0..VOICE_COUNT as i32).contains(voice) => {
+                let register = *voice as u8 * VOICE_REGISTER_SIZE + VOICE_CONTROL_REGISTER;
+                let control = self.registers[sid_number][register as usize];
+                let control = if *gate != 0 { control | GATE_BIT } else { control & !GATE_BIT };
+                self.write(sid_number, register, control);
+            }
+            (["note"], [voice, note]) if (0..VOICE_COUNT as i32).contains(voice) => {
+                let frequency = get_note_frequency(*note);
+                let register = *voice as u8 * VOICE_REGISTER_SIZE;
+                self.write(sid_number, register, (frequency & 0xff) as u8);
+                self.write(sid_number, register + 1, (frequency >> 8) as u8);
+            }
+            _ => {}
+        }
+    }
+
+    fn write(&mut self, sid_number: usize, register: u8, value: u8) {
+        if sid_number >= self.sid_count {
+            // changing the SID count restarts the audio thread and clears the queue
+            self.sid_count = sid_number + 1;
+            self.player.set_sid_count(self.sid_count as i32);
+            self.clock_time = None;
+        }
+
+        if self.clock_time.is_none() {
+            // keep some audio in the buffer, since the clock only advances in real time
+            self.delay(PAL_CLOCK_FREQUENCY * LEAD_TIME_IN_MILLIS / 1000);
+            self.clock_time = Some(CLOCK.now());
+            self.player.start_draining();
+        }
+
+        self.registers[sid_number][register as usize] = value;
+        self.last_message = CLOCK.now();
+
+        // writes without cycles are not applied by the renderer
+        self.player.write_to_sid(((sid_number as u8) << 5) | register, value, 1);
+    }
+
+    fn advance_clock(&mut self) {
+        if let Some(clock_time) = self.clock_time {
+            if CLOCK.now() - self.last_message > Duration::from_secs(IDLE_TIME_IN_SEC) {
+                self.clock_time = None;
+                return;
+            }
+
+            let elapsed = (CLOCK.now() - clock_time).min(Duration::from_millis(MAX_CLOCK_STEP_IN_MILLIS));
+            self.delay(PAL_CLOCK_FREQUENCY * elapsed.as_micros() as u64 / 1_000_000);
+            self.clock_time = Some(CLOCK.now());
+        }
+    }
+
+    fn delay(&mut self, cycles: u64) {
+        let mut cycles = cycles;
+        while cycles > 0 {
+            let step = cycles.min(u16::MAX as u64);
+            SidBackend::delay(&mut self.player, 0, step as u16);
+            cycles -= step;
+        }
+    }
+}
+
+fn get_note_frequency(note: i32) -> u16 {
+    // MIDI note number to the SID frequency register value for a PAL clock
+    let frequency = 440.0 * 2f64.powf((note - 69) as f64 / 12.0);
+    (frequency * 16_777_216.0 / PAL_CLOCK_FREQUENCY as f64).round().min(u16::MAX as f64) as u16
+}
@@ -0,0 +1,95 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use parking_lot::{const_mutex, Mutex};
+use rand::Rng;
+
+use crate::log;
+
+const MAX_PIN: u16 = 9999;
+const MAX_PIN_ATTEMPTS: u32 = 3;
+const MAX_PENDING_PINS: usize = 8;
+const PIN_LIFETIME_IN_SEC: u64 = 120;
+
+pub static PAIRING: Pairing = Pairing::new();
+
+pub enum PairingEvent {
+    PinRequested { address: IpAddr, pin: u16 },
+    Paired(IpAddr)
+}
+
+// the PIN is thrown away after too many wrong guesses, a new PIN is only issued when the old one has expired
+struct PendingPin {
+    address: IpAddr,
+    pin: Option<u16>,
+    attempts: u32,
+    issued_at: Instant
+}
+
+pub struct Pairing {
+    pending_pins: Mutex<Vec<PendingPin>>,
+    events: Mutex<Vec<PairingEvent>>
+}
+
+impl Pairing {
+    const fn new() -> Pairing {
+        Pairing {
+            pending_pins: const_mutex(Vec::new()),
+            events: const_mutex(Vec::new())
+        }
+    }
+
+    pub fn request_pin(&self, address: IpAddr) {
+        let mut pending_pins = self.pending_pins.lock();
+        pending_pins.retain(|pending_pin| pending_pin.issued_at.elapsed() < Duration::from_secs(PIN_LIFETIME_IN_SEC));
+
+        // reconnecting clients keep the PIN that is already shown
+        if pending_pins.iter().any(|pending_pin| pending_pin.address == address) {
+            return;
+        }
+
+        // every PIN raises a dialog, so the number of clients waiting for a PIN is limited
+        if pending_pins.len() >= MAX_PENDING_PINS {
+            log!("ERROR: Too many clients are waiting for a PIN, no PIN issued for {}\r", address);
+            return;
+        }
+
+        let pin = rand::thread_rng().gen_range(0..=MAX_PIN);
+        pending_pins.push(PendingPin { address, pin: Some(pin), attempts: 0, issued_at: Instant::now() });
+        self.events.lock().push(PairingEvent::PinRequested { address, pin });
+    }
+
+    pub fn verify_pin(&self, address: IpAddr, pin: u16) -> bool {
+        let mut pending_pins = self.pending_pins.lock();
+
+        let index = match pending_pins.iter().position(|pending_pin| pending_pin.address == address) {
+            Some(index) => index,
+            None => return false
+        };
+
+        let pending_pin = &mut pending_pins[index];
+        if pending_pin.issued_at.elapsed() >= Duration::from_secs(PIN_LIFETIME_IN_SEC) || pending_pin.pin.is_none() {
+            return false;
+        }
+
+        if pending_pin.pin == Some(pin) {
+            pending_pins.remove(index);
+            self.events.lock().push(PairingEvent::Paired(address));
+            return true;
+        }
+
+        pending_pin.attempts += 1;
+        if pending_pin.attempts >= MAX_PIN_ATTEMPTS {
+            log!("ERROR: Too many wrong PINs from {}, a new PIN is issued after {} seconds\r", address, PIN_LIFETIME_IN_SEC);
+            pending_pin.pin = None;
+        }
+        false
+    }
+
+    pub fn take_events(&self) -> Vec<PairingEvent> {
+        self.events.lock().drain(..).collect()
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sid_device_core::audio_tap::AUDIO_TAP;
+use sid_device_core::protocol::{Command, CommandResponse, PROTOCOL_VERSION};
+
+const CONNECT_TIMEOUT_IN_MILLIS: u64 = 1000;
+const RESPONSE_TIMEOUT_IN_MILLIS: u64 = 6000;
+const BUSY_RETRY_INTERVAL_IN_MILLIS: u64 = 10;
+const BUSY_TIMEOUT_IN_MILLIS: u64 = 5000;
+const SAMPLE_TIMEOUT_IN_MILLIS: u64 = 3000;
+
+const SID_MODEL_6581: u8 = 0;
+const ENV3_REGISTER: u8 = 0x1c;
+const READ_DELAY_IN_CYCLES: u16 = 0x4000;
+const DELAY_IN_CYCLES: u16 = 0xffff;
+const DELAY_COUNT: usize = 10;
+
+// a sawtooth on voice 3 with instant attack and full sustain, so ENV3 is at its maximum when it's read
+const TUNE_FRAGMENT: [(u8, u8); 6] = [
+    (0x18, 0x0f),
+    (0x0e, 0x00),
+    (0x0f, 0x1c),
+    (0x13, 0x00),
+    (0x14, 0xf0),
+    (0x12, 0x21)
+];
+
+// runs a short session against the running server, like a client would, and returns the step that failed
+pub fn run_self_test(address: SocketAddr) -> Result<(), String> {
+    let audio_receiver = AUDIO_TAP.subscribe();
+
+    let timeout = Duration::from_millis(CONNECT_TIMEOUT_IN_MILLIS);
+    let mut stream = TcpStream::connect_timeout(&address, timeout).map_err(|error| format!("Connect: {}", error))?;
+    stream.set_read_timeout(Some(Duration::from_millis(RESPONSE_TIMEOUT_IN_MILLIS))).map_err(|error| error.to_string())?;
+    stream.set_nodelay(true).map_err(|error| error.to_string())?;
+
+    let result = run_session(&mut stream);
+
+    // leaves the device silent, also when a step failed
+    let _ = send_command(&mut stream, Command::TryReset, 0, &[0]);
+    let _ = send_command(&mut stream, Command::Flush, 0, &[]);
+    let _ = stream.shutdown(Shutdown::Both);
+
+    result?;
+
+    if audio_receiver.recv_timeout(Duration::from_millis(SAMPLE_TIMEOUT_IN_MILLIS)).is_err() {
+        return Err("Samples: no audio reached the sound buffer".to_string());
+    }
+    Ok(())
+}
+
+fn run_session(stream: &mut TcpStream) -> Result<(), String> {
+    let response = send_command(stream, Command::GetVersion, 0, &[PROTOCOL_VERSION])?;
+    if response != [CommandResponse::Version as u8, PROTOCOL_VERSION] {
+        return Err(format!("GetVersion: unexpected response {:?}", response));
+    }
+
+    expect_ok("TrySetSidModel", &send_command(stream, Command::TrySetSidModel, 0, &[SID_MODEL_6581])?)?;
+
+    let write_data: Vec<u8> = TUNE_FRAGMENT.iter().flat_map(|&(reg, value)| [0, 8, reg, value]).collect();
+    expect_ok("TryWrite", &send_until_accepted(stream, Command::TryWrite, &write_data)?)?;
+
+    let read_data = [(READ_DELAY_IN_CYCLES >> 8) as u8, READ_DELAY_IN_CYCLES as u8, ENV3_REGISTER];
+    let response = send_until_accepted(stream, Command::TryRead, &read_data)?;
+    match response.as_slice() {
+        [response_code, env3] if *response_code == CommandResponse::Read as u8 && *env3 > 0 => {}
+        _ => return Err(format!("TryRead: unexpected ENV3 response {:?}", response))
+    }
+
+    // fills the buffer far enough for the player to start draining
+    let delay_data = [(DELAY_IN_CYCLES >> 8) as u8, DELAY_IN_CYCLES as u8];
+    for _ in 0..DELAY_COUNT {
+        expect_ok("TryDelay", &send_until_accepted(stream, Command::TryDelay, &delay_data)?)?;
+    }
+    Ok(())
+}
+
+fn expect_ok(step: &str, response: &[u8]) -> Result<(), String> {
+    if response == [CommandResponse::Ok as u8] {
+        Ok(())
+    } else {
+        Err(format!("{}: unexpected response {:?}", step, response))
+    }
+}
+
+fn send_until_accepted(stream: &mut TcpStream, command: Command, data: &[u8]) -> Result<Vec<u8>, String> {
+    let deadline = Instant::now() + Duration::from_millis(BUSY_TIMEOUT_IN_MILLIS);
+
+    loop {
+        let response = send_command(stream, command, 0, data)?;
+        if response != [CommandResponse::Busy as u8] || Instant::now() >= deadline {
+            return Ok(response);
+        }
+        thread::sleep(Duration::from_millis(BUSY_RETRY_INTERVAL_IN_MILLIS));
+    }
+}
+
+fn send_command(stream: &mut TcpStream, command: Command, sid_number: u8, data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut packet = vec![command as u8, sid_number, (data.len() >> 8) as u8, data.len() as u8];
+    packet.extend_from_slice(data);
+    stream.write_all(&packet).map_err(|error| format!("Send: {}", error))?;
+
+    let mut response = vec![0u8; 1];
+    stream.read_exact(&mut response).map_err(|error| format!("Receive: {}", error))?;
+
+    // only the read and version responses carry a value
+    if response[0] == CommandResponse::Read as u8 || response[0] == CommandResponse::Version as u8 {
+        let mut value = [0u8; 1];
+        stream.read_exact(&mut value).map_err(|error| format!("Receive: {}", error))?;
+        response.push(value[0]);
+    }
+    Ok(response)
+}
@@ -2,3 +2,15 @@
 // Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
 
 pub mod audio;
+pub mod capture;
+pub mod diagnostics;
+pub mod file_manager;
+pub mod log;
+pub mod network;
+pub mod osc;
+pub mod port;
+pub mod recorder;
+pub mod runtime_info;
+pub mod update_check;
+pub mod wav;
+pub mod webhook;
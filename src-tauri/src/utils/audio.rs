@@ -3,16 +3,24 @@
 
 use cpal::Device;
 use cpal::traits::{DeviceTrait, HostTrait};
+use sid_device_core::audio_host::{self, AUDIO_HOST};
+
+pub fn get_available_audio_host_names() -> (Vec<String>, i32) {
+    let host_name = AUDIO_HOST.get_host_id().name();
+    let host_names = audio_host::get_host_names();
+    let selected_host = host_names.iter().position(|&name| name == host_name).unwrap_or(0) as i32;
+
+    (host_names.into_iter().map(String::from).collect(), selected_host)
+}
 
 pub fn get_available_audio_output_device_names() -> (Vec<String>, i32) {
-    let host = cpal::default_host();
-    let default_device = host.default_output_device().unwrap();
-    let default_device_name = default_device.name().unwrap();
+    let host = AUDIO_HOST.get_host();
+    let default_device_name = host.default_output_device().and_then(|device| device.name().ok());
 
     let mut default_device = 0_i32;
     let devices = get_available_audio_output_devices().iter().enumerate().map(|(index, device)| {
-        let device_name = device.name().unwrap();
-        if device_name.eq(&default_device_name) {
+        let device_name = device.name().unwrap_or_default();
+        if Some(&device_name) == default_device_name.as_ref() {
             default_device = index as i32;
         }
         device_name
@@ -21,12 +29,30 @@ pub fn get_available_audio_output_device_names() -> (Vec<String>, i32) {
 }
 
 pub fn get_available_audio_output_devices() -> Vec<Device> {
-    let host = cpal::default_host();
+    let host = AUDIO_HOST.get_host();
 
     if let Ok(devices) = host.output_devices() {
         devices.enumerate().map(|(_size, device)| device).collect()
     } else {
-        vec![host.default_output_device().expect("Failed to find a default output device")]
+        host.default_output_device().into_iter().collect()
     }
 }
 
+pub fn get_available_audio_input_device_names() -> Vec<String> {
+    AUDIO_HOST.get_host().input_devices()
+        .map(|devices| devices.map(|device| device.name().unwrap_or_default()).collect())
+        .unwrap_or_default()
+}
+
+// without an audio device the output is stereo
+pub fn get_audio_output_channel_count(audio_device_number: Option<i32>) -> u16 {
+    get_audio_output_device(audio_device_number)
+        .and_then(|device| device.default_output_config().ok())
+        .map_or(2, |device_config| device_config.channels())
+}
+
+pub fn get_audio_output_device(audio_device_number: Option<i32>) -> Option<Device> {
+    audio_device_number
+        .and_then(|device_number| get_available_audio_output_devices().into_iter().nth(device_number as usize))
+        .or_else(|| AUDIO_HOST.get_host().default_output_device())
+}
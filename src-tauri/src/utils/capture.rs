@@ -0,0 +1,150 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::{const_mutex, Mutex};
+use sid_device_core::audio_tap::{AUDIO_TAP, AUDIO_TAP_CHANNELS};
+use tauri::api::path::download_dir;
+
+use crate::settings::Settings;
+use crate::utils::wav;
+
+pub const CAPTURE_LENGTH_IN_SEC: u32 = 30;
+const CYCLES_PER_SEC: u64 = 1_000_000;
+// writes without cycles in between don't count toward the capture length, a SID takes at most one write per cycle
+const MAX_CAPTURED_WRITES: usize = CAPTURE_LENGTH_IN_SEC as usize * CYCLES_PER_SEC as usize;
+const SID_WRITE_SIZE: usize = 4;
+const DELAY_REGISTER: u8 = 0x1e;
+
+pub static CAPTURE: Capture = Capture::new();
+
+#[derive(Default)]
+struct AudioCapture {
+    sample_rate: u32,
+    samples: VecDeque<i16>
+}
+
+#[derive(Default)]
+struct WriteCapture {
+    client_address: Option<SocketAddr>,
+    writes: VecDeque<[u8; SID_WRITE_SIZE]>,
+    cycles: u64
+}
+
+// keeps the last seconds of audio and register writes, so something that just played can still be saved
+pub struct Capture {
+    audio: Mutex<Option<AudioCapture>>,
+    writes: Mutex<Option<WriteCapture>>
+}
+
+impl Capture {
+    const fn new() -> Capture {
+        Capture {
+            audio: const_mutex(None),
+            writes: const_mutex(None)
+        }
+    }
+
+    // the writes are in the same format as the data of the write command, so a client can replay them,
+    // the writes of several clients can't be replayed as one, so only the last client is captured
+    pub fn record_writes(&self, client_address: SocketAddr, data: &[u8]) {
+        let mut capture = self.writes.lock();
+        let capture = capture.get_or_insert_with(WriteCapture::default);
+
+        if capture.client_address != Some(client_address) {
+            capture.client_address = Some(client_address);
+            capture.writes.clear();
+            capture.cycles = 0;
+        }
+
+        for write in data.chunks_exact(SID_WRITE_SIZE) {
+            capture.cycles += (((write[0] as u16) << 8) + write[1] as u16) as u64;
+            capture.writes.push_back([write[0], write[1], write[2], write[3]]);
+        }
+        capture.trim();
+    }
+
+    // a delay is captured as a write to an unused register, like the player does
+    pub fn record_delay(&self, client_address: SocketAddr, sid_number: u8, cycles: u16) {
+        let reg = DELAY_REGISTER.wrapping_add(sid_number.wrapping_mul(0x20));
+        self.record_writes(client_address, &[(cycles >> 8) as u8, cycles as u8, reg, 0]);
+    }
+
+    fn record_samples(&self, samples: &[i16]) {
+        let sample_rate = AUDIO_TAP.get_sample_rate();
+        let mut capture = self.audio.lock();
+        let capture = capture.get_or_insert_with(AudioCapture::default);
+
+        // samples of another rate can't be stored in the same file
+        if capture.sample_rate != sample_rate {
+            capture.sample_rate = sample_rate;
+            capture.samples.clear();
+        }
+
+        capture.samples.extend(samples.iter());
+
+        let max_samples = (sample_rate * CAPTURE_LENGTH_IN_SEC) as usize * AUDIO_TAP_CHANNELS as usize;
+        if capture.samples.len() > max_samples {
+            let excess = capture.samples.len() - max_samples;
+            capture.samples.drain(..excess);
+        }
+    }
+
+    pub fn save(&self) -> Result<(PathBuf, PathBuf), String> {
+        let (sample_rate, samples) = self.audio.lock().as_ref()
+            .map_or((0, Vec::new()), |capture| (capture.sample_rate, capture.samples.iter().copied().collect::<Vec<i16>>()));
+        let writes: Vec<u8> = self.writes.lock().as_ref()
+            .map_or(Vec::new(), |capture| capture.writes.iter().flatten().copied().collect());
+
+        if samples.is_empty() && writes.is_empty() {
+            return Err("Nothing was played yet".to_string());
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        let output_dir = download_dir().unwrap_or_else(Settings::get_config_dir);
+        let wav_filename = output_dir.join(format!("sid-device-capture-{}.wav", timestamp));
+        let writes_filename = output_dir.join(format!("sid-device-capture-{}.sidwrites", timestamp));
+
+        let audio_data = wav::samples_to_bytes(&samples);
+        let mut wav_file = BufWriter::new(File::create(&wav_filename).map_err(|error| error.to_string())?);
+        wav_file.write_all(&wav::create_header(sample_rate.max(1), AUDIO_TAP_CHANNELS, audio_data.len() as u32)).map_err(|error| error.to_string())?;
+        wav_file.write_all(&audio_data).map_err(|error| error.to_string())?;
+        wav_file.flush().map_err(|error| error.to_string())?;
+
+        let mut writes_file = BufWriter::new(File::create(&writes_filename).map_err(|error| error.to_string())?);
+        writes_file.write_all(&writes).map_err(|error| error.to_string())?;
+        writes_file.flush().map_err(|error| error.to_string())?;
+
+        Ok((wav_filename, writes_filename))
+    }
+}
+
+impl WriteCapture {
+    fn trim(&mut self) {
+        let max_cycles = CAPTURE_LENGTH_IN_SEC as u64 * CYCLES_PER_SEC;
+
+        while self.cycles > max_cycles || self.writes.len() > MAX_CAPTURED_WRITES {
+            match self.writes.pop_front() {
+                Some(write) => self.cycles -= (((write[0] as u16) << 8) + write[1] as u16) as u64,
+                None => break
+            }
+        }
+    }
+}
+
+pub fn start_capture_thread() {
+    let audio_receiver = AUDIO_TAP.subscribe();
+
+    let _capture_thread = thread::spawn(move || {
+        for samples in audio_receiver.iter() {
+            CAPTURE.record_samples(&samples);
+        }
+    });
+}
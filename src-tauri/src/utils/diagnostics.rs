@@ -0,0 +1,84 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use sid_device_core::buffer_health::{BUFFER_HEALTH, BufferHealthMonitor};
+use tauri::api::path::download_dir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::settings::{Config, Settings};
+use crate::utils::audio;
+use crate::utils::log::LOG;
+use crate::utils::runtime_info::RuntimeInfo;
+
+const DIAGNOSTICS_FILE_NAME: &str = "sid-device-diagnostics.zip";
+const DIAGNOSTICS_LOG_LINES: usize = 200;
+const REDACTED: &str = "<redacted>";
+
+// webhook URLs usually carry an access token and the addresses tell about the network of the user
+const REDACTED_CONFIG_FIELDS: [&str; 4] = ["webhook_url", "paired_hosts", "forward_address", "bind_address"];
+
+pub fn export_diagnostics(config: &Config, runtime_info: &RuntimeInfo) -> Result<PathBuf, String> {
+    let (devices, default_device) = audio::get_available_audio_output_device_names();
+
+    let mut config = serde_json::to_value(config).map_err(|error| error.to_string())?;
+    for field in REDACTED_CONFIG_FIELDS {
+        if let Some(value) = config.get_mut(field) {
+            redact(value);
+        }
+    }
+
+    let entries = [
+        ("log.txt", LOG.get_tail(DIAGNOSTICS_LOG_LINES)),
+        ("config.json", to_json(&config)?),
+        ("audio_devices.json", to_json(&json!({
+            "devices": devices,
+            "default_device": default_device
+        }))?),
+        ("buffer_health.json", to_json(&json!({
+            "current": BufferHealthMonitor::new().take_snapshot(),
+            "total_underruns": BUFFER_HEALTH.get_underruns(),
+            "total_busy_responses": BUFFER_HEALTH.get_busy_responses()
+        }))?),
+        ("system.json", to_json(&json!({
+            "os": std::env::consts::OS,
+            "os_family": std::env::consts::FAMILY,
+            "arch": std::env::consts::ARCH,
+            "runtime": runtime_info
+        }))?)
+    ];
+
+    let output_dir = download_dir().unwrap_or_else(Settings::get_config_dir);
+    let filename = output_dir.join(DIAGNOSTICS_FILE_NAME);
+
+    let file = File::create(&filename).map_err(|error| error.to_string())?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (name, content) in entries {
+        zip.start_file(name, options).map_err(|error| error.to_string())?;
+        zip.write_all(content.as_bytes()).map_err(|error| error.to_string())?;
+    }
+    zip.finish().map_err(|error| error.to_string())?;
+
+    Ok(filename)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string_pretty(value).map_err(|error| error.to_string())
+}
+
+// empty values are kept, so it stays visible whether a setting was used
+fn redact(value: &mut Value) {
+    match value {
+        Value::Null => {}
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => *value = Value::String(REDACTED.to_string())
+    }
+}
@@ -0,0 +1,28 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(target_os = "windows")]
+const FILE_MANAGER: &str = "explorer";
+#[cfg(target_os = "macos")]
+const FILE_MANAGER: &str = "open";
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const FILE_MANAGER: &str = "xdg-open";
+
+pub fn open_folder(folder: &Path) -> Result<(), String> {
+    Command::new(FILE_MANAGER)
+        .arg(folder)
+        .spawn()
+        .map(|_| ())
+        .map_err(|error| format!("Could not open {}: {}", folder.display(), error))
+}
+
+pub fn open_url(url: &str) -> Result<(), String> {
+    Command::new(FILE_MANAGER)
+        .arg(url)
+        .spawn()
+        .map(|_| ())
+        .map_err(|error| format!("Could not open {}: {}", url, error))
+}
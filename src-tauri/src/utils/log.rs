@@ -0,0 +1,61 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use parking_lot::{const_mutex, Mutex};
+
+const MAX_LOG_LINES: usize = 500;
+
+pub static LOG: Log = Log::new();
+
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::utils::log::write_line(&format!($($arg)*))
+    };
+}
+
+// for secrets like a pairing PIN, they are shown on the console but not copied or exported with the log
+#[macro_export]
+macro_rules! console {
+    ($($arg:tt)*) => {
+        $crate::utils::log::write_console_line(&format!($($arg)*))
+    };
+}
+
+// the lines of the app and of sid-device-core both end up on the console and in the log
+pub fn write_line(line: &str) {
+    write_console_line(line);
+    LOG.add_line(line);
+}
+
+pub fn write_console_line(line: &str) {
+    println!("{}", line);
+}
+
+pub struct Log {
+    lines: Mutex<Vec<String>>
+}
+
+impl Log {
+    const fn new() -> Log {
+        Log {
+            lines: const_mutex(Vec::new())
+        }
+    }
+
+    fn add_line(&self, line: &str) {
+        let mut lines = self.lines.lock();
+        lines.push(line.trim_end().to_string());
+
+        if lines.len() > MAX_LOG_LINES {
+            let overflow = lines.len() - MAX_LOG_LINES;
+            lines.drain(..overflow);
+        }
+    }
+
+    pub fn get_tail(&self, line_count: usize) -> String {
+        let lines = self.lines.lock();
+        let start = lines.len().saturating_sub(line_count);
+        lines[start..].join("\n")
+    }
+}
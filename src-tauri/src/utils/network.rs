@@ -0,0 +1,22 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::net::IpAddr;
+
+#[derive(serde::Serialize)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub address: IpAddr
+}
+
+pub fn get_network_interfaces() -> Vec<NetworkInterface> {
+    if_addrs::get_if_addrs()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|interface| !interface.is_loopback())
+        .map(|interface| NetworkInterface {
+            address: interface.ip(),
+            name: interface.name
+        })
+        .collect()
+}
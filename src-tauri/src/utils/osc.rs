@@ -0,0 +1,103 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+const BUNDLE_HEADER_SIZE: usize = 16;
+
+pub enum OscArgument {
+    Int(i32),
+    Float(f32),
+    Bool(bool)
+}
+
+impl OscArgument {
+    pub fn as_i32(&self) -> i32 {
+        match self {
+            OscArgument::Int(value) => *value,
+            OscArgument::Float(value) => value.round() as i32,
+            OscArgument::Bool(value) => *value as i32
+        }
+    }
+}
+
+pub struct OscMessage {
+    pub address: String,
+    pub arguments: Vec<OscArgument>
+}
+
+// bundle time tags are ignored, all messages are handled as soon as they arrive
+pub fn parse_packet(packet: &[u8]) -> Vec<OscMessage> {
+    let mut messages = vec![];
+    parse_element(packet, &mut messages);
+    messages
+}
+
+fn parse_element(data: &[u8], messages: &mut Vec<OscMessage>) {
+    if data.starts_with(BUNDLE_TAG) {
+        let mut offset = BUNDLE_HEADER_SIZE;
+
+        while let Some(size) = read_i32(data, offset) {
+            let start = offset + 4;
+            let end = start + size.max(0) as usize;
+            if end > data.len() {
+                break;
+            }
+
+            parse_element(&data[start..end], messages);
+            offset = end;
+        }
+    } else if let Some(message) = parse_message(data) {
+        messages.push(message);
+    }
+}
+
+fn parse_message(data: &[u8]) -> Option<OscMessage> {
+    let (address, mut offset) = read_string(data, 0)?;
+    if !address.starts_with('/') {
+        return None;
+    }
+
+    // messages without a type tag string have no arguments
+    let type_tags = match read_string(data, offset) {
+        Some((type_tags, next_offset)) if type_tags.starts_with(',') => {
+            offset = next_offset;
+            type_tags
+        }
+        _ => ","
+    };
+
+    let mut arguments = vec![];
+    for type_tag in type_tags.chars().skip(1) {
+        match type_tag {
+            'i' => {
+                arguments.push(OscArgument::Int(read_i32(data, offset)?));
+                offset += 4;
+            }
+            'f' => {
+                arguments.push(OscArgument::Float(f32::from_bits(read_i32(data, offset)? as u32)));
+                offset += 4;
+            }
+            'T' => arguments.push(OscArgument::Bool(true)),
+            'F' => arguments.push(OscArgument::Bool(false)),
+            _ => return None
+        }
+    }
+
+    Some(OscMessage {
+        address: address.to_string(),
+        arguments
+    })
+}
+
+fn read_string(data: &[u8], offset: usize) -> Option<(&str, usize)> {
+    let length = data.get(offset..)?.iter().position(|&c| c == 0)?;
+    let text = std::str::from_utf8(&data[offset..offset + length]).ok()?;
+
+    // strings are null terminated and padded to a multiple of 4 bytes
+    Some((text, offset + (length + 4) / 4 * 4))
+}
+
+fn read_i32(data: &[u8], offset: usize) -> Option<i32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
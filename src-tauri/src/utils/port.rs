@@ -0,0 +1,69 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::net::{IpAddr, TcpListener};
+use std::process::Command;
+
+const MAX_ALTERNATE_PORT_ATTEMPTS: u16 = 100;
+
+pub fn find_available_port(host: IpAddr, port: u16) -> Option<u16> {
+    (1..=MAX_ALTERNATE_PORT_ATTEMPTS)
+        .filter_map(|offset| port.checked_add(offset))
+        .find(|candidate| TcpListener::bind((host, *candidate)).is_ok())
+}
+
+#[cfg(windows)]
+pub fn find_port_owner(port: u16) -> Option<String> {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+    let netstat = Command::new("netstat").args(["-ano", "-p", "TCP"]).creation_flags(CREATE_NO_WINDOW).output().ok()?;
+    let port_suffix = format!(":{}", port);
+
+    let pid = String::from_utf8_lossy(&netstat.stdout).lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .find(|columns| columns.len() == 5 && columns[1].ends_with(&port_suffix) && columns[3] == "LISTENING")
+        .map(|columns| columns[4].to_string())?;
+
+    let tasklist = Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+
+    let output = String::from_utf8_lossy(&tasklist.stdout);
+    let name = output.lines()
+        .find(|line| line.starts_with('"'))
+        .and_then(|line| line.split(',').next())
+        .map(|name| name.trim_matches('"'))
+        .unwrap_or_default();
+    format_owner(name, &pid)
+}
+
+#[cfg(unix)]
+pub fn find_port_owner(port: u16) -> Option<String> {
+    let lsof = Command::new("lsof")
+        .args(["-nP", &format!("-iTCP:{}", port), "-sTCP:LISTEN", "-Fpc"])
+        .output()
+        .ok()?;
+
+    let output = String::from_utf8_lossy(&lsof.stdout);
+    let pid = output.lines().find_map(|line| line.strip_prefix('p'))?;
+    let name = output.lines().find_map(|line| line.strip_prefix('c')).unwrap_or_default();
+    format_owner(name, pid)
+}
+
+#[cfg(not(any(windows, unix)))]
+pub fn find_port_owner(_port: u16) -> Option<String> {
+    None
+}
+
+#[cfg(any(windows, unix))]
+fn format_owner(name: &str, pid: &str) -> Option<String> {
+    if name.is_empty() {
+        Some(format!("PID {}", pid))
+    } else {
+        Some(format!("{} (PID {})", name, pid))
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crossbeam_channel::Receiver;
+use parking_lot::{const_mutex, Mutex};
+use sid_device_core::audio_tap::{AUDIO_TAP, AUDIO_TAP_CHANNELS};
+use tauri::api::path::download_dir;
+
+use crate::settings::Settings;
+use crate::utils::wav;
+
+const STOP_CHECK_INTERVAL_IN_MILLIS: u64 = 100;
+
+pub static RECORDER: Recorder = Recorder::new();
+
+struct Recording {
+    filename: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<io::Result<()>>
+}
+
+// writes the samples that are played to a WAV file, next to the normal playback
+pub struct Recorder {
+    recording: Mutex<Option<Recording>>
+}
+
+impl Recorder {
+    const fn new() -> Recorder {
+        Recorder {
+            recording: const_mutex(None)
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.lock().is_some()
+    }
+
+    pub fn start(&self) -> Result<PathBuf, String> {
+        let mut recording = self.recording.lock();
+        if recording.is_some() {
+            return Err("A recording is already in progress".to_string());
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        let output_dir = download_dir().unwrap_or_else(Settings::get_config_dir);
+        let filename = output_dir.join(format!("sid-device-recording-{}.wav", timestamp));
+
+        let file = File::create(&filename).map_err(|error| error.to_string())?;
+        let audio_receiver = AUDIO_TAP.subscribe();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            move || record(file, audio_receiver, &stop)
+        });
+
+        *recording = Some(Recording { filename: filename.clone(), stop, thread });
+        Ok(filename)
+    }
+
+    pub fn stop(&self) -> Result<PathBuf, String> {
+        let recording = self.recording.lock().take().ok_or_else(|| "No recording is in progress".to_string())?;
+        recording.stop.store(true, Ordering::Release);
+
+        match recording.thread.join() {
+            Ok(Ok(())) => Ok(recording.filename),
+            Ok(Err(error)) => Err(error.to_string()),
+            Err(_) => Err("Recording thread stopped unexpectedly".to_string())
+        }
+    }
+}
+
+// the sizes in the header are only known at the end, so it's written again when the recording stops
+fn record(file: File, audio_receiver: Receiver<Vec<i16>>, stop: &AtomicBool) -> io::Result<()> {
+    let sample_rate = AUDIO_TAP.get_sample_rate();
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&wav::create_header(sample_rate, AUDIO_TAP_CHANNELS, 0))?;
+
+    let mut data_size: u32 = 0;
+    while !stop.load(Ordering::Acquire) {
+        if let Ok(samples) = audio_receiver.recv_timeout(Duration::from_millis(STOP_CHECK_INTERVAL_IN_MILLIS)) {
+            let data = wav::samples_to_bytes(&samples);
+
+            // a WAV file can't be larger than 4 GB
+            if data_size.checked_add(data.len() as u32 + wav::WAV_HEADER_SIZE as u32).is_none() {
+                break;
+            }
+            writer.write_all(&data)?;
+            data_size += data.len() as u32;
+        }
+    }
+
+    writer.seek(SeekFrom::Start(0))?;
+    writer.write_all(&wav::create_header(sample_rate, AUDIO_TAP_CHANNELS, data_size))?;
+    writer.flush()
+}
@@ -0,0 +1,47 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::time::Instant;
+
+use cpal::traits::DeviceTrait;
+use sid_device_core::audio_host::AUDIO_HOST;
+
+use crate::settings::Config;
+use crate::utils::audio;
+
+pub struct AppStartTime(pub Instant);
+
+#[derive(serde::Serialize)]
+pub struct RuntimeInfo {
+    app_version: &'static str,
+    git_commit: &'static str,
+    resid_version: &'static str,
+    resid_new_8580_filter: bool,
+    tauri_version: &'static str,
+    cpal_version: &'static str,
+    audio_host: &'static str,
+    audio_device: Option<String>,
+    sample_rate: Option<u32>,
+    uptime_in_sec: u64
+}
+
+pub fn get_runtime_info(start_time: &AppStartTime, config: &Config) -> RuntimeInfo {
+    let device = audio::get_audio_output_device(config.audio_device_number);
+    let audio_device = device.as_ref().and_then(|device| device.name().ok());
+    let sample_rate = device
+        .and_then(|device| device.default_output_config().ok())
+        .map(|device_config| device_config.sample_rate().0);
+
+    RuntimeInfo {
+        app_version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("GIT_COMMIT_HASH"),
+        resid_version: resid::RESID_VERSION,
+        resid_new_8580_filter: !config.old_8580_filter_enabled,
+        tauri_version: env!("TAURI_VERSION"),
+        cpal_version: env!("CPAL_VERSION"),
+        audio_host: AUDIO_HOST.get_host_id().name(),
+        audio_device,
+        sample_rate,
+        uptime_in_sec: start_time.0.elapsed().as_secs()
+    }
+}
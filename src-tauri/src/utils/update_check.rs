@@ -0,0 +1,67 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_lite::future::block_on;
+use parking_lot::{const_mutex, Mutex};
+use tauri::api::http::{ClientBuilder, HttpRequestBuilder, ResponseType};
+
+pub const RELEASES_PAGE_URL: &str = "https://github.com/WilfredC64/sid-device/releases/latest";
+const LATEST_RELEASE_API_URL: &str = "https://api.github.com/repos/WilfredC64/sid-device/releases/latest";
+const REQUEST_TIMEOUT_IN_SEC: u64 = 10;
+
+pub static PENDING_UPDATE: Mutex<Option<ReleaseVersion>> = const_mutex(None);
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct ReleaseVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32
+}
+
+impl ReleaseVersion {
+    pub fn current() -> ReleaseVersion {
+        Self::parse(env!("CARGO_PKG_VERSION")).unwrap()
+    }
+
+    pub fn parse(version: &str) -> Option<ReleaseVersion> {
+        let mut parts = version.trim().trim_start_matches('v').split('.').map(|part| part.parse::<u32>().ok());
+
+        Some(ReleaseVersion {
+            major: parts.next()??,
+            minor: parts.next().unwrap_or(Some(0))?,
+            patch: parts.next().unwrap_or(Some(0))?
+        })
+    }
+}
+
+impl fmt::Display for ReleaseVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+pub fn fetch_latest_version() -> Result<ReleaseVersion, String> {
+    block_on(async {
+        let client = ClientBuilder::new().build().map_err(|error| error.to_string())?;
+
+        let request = HttpRequestBuilder::new("GET", LATEST_RELEASE_API_URL)
+            .and_then(|request| request.header("User-Agent", "sid-device"))
+            .map_err(|error| error.to_string())?
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_IN_SEC))
+            .response_type(ResponseType::Json);
+
+        let response = client.send(request).await.map_err(|error| error.to_string())?;
+        let response_data = response.read().await.map_err(|error| error.to_string())?;
+
+        response_data.data["tag_name"].as_str()
+            .and_then(ReleaseVersion::parse)
+            .ok_or_else(|| "Invalid release information received".to_string())
+    })
+}
+
+pub fn get_current_time_in_sec() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
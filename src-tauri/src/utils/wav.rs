@@ -0,0 +1,31 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+const BITS_PER_SAMPLE: u16 = 16;
+const PCM_FORMAT: u16 = 1;
+
+pub const WAV_HEADER_SIZE: usize = 44;
+
+pub fn create_header(sample_rate: u32, channels: u16, data_size: u32) -> Vec<u8> {
+    let block_align = channels * BITS_PER_SAMPLE / 8;
+    let byte_rate = sample_rate * block_align as u32;
+
+    let mut header = Vec::with_capacity(WAV_HEADER_SIZE);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&data_size.saturating_add(WAV_HEADER_SIZE as u32 - 8).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&PCM_FORMAT.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+    header
+}
+
+pub fn samples_to_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|sample| sample.to_le_bytes()).collect()
+}
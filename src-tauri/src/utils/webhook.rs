@@ -0,0 +1,76 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use futures_lite::future::block_on;
+use parking_lot::{const_mutex, Mutex};
+use tauri::api::http::{Body, ClientBuilder, HttpRequestBuilder};
+
+const REQUEST_TIMEOUT_IN_SEC: u64 = 5;
+const MAX_QUEUED_EVENTS: usize = 100;
+
+pub static WEBHOOK: Webhook = Webhook::new();
+
+#[derive(serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    ClientConnected { address: String },
+    ClientDisconnected { address: String },
+    Error { message: String },
+    NowPlaying { title: String, author: String, released: String }
+}
+
+pub struct Webhook {
+    enabled: AtomicBool,
+    events: Mutex<Vec<WebhookEvent>>
+}
+
+impl Webhook {
+    const fn new() -> Webhook {
+        Webhook {
+            enabled: AtomicBool::new(false),
+            events: const_mutex(Vec::new())
+        }
+    }
+
+    pub fn notify(&self, event: WebhookEvent) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut events = self.events.lock();
+        if events.len() < MAX_QUEUED_EVENTS {
+            events.push(event);
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.events.lock().clear();
+        }
+    }
+
+    pub fn take_events(&self) -> Vec<WebhookEvent> {
+        self.events.lock().drain(..).collect()
+    }
+}
+
+pub fn post_event(url: &str, event: &WebhookEvent) -> Result<(), String> {
+    let body = serde_json::to_value(event).map_err(|error| error.to_string())?;
+
+    block_on(async {
+        let client = ClientBuilder::new().build().map_err(|error| error.to_string())?;
+
+        let request = HttpRequestBuilder::new("POST", url)
+            .and_then(|request| request.header("User-Agent", "sid-device"))
+            .map_err(|error| error.to_string())?
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_IN_SEC))
+            .body(Body::Json(body));
+
+        client.send(request).await.map_err(|error| error.to_string())?;
+        Ok(())
+    })
+}
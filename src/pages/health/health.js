@@ -0,0 +1,7 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+import { createApp } from 'vue'
+import App from './Health.vue'
+
+createApp(App).mount('#health')
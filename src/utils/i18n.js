@@ -0,0 +1,19 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+import { invoke } from '@tauri-apps/api/tauri'
+import { ref } from 'vue'
+
+const strings = ref({});
+
+export default class {
+    static loadTranslations = async () => {
+        const translations = await invoke('get_translations_cmd');
+        strings.value = translations.strings;
+        document.documentElement.lang = translations.language;
+    }
+
+    static t = (key, ...args) => {
+        return args.reduce((text, arg) => text.replace('{}', arg), strings.value[key] ?? key);
+    }
+}
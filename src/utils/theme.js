@@ -0,0 +1,19 @@
+// Copyright (C) 2022 Wilfred Bos
+// Licensed under the GNU GPL v3 license. See the LICENSE file for the terms and conditions.
+
+import { listen } from '@tauri-apps/api/event';
+import { invoke } from '@tauri-apps/api/tauri'
+
+export default class {
+    static applyTheme = (theme) => {
+        document.documentElement.dataset.theme = theme;
+    }
+
+    static followTheme = async () => {
+        invoke('get_theme_cmd').then(this.applyTheme);
+
+        await listen('theme-changed', (event) => {
+            this.applyTheme(event.payload);
+        });
+    }
+}
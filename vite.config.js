@@ -21,7 +21,8 @@ export default defineConfig({
         rollupOptions: {
             input: {
                 settings: resolve(root, 'pages', 'settings', 'index.html'),
-                about: resolve(root, 'pages', 'about', 'index.html')
+                about: resolve(root, 'pages', 'about', 'index.html'),
+                health: resolve(root, 'pages', 'health', 'index.html')
             }
         }
     }